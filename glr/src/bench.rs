@@ -0,0 +1,51 @@
+use crate::bytecode::Backend;
+use crate::programs::{Machine, counter, fibonacci, arithmetic};
+
+#[cfg(target_arch = "x86_64")]
+#[inline(always)]
+fn cycles() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(target_arch = "aarch64")]
+asm_func!(read_cycle_counter() -> u64, r#"
+    mrs x0, cntvct_el0
+    ret
+"#);
+
+#[cfg(target_arch = "aarch64")]
+#[inline(always)]
+fn cycles() -> u64 {
+    unsafe { read_cycle_counter() }
+}
+
+/// Run each benchmark program on every backend, reporting cycles taken and whether it produced the expected result
+pub fn run() -> i32 {
+    let mut machine = match Machine::new() {
+        Some(machine) => machine,
+        None => {
+            println!("bench: failed to reserve vm memory");
+            return 1
+        }
+    };
+
+    let programs = [
+        counter(100_000_000),
+        fibonacci(32),
+        arithmetic(50_000_000),
+    ];
+
+    let backends = [Backend::Assembly, Backend::Portable];
+    programs.iter().fold(0, |status, program| {
+        backends.iter().fold(status, |status, &backend| {
+            let start = cycles();
+            let result = machine.run(program, backend);
+            let elapsed = cycles() - start;
+
+            let passed = result == Some(program.expected);
+            println!("{:<8} {:<8} {:>14} cycles  result = {:?} ({})",
+                program.name, backend.name(), elapsed, result, if passed { "ok" } else { "MISMATCH" });
+            if passed { status } else { 1 }
+        })
+    })
+}
@@ -0,0 +1,157 @@
+use super::{Reader, Mapping, Mappable, NameHasher, ClassError, ClassResult, ClassLoader, join, MAX_NAME_SIZE};
+use super::shared::c_char;
+use super::shared::mem::MappedFile;
+use core::slice::{from_raw_parts, from_raw_parts_mut};
+use core::str::from_utf8;
+
+pub const ARCHIVE_EXTENSION: &'static str = ".glra";
+
+const ARCHIVE_MAGIC: &'static [u8; 4] = b"$GLA";
+const ARCHIVE_VERSION: u8 = 1;
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_LZ: u8 = 1;
+const MIN_MATCH: usize = 4;
+
+/// A class archive written by `glras pack`, mapped into memory for the life of the loader.
+/// Its index maps class names to class files stored in it, optionally compressed, and its
+/// manifest can name an entry class to run.
+pub struct Archive {
+    file: MappedFile,
+    pub entry_class: Option<&'static str>,
+    classes: Mapping<str, ArchiveEntry, NameHasher>,
+}
+
+pub struct ArchiveEntry {
+    name: &'static str,
+    compression: u8,
+    data: &'static [u8],
+    size: usize,
+    next_entry: usize,
+}
+
+impl Mappable<str> for ArchiveEntry {
+    fn id(&self) -> &str {
+        self.name
+    }
+
+    fn next(&self) -> usize {
+        self.next_entry
+    }
+
+    fn next_mut(&mut self) -> &mut usize {
+        &mut self.next_entry
+    }
+}
+
+impl ClassLoader {
+    /// Map the archive at `path` and index the classes stored in it
+    pub fn open_archive(&mut self, path: &str) -> ClassResult<*mut Archive> {
+        let mut buffer = [0u8; MAX_NAME_SIZE];
+        let path = join(&mut buffer, &[path, "\0"])?;
+        let file = unsafe { MappedFile::open(path.as_ptr() as *const c_char) };
+        let file = file.ok_or(ClassError::ArchiveNotFound)?;
+
+        // archives are never unmapped, so what's read from them can outlive `file`
+        let bytes = unsafe { from_raw_parts(file.as_bytes().as_ptr(), file.as_bytes().len()) };
+        let mut reader: Reader = bytes.into();
+        if reader.read_bytes(ARCHIVE_MAGIC.len()) != Some(ARCHIVE_MAGIC) {
+            return Err(ClassError::BadArchive)
+        } else if reader.read::<u8>() != Some(ARCHIVE_VERSION) {
+            return Err(ClassError::BadArchive)
+        }
+
+        let entry_class = match read_name(&mut reader)? {
+            "" => None,
+            entry_class => Some(entry_class),
+        };
+
+        let num_classes = reader.read::<u32>().ok_or(ClassError::BadArchive)? as usize;
+        let mut classes = self.alloc_mapping(num_classes)?;
+        for _ in 0..num_classes {
+            let name = read_name(&mut reader)?;
+            let compression = reader.read::<u8>().ok_or(ClassError::BadArchive)?;
+            let offset = reader.read::<u32>().ok_or(ClassError::BadArchive)? as usize;
+            let stored = reader.read::<u32>().ok_or(ClassError::BadArchive)? as usize;
+            let size = reader.read::<u32>().ok_or(ClassError::BadArchive)? as usize;
+
+            let data = bytes.get(offset..offset + stored).ok_or(ClassError::BadArchive)?;
+            let entry = self.alloc(ArchiveEntry { name, compression, data, size, next_entry: 0 })?;
+            classes.insert(entry).map_err(|error| error.into_error(ClassError::BadArchive))?;
+        }
+
+        self.alloc(Archive { file, entry_class, classes })
+    }
+}
+
+impl Archive {
+    /// The class file for `class_name`, decompressed into the loader's memory if it was stored compressed
+    pub fn find(&self, class_name: &str, loader: &mut ClassLoader) -> ClassResult<Option<&'static [u8]>> {
+        let entry = match self.classes.find(class_name) {
+            Some(entry) => entry,
+            None => return Ok(None),
+        };
+
+        match entry.compression {
+            COMPRESSION_NONE => Ok(Some(entry.data)),
+            COMPRESSION_LZ => unsafe {
+                let bytes = from_raw_parts_mut(loader.alloc_bytes(entry.size)?, entry.size);
+                decompress(entry.data, bytes).ok_or(ClassError::BadCompressedData)?;
+                Ok(Some(bytes))
+            },
+            _ => Err(ClassError::BadCompressedData),
+        }
+    }
+}
+
+fn read_name(reader: &mut Reader<'static>) -> ClassResult<&'static str> {
+    let size = reader.read::<u16>().ok_or(ClassError::BadArchive)? as usize;
+    let name = reader.read_bytes(size).ok_or(ClassError::BadArchive)?;
+    from_utf8(name).map_err(|_| ClassError::BadArchive)
+}
+
+/// Undo the LZ77 compression of `glras` (see glras/src/lz.rs for the layout) into
+/// `output`, which must be exactly the size of the uncompressed data
+fn decompress(input: &[u8], output: &mut [u8]) -> Option<()> {
+    let (mut src, mut dst) = (0, 0);
+    loop {
+        let token = *input.get(src)?;
+        src += 1;
+
+        let literals = read_length(input, &mut src, token as usize >> 4)?;
+        output.get_mut(dst..dst + literals)?.copy_from_slice(input.get(src..src + literals)?);
+        src += literals;
+        dst += literals;
+
+        if src == input.len() {
+            return if dst == output.len() { Some(()) } else { None }
+        }
+
+        let offset = *input.get(src)? as usize | (*input.get(src + 1)? as usize) << 8;
+        src += 2;
+        let length = read_length(input, &mut src, token as usize & 15)? + MIN_MATCH;
+        if offset == 0 || offset > dst || dst + length > output.len() {
+            return None
+        }
+
+        // matches may overlap the bytes they produce, so copy forwards one at a time
+        for pos in dst..dst + length {
+            output[pos] = output[pos - offset];
+        }
+        dst += length;
+    }
+}
+
+fn read_length(input: &[u8], pos: &mut usize, nibble: usize) -> Option<usize> {
+    let mut length = nibble;
+    if nibble == 15 {
+        loop {
+            let byte = *input.get(*pos)?;
+            *pos += 1;
+            length += byte as usize;
+            if byte != 255 {
+                break
+            }
+        }
+    }
+    Some(length)
+}
@@ -1,54 +1,81 @@
-use super::{ConstPool, Mapping, Mappable, Hash32};
+use super::{Cell, ConstPool, Native, MethodDebug, ExceptionTable, Impl, Link, LinkState, Mapping, Mappable, NameHasher, Symbol};
+use super::{ClassVersion, MethodKey, ValueType, Params, params, return_type, ClassError, ClassResult};
+
+// access modifier bits of classes, fields and methods. Members that are neither public
+// nor private are visible to the classes of their package, named up to the last `.`
+pub const ACCESS_PUBLIC:  u8 = 1 << 0;
+pub const ACCESS_PRIVATE: u8 = 1 << 1;
+pub const ACCESS_STATIC:  u8 = 1 << 2;
+/// Method access bit marking a method implemented by the host instead of bytecode
+pub const ACCESS_NATIVE:  u8 = 1 << 3;
+pub const ACCESS_FINAL:   u8 = 1 << 4;
+/// Field access bit letting classes other than the field's own write it
+pub const ACCESS_MUTABLE: u8 = 1 << 5;
+
+// the access bits each kind of declaration may carry
+pub const CLASS_ACCESS:  u8 = ACCESS_PUBLIC | ACCESS_FINAL;
+pub const METHOD_ACCESS: u8 = ACCESS_PUBLIC | ACCESS_PRIVATE | ACCESS_STATIC | ACCESS_NATIVE | ACCESS_FINAL;
+pub const FIELD_ACCESS:  u8 = ACCESS_PUBLIC | ACCESS_PRIVATE | ACCESS_STATIC | ACCESS_MUTABLE;
 
 #[repr(u8)]
 pub enum Class {
     Enum(ClassFile),
     Struct(ClassFile),
     Module(ClassFile),
+    Interface(ClassFile),
 }
 
 #[repr(u8)]
 pub enum Field {
-    Module(FieldContext, u16),
-    Struct(FieldContext, u16, u16),
-    Enum(FieldContext, u16, Option<*mut Field>),
+    Module(FieldContext, Symbol, u64),
+    Struct(FieldContext, Symbol, u16),
+    Enum(FieldContext, Symbol, Option<*mut Field>),
 }
 
 #[derive(Copy, Clone)]
 pub struct FieldContext {
     pub class: *mut Class,
+    pub access: u8,
     pub next_field: usize,
 }
 
 pub struct Method {
-    pub name: u16,
+    pub key: MethodKey,
     pub access: u8,
+    pub num_params: u8,
+    pub max_locals: u16,
+    pub max_stack: u16,
     pub code_pos: u64,
+    /// index of an interface method in the vtables of its implementations
+    pub slot: u16,
     pub class: *mut Class,
+    pub native: Option<*mut Native>,
+    pub debug: Option<*mut MethodDebug>,
+    pub exceptions: Option<*mut ExceptionTable>,
     pub next_method: usize,
 }
 
 pub struct ClassFile {
+    pub name: Symbol,
+    pub version: ClassVersion,
     pub access: u8,
+    pub type_params: &'static [Symbol],
     pub next_class: usize,
     pub bytecode: *const u8,
+    pub code: *const Cell,
+    pub code_size: usize,
     pub const_pool: ConstPool,
-    pub fields: Option<Mapping<str, Field>>,
-    pub methods: Option<Mapping<str, Method>>,
-}
-
-impl Hash32 for str {
-    fn hash32(&self) -> u32 {
-        const FNV_PRIME: u32 = 16777619;
-        const FNV_OFFSET: u32 = 2166136261;
-        self.as_bytes().iter().fold(FNV_OFFSET,
-            |hash, &byte| (hash ^ byte as u32) * FNV_PRIME)
-    }
+    pub source_file: Option<u16>,
+    pub links: *mut Link,
+    pub state: LinkState,
+    pub fields: Option<Mapping<Symbol, Field, NameHasher>>,
+    pub methods: Option<Mapping<MethodKey, Method, NameHasher>>,
+    pub impls: Option<*mut Impl>,
 }
 
-impl Mappable<str> for Class {
-    fn id(&self) -> &str {
-        self.class_file().const_pool.get_str(0).unwrap_or("")
+impl Mappable<Symbol> for Class {
+    fn id(&self) -> &Symbol {
+        &self.class_file().name
     }
 
     fn next(&self) -> usize {
@@ -60,9 +87,13 @@ impl Mappable<str> for Class {
     }
 }
 
-impl Mappable<str> for Field {
-    fn id(&self) -> &str {
-        self.name()
+impl Mappable<Symbol> for Field {
+    fn id(&self) -> &Symbol {
+        match self {
+            Field::Module(_, name, _)  |
+            Field::Enum(_, name, _)    |
+            Field::Struct(_, name, _) => name
+        }
     }
 
     fn next(&self) -> usize {
@@ -74,9 +105,9 @@ impl Mappable<str> for Field {
     }
 }
 
-impl Mappable<str> for Method {
-    fn id(&self) -> &str {
-        self.name()
+impl Mappable<MethodKey> for Method {
+    fn id(&self) -> &MethodKey {
+        &self.key
     }
 
     fn next(&self) -> usize {
@@ -94,7 +125,8 @@ impl Class {
         match self {
             Class::Module(class_file) |
             Class::Struct(class_file) |
-            Class::Enum(class_file) => class_file
+            Class::Enum(class_file)   |
+            Class::Interface(class_file) => class_file
         }
     }
 
@@ -103,9 +135,53 @@ impl Class {
         match self {
             Class::Module(class_file) |
             Class::Struct(class_file) |
-            Class::Enum(class_file) => class_file
+            Class::Enum(class_file)   |
+            Class::Interface(class_file) => class_file
+        }
+    }
+
+    #[inline]
+    pub fn is_interface(&self) -> bool {
+        if let Class::Interface(_) = self { true } else { false }
+    }
+
+    #[inline]
+    pub fn is_struct(&self) -> bool {
+        if let Class::Struct(_) = self { true } else { false }
+    }
+}
+
+/// Check `access` only holds bits out of `allowed`, and isn't both public and private
+pub fn check_access(access: u8, allowed: u8) -> ClassResult<u8> {
+    match access & !allowed == 0 && access & (ACCESS_PUBLIC | ACCESS_PRIVATE) != ACCESS_PUBLIC | ACCESS_PRIVATE {
+        true => Ok(access),
+        false => Err(ClassError::BadAccessModifier),
+    }
+}
+
+impl ClassFile {
+    /// The package of the class, which is its name up to the last `.`
+    #[inline]
+    pub fn package(&self) -> &str {
+        let name = self.name.as_str();
+        name.rfind('.').map_or("", |end| &name[..end])
+    }
+
+    /// Whether code in this class may reference a declaration of `owner` with `access`
+    pub fn can_access(&self, owner: &ClassFile, access: u8) -> bool {
+        if access & ACCESS_PUBLIC != 0 {
+            true
+        } else if access & ACCESS_PRIVATE != 0 {
+            self.name == owner.name
+        } else {
+            self.package() == owner.package()
         }
     }
+
+    /// The methods overloading `name`, whatever their descriptors
+    pub fn methods_named<'a>(&'a self, name: Symbol) -> impl Iterator<Item = &'a Method> + 'a {
+        self.methods.iter().flat_map(|methods| methods.iter()).filter(move |method| method.key.name == name)
+    }
 }
 
 impl Method {
@@ -116,7 +192,50 @@ impl Method {
 
     #[inline]
     pub fn name(&self) -> &str {
-        self.const_pool().get_str(self.name as usize).unwrap_or("")
+        self.key.name.as_str()
+    }
+
+    #[inline]
+    pub fn descriptor(&self) -> &str {
+        self.key.descriptor.as_str()
+    }
+
+    #[inline]
+    pub fn params(&self) -> Params {
+        params(self.descriptor())
+    }
+
+    #[inline]
+    pub fn return_type(&self) -> ValueType {
+        return_type(self.descriptor())
+    }
+
+    #[inline]
+    pub fn is_native(&self) -> bool {
+        self.access & ACCESS_NATIVE != 0
+    }
+
+    #[inline]
+    pub fn is_private(&self) -> bool {
+        self.access & ACCESS_PRIVATE != 0
+    }
+
+    #[inline]
+    pub fn is_static(&self) -> bool {
+        self.access & ACCESS_STATIC != 0
+    }
+
+    /// Whether invoking the method with `num_args` arguments matches its parameters,
+    /// which 1.x methods don't declare
+    #[inline]
+    pub fn takes_args(&self, num_args: u64) -> bool {
+        self.num_params as u64 == num_args || self.descriptor().is_empty()
+    }
+
+    /// Interface methods have no code, only a slot in the vtables of their implementations
+    #[inline]
+    pub fn is_abstract(&self) -> bool {
+        unsafe { (*self.class).is_interface() }
     }
 }
 
@@ -129,7 +248,7 @@ impl Field {
     #[inline]
     pub fn context(&self) -> &FieldContext {
         match self {
-            Field::Module(context, _)   |
+            Field::Module(context, _, _)|
             Field::Enum(context, _, _)  |
             Field::Struct(context, _, _) => context
         }
@@ -138,18 +257,34 @@ impl Field {
     #[inline]
     pub fn context_mut(&mut self) -> &mut FieldContext {
         match self {
-            Field::Module(context, _)   |
+            Field::Module(context, _, _)|
             Field::Enum(context, _, _)  |
             Field::Struct(context, _, _) => context
         }
     }
 
+    #[inline]
     pub fn name(&self) -> &str {
-        self.const_pool().get_str(match self {
-            Field::Module(_, index)     |
-            Field::Enum(_, index, _)    |
-            Field::Struct(_, index, _) => *index as usize
-        }).unwrap_or("")
+        self.id().as_str()
+    }
+
+    #[inline]
+    pub fn class_file(&self) -> &ClassFile {
+        unsafe { (*self.context().class).class_file() }
+    }
+
+    #[inline]
+    pub fn access(&self) -> u8 {
+        self.context().access
+    }
+
+    /// The value of a module field
+    #[inline]
+    pub fn static_value(&mut self) -> Option<*mut u64> {
+        match self {
+            Field::Module(_, _, value) => Some(value),
+            _ => None,
+        }
     }
 
     #[inline]
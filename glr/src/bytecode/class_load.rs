@@ -1,14 +1,71 @@
-use super::{Class, ClassResult, ClassError};
-use super::{Reader, Mapping, Mappable, Hash32};
-use super::shared::mem::{MemoryRange, CODE_MEMORY, CLASS_MEMORY, CLASS_MAPPING};
+use super::{Backend, Cell, Class, ClassFile, Method, ClassResult, ClassError, Archive, ARCHIVE_EXTENSION};
+use super::{Reader, Mapping, MappingIter, Mappable, Hash32, NameHasher, Symbol, SymbolEntry, InsertError};
+use super::{NativeFn, NativeTarget, NativeSignature, str_words, write_str};
+use super::shared::{c_char, c_void, CString, FILE, fopen, fclose, fseek, ftell, fread, SEEK_SET, SEEK_END};
+use super::shared::dylib::{open_library, find_symbol};
+use super::shared::mem::{MemoryRange, CODE_MEMORY, CLASS_MEMORY, CLASS_MAPPING, CODE_INDEX};
+use crate::stdlib;
+use core::str::from_utf8_unchecked;
+use core::mem::size_of;
 
 const DEFAULT_CLASSES: usize = 8;
+const DEFAULT_NATIVES: usize = 64;
+const DEFAULT_SYMBOLS: usize = 256;
+const MAX_LIBRARIES: usize = 16;
+pub(crate) const MAX_NAME_SIZE: usize = 4096;
+const MAX_CLASS_PATH: usize = 16;
+const CLASS_FILE_EXTENSION: &'static str = ".glrc";
 
 pub struct ClassLoader {
     mapping: MemoryRange,
     pub memory: MemoryRange,
     pub bytecode: MemoryRange,
-    classes: Mapping<str, Class>,
+    code_index: MemoryRange,
+    pub backend: Backend,
+    hasher: NameHasher,
+    classes: Mapping<Symbol, Class, NameHasher>,
+    natives: Mapping<str, NativeBinding, NameHasher>,
+    pub(crate) symbols: Mapping<str, SymbolEntry, NameHasher>,
+    libraries: [*mut c_void; MAX_LIBRARIES],
+    num_libraries: usize,
+    class_path: [ClassPathEntry; MAX_CLASS_PATH],
+    num_class_paths: usize,
+    failed_symbol: Option<(&'static str, &'static str)>,
+}
+
+/// Where `resolve` looks for classes that aren't loaded yet
+#[derive(Copy, Clone)]
+pub enum ClassPathEntry {
+    Directory(&'static str),
+    Archive(&'static str, *mut Archive),
+}
+
+/// Where the cells of a method with bytecode start. The code index holds one for every
+/// method of the defined classes, sorted by address, for `find_code` to search.
+#[derive(Copy, Clone)]
+struct CodeEntry {
+    code: *const Cell,
+    method: *const Method,
+}
+
+pub struct NativeBinding {
+    name: &'static str,
+    function: NativeFn,
+    next_binding: usize,
+}
+
+impl Mappable<str> for NativeBinding {
+    fn id(&self) -> &str {
+        self.name
+    }
+
+    fn next(&self) -> usize {
+        self.next_binding
+    }
+
+    fn next_mut(&mut self) -> &mut usize {
+        &mut self.next_binding
+    }
 }
 
 pub trait ClassLoadable<'a, T>: Sized {
@@ -20,16 +77,40 @@ pub trait ClassLoadable<'a, T>: Sized {
 }
 
 impl ClassLoader {
+    #[inline]
     pub fn new() -> ClassResult<Self> {
+        Self::with_backend(Backend::default())
+    }
+
+    #[inline]
+    pub fn with_backend(backend: Backend) -> ClassResult<Self> {
+        Self::with_hasher(backend, NameHasher::default())
+    }
+
+    /// A loader hashing names with `hasher`, e.g. `NameHasher::seeded()` for untrusted classes
+    pub fn with_hasher(backend: Backend, hasher: NameHasher) -> ClassResult<Self> {
         let class_loader: Option<Self> = try {
-            let memory = MemoryRange::at(CLASS_MEMORY)?;
+            let mut memory = MemoryRange::at(CLASS_MEMORY)?;
             let bytecode = MemoryRange::at_exec(CODE_MEMORY)?;
             let mut mapping = MemoryRange::at(CLASS_MAPPING)?;
+            let code_index = MemoryRange::at(CODE_INDEX)?;
     
-            let classes = Mapping::from(&mut mapping, DEFAULT_CLASSES)?;
-            Self { memory, mapping, bytecode, classes }
+            let classes = Mapping::with_hasher(&mut mapping, DEFAULT_CLASSES, hasher)?;
+            let natives = Mapping::with_hasher(&mut memory, DEFAULT_NATIVES, hasher)?;
+            let symbols = Mapping::with_hasher(&mut memory, DEFAULT_SYMBOLS, hasher)?;
+            let libraries = [core::ptr::null_mut(); MAX_LIBRARIES];
+            let class_path = [ClassPathEntry::Directory(""); MAX_CLASS_PATH];
+            Self {
+                memory, mapping, bytecode, code_index, backend, hasher, classes, natives, symbols,
+                libraries, num_libraries: 0,
+                class_path, num_class_paths: 0,
+                failed_symbol: None,
+            }
         };
-        class_loader.ok_or(ClassError::OutOfMemory)
+
+        let mut class_loader = class_loader.ok_or(ClassError::OutOfMemory)?;
+        stdlib::register(&mut class_loader)?;
+        Ok(class_loader)
     }
 
     #[inline]
@@ -53,31 +134,329 @@ impl ClassLoader {
     }
 
     #[inline]
-    pub fn alloc_mapping<K, V: Mappable<K>>(&mut self, capacity: usize)
-        -> Result<Mapping<K, V>, ClassError>
-        where K: PartialEq + Hash32, V: Mappable<K> {
-        Mapping::from(&mut self.memory, capacity).ok_or(ClassError::OutOfMemory)
+    pub fn alloc_cells(&mut self, amount: usize) -> ClassResult<*mut Cell> {
+        self.bytecode.alloc_many(amount).ok_or(ClassError::OutOfMemory)
+    }
+
+    #[inline]
+    pub fn alloc_mapping<K, V: Mappable<K>>(&mut self, num_items: usize)
+        -> Result<Mapping<K, V, NameHasher>, ClassError>
+        where K: ?Sized + PartialEq + Hash32, V: Mappable<K> {
+        Mapping::with_hasher(&mut self.memory, num_items, self.hasher).ok_or(ClassError::OutOfMemory)
     }
 
     #[inline]
     pub fn find(&self, class_name: &str) -> Option<&mut Class> {
-        self.classes.find(class_name)
+        self.classes.find(&self.symbol(class_name)?)
+    }
+
+    /// Every class loaded so far
+    #[inline]
+    pub fn classes(&self) -> MappingIter<Symbol, Class, NameHasher> {
+        self.classes.iter()
+    }
+
+    /// Allocate `parts` concatenated as a string laid out for the interpreter (see `str_from_slot`)
+    pub fn alloc_str(&mut self, parts: &[&[u8]]) -> ClassResult<&'static str> {
+        let size = parts.iter().map(|part| part.len()).sum();
+        let header = self.alloc_many::<u64>(str_words(size))?;
+        Ok(unsafe { write_str(header, parts) })
     }
 
     pub fn load_class(&mut self, bytes: &[u8]) -> ClassResult<*mut Class> {
+        let class = self.load_into(bytes)?;
+        self.define_class(class)?;
+        self.link(class)?;
+        Ok(class)
+    }
+
+    /// Load a class into memory allocated for it up front, which its fields and methods point back to
+    fn load_into(&mut self, bytes: &[u8]) -> ClassResult<*mut Class> {
+        let class = self.alloc_many::<Class>(1)?;
+        let loaded = Class::load(class, &mut bytes.into(), self)?;
         unsafe {
-            let class = Class::load((), &mut bytes.into(), self)?;
-            let class = self.alloc(class)?;
-
-            self.classes.insert(class).or_else(|| {
-                if self.classes.expand(self.mapping.len()) {
-                    self.classes.insert(class)
-                } else {
-                    None
+            class.write(loaded);
+            debug_check_owner(class);
+        }
+        Ok(class)
+    }
+
+    /// Append a directory to search for `Name.glrc` files when resolving classes,
+    /// or a class archive if the path ends in `.glra`
+    pub fn add_class_path(&mut self, path: &str) -> ClassResult<()> {
+        if self.num_class_paths == MAX_CLASS_PATH {
+            return Err(ClassError::OutOfMemory)
+        }
+
+        let path = path.trim_end_matches(|c| c == '/' || c == '\\');
+        let path = self.alloc_str(&[if path.is_empty() { "." } else { path }.as_bytes()])?;
+        self.class_path[self.num_class_paths] = match path.ends_with(ARCHIVE_EXTENSION) {
+            true => ClassPathEntry::Archive(path, self.open_archive(path)?),
+            false => ClassPathEntry::Directory(path),
+        };
+        self.num_class_paths += 1;
+        Ok(())
+    }
+
+    /// The entry class named by the manifest of the first archive on the class path that has one
+    pub fn entry_class(&self) -> Option<&'static str> {
+        self.class_path().iter().filter_map(|entry| match *entry {
+            ClassPathEntry::Archive(_, archive) => unsafe { (*archive).entry_class },
+            ClassPathEntry::Directory(_) => None,
+        }).next()
+    }
+
+    #[inline]
+    pub fn class_path(&self) -> &[ClassPathEntry] {
+        &self.class_path[..self.num_class_paths]
+    }
+
+    /// Find a loaded class by name, otherwise load it from the first class path entry that has it
+    pub fn resolve(&mut self, class_name: &str) -> ClassResult<*mut Class> {
+        if let Some(class) = self.find(class_name) {
+            let class = class as *mut Class;
+            self.link(class)?;
+            return Ok(class)
+        } else if class_name.is_empty() || class_name.contains(|c| c == '/' || c == '\\') {
+            return Err(ClassError::BadClassName)
+        }
+
+        let class_path = self.class_path;
+        for entry in &class_path[..self.num_class_paths] {
+            let bytes = match *entry {
+                ClassPathEntry::Directory(_) => {
+                    let mut buffer = [0u8; MAX_NAME_SIZE];
+                    let path = entry.locate(&mut buffer, class_name)?;
+                    self.read_file(path)?
+                },
+                ClassPathEntry::Archive(_, archive) => unsafe { (*archive).find(class_name, self)? },
+            };
+
+            if let Some(bytes) = bytes {
+                let class = self.load_into(bytes)?;
+                if unsafe { (*class).id().as_str() } != class_name {
+                    return Err(ClassError::BadClassName)
                 }
-            }).ok_or(ClassError::OutOfMemory)?;
 
-            Ok(class)
+                self.define_class(class)?;
+                self.link(class)?;
+                return Ok(class)
+            }
         }
+
+        Err(self.fail(ClassError::ClassNotFound, class_name, ""))
     }
-}
\ No newline at end of file
+
+    /// The class and member names (empty for a missing class) the last
+    /// `ClassNotFound` or symbol error from loading and linking was about
+    #[inline]
+    pub fn failed_symbol(&self) -> Option<(&str, &str)> {
+        self.failed_symbol
+    }
+
+    pub(crate) fn fail(&mut self, error: ClassError, class_name: &str, member: &str) -> ClassError {
+        let class_name = self.alloc_str(&[class_name.as_bytes()]);
+        let member = self.alloc_str(&[member.as_bytes()]);
+        if let (Ok(class_name), Ok(member)) = (class_name, member) {
+            self.failed_symbol = Some((class_name, member));
+        }
+        error
+    }
+
+    /// Call `each` with every file `resolve` checks for `class_name`, in search order
+    pub fn searched_paths(&self, class_name: &str, each: &mut dyn FnMut(&str)) {
+        for entry in self.class_path() {
+            let mut buffer = [0u8; MAX_NAME_SIZE];
+            if let Ok(path) = entry.locate(&mut buffer, class_name) {
+                each(path.trim_end_matches('\0'));
+            }
+        }
+    }
+
+    /// The class, method and bytecode offset of a predecoded `cell`, for stack traces and
+    /// unwinding: the method with the last code starting at or before it in the code index
+    pub fn find_code(&self, cell: *const Cell) -> Option<(&ClassFile, &Method, u64)> {
+        let entries = self.code_entries();
+        let index = match entries.binary_search_by(|entry| entry.code.cmp(&cell)) {
+            Ok(index) => index,
+            Err(0) => return None,
+            Err(index) => index - 1,
+        };
+
+        let method = unsafe { &*entries[index].method };
+        let class_file = unsafe { (*method.class).class_file() };
+        if cell >= unsafe { class_file.code.add(class_file.code_size) } {
+            return None
+        }
+
+        let code_pos = (cell as usize - class_file.code as usize) / size_of::<Cell>();
+        Some((class_file, method, code_pos as u64))
+    }
+
+    #[inline]
+    fn code_entries(&self) -> &[CodeEntry] {
+        let len = (self.code_index.top_ptr() as usize - self.code_index.as_ptr::<u8>() as usize) / size_of::<CodeEntry>();
+        unsafe { core::slice::from_raw_parts(self.code_index.as_ptr(), len) }
+    }
+
+    /// Add the methods of `class` with bytecode to the code index, keeping it sorted.
+    /// Classes are mostly defined in the order their code was allocated, so each entry
+    /// rarely moves far.
+    fn index_code(&mut self, class: *mut Class) -> ClassResult<()> {
+        let class_file = unsafe { (*class).class_file() };
+        for method in class_file.methods.iter().flat_map(|methods| methods.iter()) {
+            if method.is_native() || method.is_abstract() {
+                continue
+            }
+
+            let code = unsafe { class_file.code.add(method.code_pos as usize) };
+            self.code_index.alloc(CodeEntry { code, method }).ok_or(ClassError::OutOfMemory)?;
+            let len = self.code_entries().len();
+            let entries = unsafe { core::slice::from_raw_parts_mut(self.code_index.as_ptr::<CodeEntry>(), len) };
+            let mut index = len - 1;
+            while index > 0 && entries[index - 1].code > code {
+                entries.swap(index - 1, index);
+                index -= 1;
+            }
+        }
+        Ok(())
+    }
+
+    /// Read the file at the NUL-terminated `path` into class memory, or None if it doesn't exist
+    fn read_file(&mut self, path: &str) -> ClassResult<Option<&'static [u8]>> {
+        unsafe {
+            let file = fopen(path.c_str(), "rb\0".c_str());
+            if file.is_null() {
+                return Ok(None)
+            }
+
+            let bytes = self.read_contents(file);
+            fclose(file);
+            bytes.map(Some)
+        }
+    }
+
+    unsafe fn read_contents(&mut self, file: *mut FILE) -> ClassResult<&'static [u8]> {
+        let size = match fseek(file, 0, SEEK_END) {
+            0 => ftell(file),
+            _ => -1,
+        };
+
+        if size < 0 || fseek(file, 0, SEEK_SET) != 0 {
+            return Err(ClassError::ClassUnreadable)
+        }
+
+        let size = size as usize;
+        let bytes = self.alloc_bytes(size)?;
+        match fread(bytes as *mut c_void, 1, size, file) as usize {
+            read if read == size => Ok(core::slice::from_raw_parts(bytes, size)),
+            _ => Err(ClassError::ClassUnreadable),
+        }
+    }
+
+    /// Make an allocated class visible to `find` under the name in its const pool, which must not be taken
+    pub fn define_class(&mut self, class: *mut Class) -> ClassResult<()> {
+        let inserted = match self.classes.insert(class) {
+            Err(InsertError::Full) if self.classes.expand(&mut self.mapping) => self.classes.insert(class),
+            inserted => inserted,
+        };
+        inserted.map_err(|error| error.into_error(ClassError::DuplicateClass))?;
+        self.index_code(class)
+    }
+
+    /// Back native methods named `name` (as "Class.method") with `function`, replacing any earlier binding
+    pub fn register_native(&mut self, name: &'static str, function: NativeFn) -> ClassResult<()> {
+        let binding = self.alloc(NativeBinding { name, function, next_binding: 0 })?;
+        self.natives.upsert(binding).map(|_| ()).map_err(|error| error.into_error(ClassError::OutOfMemory))
+    }
+
+    /// Open a shared library whose symbols native methods can be bound to
+    pub fn load_library(&mut self, path: &str) -> ClassResult<()> {
+        if self.num_libraries == MAX_LIBRARIES {
+            return Err(ClassError::OutOfMemory)
+        }
+
+        let mut buffer = [0u8; MAX_NAME_SIZE];
+        let path = join(&mut buffer, &[path, "\0"])?;
+        let library = unsafe { open_library(path.as_ptr() as *const c_char) };
+        self.libraries[self.num_libraries] = library.ok_or(ClassError::LibraryNotFound)?;
+        self.num_libraries += 1;
+        Ok(())
+    }
+
+    /// Find what implements a native method: a Rust function registered as "Class.method",
+    /// otherwise a C symbol named after the method in the loaded libraries or the process itself
+    pub fn bind_native(
+        &self,
+        class_name: &str,
+        method_name: &str,
+        signature: &NativeSignature,
+    ) -> ClassResult<NativeTarget> {
+        let mut buffer = [0u8; MAX_NAME_SIZE];
+        if let Some(binding) = self.natives.find(join(&mut buffer, &[class_name, ".", method_name])?) {
+            return Ok(NativeTarget::Rust(binding.function))
+        } else if !signature.is_callable_symbol() {
+            return Err(ClassError::BadNativeType)
+        }
+
+        let symbol = join(&mut buffer, &[method_name, "\0"])?.as_ptr() as *const c_char;
+        self.libraries[..self.num_libraries].iter()
+            .map(|&library| Some(library))
+            .chain(core::iter::once(None))
+            .filter_map(|library| unsafe { find_symbol(library, symbol) })
+            .next()
+            .map(NativeTarget::Symbol)
+            .ok_or(ClassError::UnboundNative)
+    }
+}
+
+/// Every field, enum variant and method of the class must point back at it
+#[cfg(debug_assertions)]
+unsafe fn debug_check_owner(class: *mut Class) {
+    use super::Field;
+    let class_file = (*class).class_file();
+    for method in class_file.methods.iter().flat_map(|methods| methods.iter()) {
+        debug_assert!(method.class == class, "method {} not owned by its class", method.name());
+    }
+
+    for field in class_file.fields.iter().flat_map(|fields| fields.iter()) {
+        let mut variant = Some(field as *const Field);
+        while let Some(field) = variant {
+            debug_assert!((*field).context().class == class, "field {} not owned by its class", (*field).name());
+            variant = match *field {
+                Field::Enum(_, _, next_field) => next_field.map(|field| field as *const Field),
+                _ => None,
+            };
+        }
+    }
+}
+
+#[cfg(not(debug_assertions))]
+#[inline(always)]
+unsafe fn debug_check_owner(_class: *mut Class) {}
+
+impl ClassPathEntry {
+    /// The NUL-terminated path `class_name` would be loaded from under this entry.
+    /// Classes in archives are named as `archive.glra!/Name.glrc`.
+    fn locate<'a>(&self, buffer: &'a mut [u8], class_name: &str) -> ClassResult<&'a str> {
+        match *self {
+            ClassPathEntry::Directory(directory) =>
+                join(buffer, &[directory, "/", class_name, CLASS_FILE_EXTENSION, "\0"]),
+            ClassPathEntry::Archive(archive, _) =>
+                join(buffer, &[archive, "!/", class_name, CLASS_FILE_EXTENSION, "\0"]),
+        }
+    }
+}
+
+/// Concatenate `parts` into `buffer` without allocating
+pub(crate) fn join<'a>(buffer: &'a mut [u8], parts: &[&str]) -> ClassResult<&'a str> {
+    let mut size = 0;
+    for part in parts {
+        let end = size + part.len();
+        buffer.get_mut(size..end)
+            .ok_or(ClassError::BadClassName)?
+            .copy_from_slice(part.as_bytes());
+        size = end;
+    }
+    Ok(unsafe { from_utf8_unchecked(&buffer[..size]) })
+}
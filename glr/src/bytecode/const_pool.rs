@@ -1,6 +1,8 @@
+use super::NO_DESCRIPTOR;
 use core::str::from_utf8_unchecked;
 use core::slice::{from_raw_parts, from_raw_parts_mut};
 
+#[derive(Copy, Clone)]
 pub struct ConstPool {
     size: usize,
     pool: *mut Const,
@@ -12,6 +14,8 @@ pub enum Const {
     UInt(u64),
     Float(f64),
     Str(*const u8, usize),
+    Class(u16),
+    Member(u16, u16, u16),
 }
 
 impl ConstPool {
@@ -20,6 +24,11 @@ impl ConstPool {
         Self { size, pool }
     }
 
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
     #[inline]
     pub fn as_slice(&self) -> &[Const] {
         unsafe { from_raw_parts(self.pool, self.size) }
@@ -63,4 +72,25 @@ impl ConstPool {
             _ => None
         }
     }
-}
\ No newline at end of file
+
+    /// Name of the class referenced by the `Class` const at `index`
+    #[inline]
+    pub fn get_class(&self, index: usize) -> Option<&str> {
+        match self.as_slice().get(index) {
+            Some(&Const::Class(name)) => self.get_str(name as usize),
+            _ => None
+        }
+    }
+
+    /// Index of the owning `Class` const, name and descriptor (if any, see `NO_DESCRIPTOR`)
+    /// of the member referenced at `index`
+    #[inline]
+    pub fn get_member(&self, index: usize) -> Option<(usize, &str, Option<&str>)> {
+        match self.as_slice().get(index) {
+            Some(&Const::Member(class, name, NO_DESCRIPTOR)) => Some((class as usize, self.get_str(name as usize)?, None)),
+            Some(&Const::Member(class, name, descriptor)) =>
+                Some((class as usize, self.get_str(name as usize)?, Some(self.get_str(descriptor as usize)?))),
+            _ => None
+        }
+    }
+}
@@ -0,0 +1,115 @@
+use super::{Reader, ClassError, ClassResult, ClassLoader, ClassFile, Method, Mapping, NameHasher, MethodKey, read_section_method};
+use core::slice::from_raw_parts;
+
+/// Name of the optional section carrying debug info:
+///
+/// ```text
+/// u16 const index of the source file name
+/// u16 number of methods
+/// per method: u16 const indices of its name and descriptor (in 1.x files only its name)
+///             u32 number of lines, then per line a u32 bytecode offset and a u32 line,
+///                 sorted by offset and each covering the code up to the next
+///             u16 number of locals, then per local its u16 slot and u16 const index of its name
+/// ```
+pub const DEBUG_SECTION: &'static str = "debug";
+
+/// Where a method's code came from, for stack traces and the disassembler
+pub struct MethodDebug {
+    lines: *const LineEntry,
+    num_lines: usize,
+    locals: *const LocalName,
+    num_locals: usize,
+}
+
+#[derive(Copy, Clone)]
+pub struct LineEntry {
+    pub code_pos: u32,
+    pub line: u32,
+}
+
+#[derive(Copy, Clone)]
+pub struct LocalName {
+    pub slot: u16,
+    pub name: u16,
+}
+
+/// Attach the debug section's line tables and local names to the methods they describe,
+/// returning the const index of the source file name
+pub fn load_debug<'a>(
+    reader: &mut Reader<'a>,
+    code_size: usize,
+    methods: Option<&Mapping<MethodKey, Method, NameHasher>>,
+    class_file: &ClassFile,
+    loader: &mut ClassLoader,
+) -> ClassResult<u16> {
+    let source_file = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+    class_file.const_pool.get_str(source_file as usize).ok_or(ClassError::BadConstIndex)?;
+
+    let num_methods = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+    for _ in 0..num_methods {
+        let method = read_section_method(reader, methods, class_file, loader)?;
+
+        let num_lines = reader.read::<u32>().ok_or(ClassError::BadSection)? as usize;
+        let lines = loader.alloc_many::<LineEntry>(num_lines)?;
+        let mut last_pos = 0;
+        for index in 0..num_lines {
+            let code_pos = reader.read::<u32>().ok_or(ClassError::BadSection)?;
+            let line = reader.read::<u32>().ok_or(ClassError::BadSection)?;
+            if code_pos < last_pos || code_pos as usize >= code_size {
+                return Err(ClassError::BadSection)
+            }
+
+            last_pos = code_pos;
+            unsafe { *lines.add(index) = LineEntry { code_pos, line } };
+        }
+
+        let num_locals = reader.read::<u16>().ok_or(ClassError::BadSection)? as usize;
+        let locals = loader.alloc_many::<LocalName>(num_locals)?;
+        for index in 0..num_locals {
+            let slot = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+            let name = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+            class_file.const_pool.get_str(name as usize).ok_or(ClassError::BadConstIndex)?;
+            unsafe { *locals.add(index) = LocalName { slot, name } };
+        }
+
+        method.debug = Some(loader.alloc(MethodDebug { lines, num_lines, locals, num_locals })?);
+    }
+
+    Ok(source_file)
+}
+
+impl MethodDebug {
+    #[inline]
+    pub fn lines(&self) -> &[LineEntry] {
+        unsafe { from_raw_parts(self.lines, self.num_lines) }
+    }
+
+    #[inline]
+    pub fn locals(&self) -> &[LocalName] {
+        unsafe { from_raw_parts(self.locals, self.num_locals) }
+    }
+}
+
+impl ClassFile {
+    #[inline]
+    pub fn source_file(&self) -> Option<&str> {
+        self.const_pool.get_str(self.source_file? as usize)
+    }
+}
+
+impl Method {
+    /// The source line of the instruction at `code_pos` in the class's bytecode
+    pub fn line_at(&self, code_pos: u64) -> Option<u32> {
+        let debug = unsafe { &*self.debug? };
+        debug.lines().iter()
+            .take_while(|entry| entry.code_pos as u64 <= code_pos)
+            .last()
+            .map(|entry| entry.line)
+    }
+
+    pub fn local_name(&self, slot: u16) -> Option<&str> {
+        let debug = unsafe { &*self.debug? };
+        let local = debug.locals().iter().find(|local| local.slot == slot)?;
+        self.const_pool().get_str(local.name as usize)
+    }
+}
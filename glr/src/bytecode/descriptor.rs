@@ -0,0 +1,246 @@
+use super::{TypeSize, NativeType, NativeSignature, MAX_NATIVE_ARGS};
+use super::{ClassError, ClassResult, ClassLoader, ConstPool, Symbol, Hash32, Hasher32};
+
+/// Descriptor index of member consts referring to a field, or to the only method with their name
+pub const NO_DESCRIPTOR: u16 = 0xffff;
+
+/// Descriptor letters of each `TypeSize`, like those of Python's `struct`
+const TYPE_SIZES: &'static [u8; 8] = b"BHIQiqfd";
+
+/// The type of a field, method parameter or return value. Descriptors spell them as:
+///
+/// ```text
+/// B H I Q   u8 u16 u32 u64        s                str
+/// i q       i32 i64               LName;           class Name
+/// f d       f32 f64               LName<TT;q>;     class Name with type arguments T and i64
+/// v         nothing, for return   TName;           type parameter Name of the struct or
+///           types only                             enum declaring the field or method
+///                                   F(q)s            closure taking an i64 and returning a str
+///                                   [i               array of i32, [s of strs and so on
+/// ```
+///
+/// A method's descriptor lists its parameter types in parentheses followed by its return
+/// type, e.g. `(qLPoint;)s` for one taking an i64 and a Point and returning a str.
+///
+/// Type parameters are erased: every value already fits the same 64 bit slot, so a generic
+/// class is loaded once and shared by all its instantiations, with values of a parameter's
+/// type held as the slot's raw bits. Type arguments are only checked to be well formed, and
+/// stay part of method descriptors, so callers name a method by exactly the types it declares.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ValueType<'a> {
+    Num(TypeSize),
+    Str,
+    Class(&'a str),
+    Param(&'a str),
+    Function(&'a str),
+    Array(&'a str),
+    Void,
+}
+
+/// Methods are mapped by name and descriptor, so a class can overload a name
+#[derive(Copy, Clone, PartialEq)]
+pub struct MethodKey {
+    pub name: Symbol,
+    pub descriptor: Symbol,
+}
+
+/// The parameter types of a descriptor `check_descriptor` accepted
+pub struct Params<'a> {
+    rest: &'a str,
+}
+
+impl Hash32 for MethodKey {
+    #[inline]
+    fn hash32<H: Hasher32>(&self, hasher: &H) -> u32 {
+        self.name.hash32(hasher) ^ self.descriptor.hash32(hasher).rotate_left(16)
+    }
+}
+
+impl<'a> Iterator for Params<'a> {
+    type Item = ValueType<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.rest.as_bytes().first() {
+            Some(b')') | None => None,
+            Some(_) => next_type(&mut self.rest, &|_| true),
+        }
+    }
+}
+
+/// Check that `descriptor` is well formed and only refers to `type_params`,
+/// returning its number of parameters
+pub fn check_descriptor(descriptor: &str, type_params: &[Symbol]) -> ClassResult<usize> {
+    if !descriptor.starts_with('(') {
+        return Err(ClassError::BadDescriptor)
+    }
+
+    let is_param = |name: &str| type_params.iter().any(|param| param.as_str() == name);
+    let mut rest = &descriptor[1..];
+    let mut num_params = 0;
+    while !rest.starts_with(')') {
+        match next_type(&mut rest, &is_param) {
+            Some(ValueType::Void) | None => return Err(ClassError::BadDescriptor),
+            Some(_) => num_params += 1,
+        }
+    }
+
+    rest = &rest[1..];
+    match next_type(&mut rest, &is_param) {
+        Some(_) if rest.is_empty() => Ok(num_params),
+        _ => Err(ClassError::BadDescriptor),
+    }
+}
+
+/// Check that `text` is a single type other than void, as fields are declared with
+pub fn check_type(text: &str, type_params: &[Symbol]) -> ClassResult<()> {
+    let mut rest = text;
+    match next_type(&mut rest, &|name| type_params.iter().any(|param| param.as_str() == name)) {
+        Some(ValueType::Void) | None => Err(ClassError::BadDescriptor),
+        Some(_) if rest.is_empty() => Ok(()),
+        Some(_) => Err(ClassError::BadDescriptor),
+    }
+}
+
+#[inline]
+pub fn params(descriptor: &str) -> Params {
+    Params { rest: descriptor.get(1..).unwrap_or("") }
+}
+
+pub fn return_type(descriptor: &str) -> ValueType {
+    let mut rest = descriptor.rfind(')').map_or("", |end| &descriptor[end + 1..]);
+    next_type(&mut rest, &|_| true).unwrap_or(ValueType::Void)
+}
+
+/// Read the type at the start of `text`, advancing past it. Type parameters have to be
+/// named by `is_param`.
+fn next_type<'a>(text: &mut &'a str, is_param: &dyn Fn(&str) -> bool) -> Option<ValueType<'a>> {
+    let letter = *text.as_bytes().first()?;
+    let rest = &text[1..];
+    let value_type = match letter {
+        b's' => ValueType::Str,
+        b'v' => ValueType::Void,
+        b'L' => {
+            let end = rest.find(|c| c == ';' || c == '<').filter(|&end| end > 0)?;
+            let mut args = &rest[end..];
+
+            // type arguments aren't void, and there's at least one between the brackets
+            if args.starts_with('<') {
+                args = &args[1..];
+                loop {
+                    match next_type(&mut args, is_param)? {
+                        ValueType::Void => return None,
+                        _ if args.starts_with('>') => break,
+                        _ => {},
+                    }
+                }
+                args = &args[1..];
+            }
+
+            if !args.starts_with(';') {
+                return None
+            }
+            *text = &args[1..];
+            return Some(ValueType::Class(&rest[..end]))
+        },
+        b'F' => {
+            if !rest.starts_with('(') {
+                return None
+            }
+
+            let mut signature = &rest[1..];
+            while !signature.starts_with(')') {
+                match next_type(&mut signature, is_param)? {
+                    ValueType::Void => return None,
+                    _ => {},
+                }
+            }
+            signature = &signature[1..];
+            next_type(&mut signature, is_param)?;
+
+            let end = rest.len() - signature.len();
+            *text = signature;
+            return Some(ValueType::Function(&rest[..end]))
+        },
+        b'[' => {
+            let mut element = rest;
+            match next_type(&mut element, is_param)? {
+                ValueType::Void => return None,
+                _ => {},
+            }
+
+            let end = rest.len() - element.len();
+            *text = element;
+            return Some(ValueType::Array(&rest[..end]))
+        },
+        b'T' => {
+            let end = rest.find(';').filter(|&end| end > 0 && is_param(&rest[..end]))?;
+            *text = &rest[end + 1..];
+            return Some(ValueType::Param(&rest[..end]))
+        },
+        letter => {
+            let type_size = TYPE_SIZES.iter().position(|&size| size == letter)?;
+            ValueType::Num(TypeSize::from(type_size as u8)?)
+        },
+    };
+
+    *text = rest;
+    Some(value_type)
+}
+
+impl NativeSignature {
+    /// The signature of a native method with `descriptor`, which can't take or return classes
+    pub fn from_descriptor(descriptor: &str) -> ClassResult<Self> {
+        let mut args = [NativeType::Void; MAX_NATIVE_ARGS];
+        let mut num_args = 0;
+        for param in params(descriptor) {
+            let arg = args.get_mut(num_args).ok_or(ClassError::BadNativeType)?;
+            *arg = NativeType::from_value(param).ok_or(ClassError::BadNativeType)?;
+            num_args += 1;
+        }
+
+        let returns = NativeType::from_value(return_type(descriptor)).ok_or(ClassError::BadNativeType)?;
+        Ok(NativeSignature { num_args, args, returns })
+    }
+
+    /// Write the descriptor of a method with this signature into `buffer`
+    pub fn descriptor<'a>(&self, buffer: &'a mut [u8; MAX_NATIVE_ARGS + 3]) -> &'a str {
+        buffer[0] = b'(';
+        for (letter, arg) in buffer[1..].iter_mut().zip(self.args()) {
+            *letter = arg.letter();
+        }
+        buffer[1 + self.num_args] = b')';
+        buffer[2 + self.num_args] = self.returns.letter();
+        unsafe { core::str::from_utf8_unchecked(&buffer[..3 + self.num_args]) }
+    }
+}
+
+impl NativeType {
+    #[inline]
+    pub fn from_value(value_type: ValueType) -> Option<Self> {
+        match value_type {
+            ValueType::Num(type_size) => Some(NativeType::Num(type_size)),
+            ValueType::Str => Some(NativeType::Str),
+            ValueType::Void => Some(NativeType::Void),
+            ValueType::Class(_) | ValueType::Param(_) | ValueType::Function(_) | ValueType::Array(_) => None,
+        }
+    }
+
+    #[inline]
+    fn letter(self) -> u8 {
+        match self {
+            NativeType::Num(type_size) => TYPE_SIZES[type_size as usize],
+            NativeType::Str => b's',
+            NativeType::Void => b'v',
+        }
+    }
+}
+
+impl ClassLoader {
+    /// Key of the method named by the string consts at `name` and `descriptor`,
+    /// or None if no method loaded has it
+    pub fn method_key(&self, const_pool: &ConstPool, name: u16, descriptor: u16) -> ClassResult<Option<MethodKey>> {
+        let name = const_pool.get_str(name as usize).ok_or(ClassError::BadConstIndex)?;
+        let descriptor = const_pool.get_str(descriptor as usize).ok_or(ClassError::BadConstIndex)?;
+        Ok(try { MethodKey { name: self.symbol(name)?, descriptor: self.symbol(descriptor)? } })
+    }
+}
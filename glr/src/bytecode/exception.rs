@@ -0,0 +1,122 @@
+use super::{Reader, ClassError, ClassResult, ClassLoader, Class, ClassFile, Method, Link, Mapping, NameHasher, MethodKey, Cell};
+use super::read_section_method;
+use core::slice::from_raw_parts;
+use core::ops::Range;
+
+/// Name of the optional section carrying the error handlers of methods:
+///
+/// ```text
+/// u16 number of methods
+/// per method: u16 const indices of its name and descriptor (in 1.x files only its name)
+///             u16 number of handlers, then per handler its u32 start and u32 end offsets,
+///                 the u32 offset of its code, the u16 const index of the class of errors
+///                 it catches (CATCH_ALL for any) and the u16 number of stack slots it keeps
+/// ```
+///
+/// Handlers are tried in order, so nested ones come before those enclosing them.
+/// A handler runs in the frame of its method with the slots above the ones it keeps
+/// replaced by the error's payload and then its class, so it has to keep room for both
+/// within the method's locals and stack. Its offsets must lie in the method's code.
+pub const EXCEPTIONS_SECTION: &'static str = "exceptions";
+
+/// Error class index of handlers catching every error, runtime errors included
+pub const CATCH_ALL: u16 = 0xffff;
+
+pub struct ExceptionTable {
+    handlers: *const ErrorHandler,
+    num_handlers: usize,
+}
+
+/// Catches errors of `error_class` thrown by instructions in `start..end`
+#[derive(Copy, Clone)]
+pub struct ErrorHandler {
+    pub start: u32,
+    pub end: u32,
+    pub handler: u32,
+    pub error_class: u16,
+    pub stack_depth: u16,
+}
+
+/// Attach the exception section's handlers to the methods they protect
+pub fn load_exceptions<'a>(
+    reader: &mut Reader<'a>,
+    code: *const Cell,
+    code_size: usize,
+    methods: Option<&Mapping<MethodKey, Method, NameHasher>>,
+    class_file: &ClassFile,
+    loader: &mut ClassLoader,
+) -> ClassResult<()> {
+    let invalid = loader.backend.dispatch_table()[255];
+    let num_methods = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+    for _ in 0..num_methods {
+        let method = read_section_method(reader, methods, class_file, loader)?;
+        let code_range = method_code(method, methods, code_size);
+        let frame_size = method.max_locals as usize + method.max_stack as usize;
+
+        let num_handlers = reader.read::<u16>().ok_or(ClassError::BadSection)? as usize;
+        let handlers = loader.alloc_many::<ErrorHandler>(num_handlers)?;
+        for index in 0..num_handlers {
+            let handler = ErrorHandler {
+                start: reader.read::<u32>().ok_or(ClassError::BadSection)?,
+                end: reader.read::<u32>().ok_or(ClassError::BadSection)?,
+                handler: reader.read::<u32>().ok_or(ClassError::BadSection)?,
+                error_class: reader.read::<u16>().ok_or(ClassError::BadSection)?,
+                stack_depth: reader.read::<u16>().ok_or(ClassError::BadSection)?,
+            };
+
+            // handlers must start on an instruction, like jump targets
+            let (start, end, target) = (handler.start as u64, handler.end as u64, handler.handler as u64);
+            if start >= end || start < code_range.start || end > code_range.end {
+                return Err(ClassError::BadSection)
+            } else if handler.stack_depth as usize + 2 > frame_size {
+                return Err(ClassError::BadSection)
+            } else if target < code_range.start || target >= code_range.end {
+                return Err(ClassError::BadCodePos)
+            } else if unsafe { (*code.add(handler.handler as usize)).handler } == invalid {
+                return Err(ClassError::BadCodePos)
+            } else if handler.error_class != CATCH_ALL {
+                class_file.const_pool.get_class(handler.error_class as usize).ok_or(ClassError::BadConstIndex)?;
+            }
+
+            unsafe { *handlers.add(index) = handler };
+        }
+
+        method.exceptions = Some(loader.alloc(ExceptionTable { handlers, num_handlers })?);
+    }
+
+    Ok(())
+}
+
+/// The offsets of the method's code, which runs up to the next method's
+fn method_code(method: &Method, methods: Option<&Mapping<MethodKey, Method, NameHasher>>, code_size: usize) -> Range<u64> {
+    let end = methods.iter().flat_map(|methods| methods.iter())
+        .filter(|other| !other.is_native() && other.code_pos > method.code_pos)
+        .map(|other| other.code_pos)
+        .min()
+        .unwrap_or(code_size as u64);
+    method.code_pos..end
+}
+
+impl ExceptionTable {
+    #[inline]
+    pub fn handlers(&self) -> &[ErrorHandler] {
+        unsafe { from_raw_parts(self.handlers, self.num_handlers) }
+    }
+}
+
+impl Method {
+    /// The first handler of the method covering `code_pos` that catches errors of `error_class`
+    pub fn find_handler(&self, class_file: &ClassFile, code_pos: u64, error_class: *mut Class) -> Option<&ErrorHandler> {
+        let exceptions = unsafe { &*self.exceptions? };
+        exceptions.handlers().iter().find(|handler| {
+            let covered = handler.start as u64 <= code_pos && code_pos < handler.end as u64;
+            covered && match handler.error_class {
+                CATCH_ALL => true,
+                index => match unsafe { *class_file.links.add(index as usize) } {
+                    Link::Class(class) => class == error_class,
+                    _ => false,
+                },
+            }
+        })
+    }
+}
@@ -0,0 +1,178 @@
+use super::shared::c_void;
+use core::convert::TryInto;
+
+lazy_static! {
+    static ref PROCESS_SEED: SipHasher = unsafe { random_seed() };
+}
+
+/// Turns keys into the 32 bit hashes `Mapping` places items by
+pub trait Hasher32: Copy {
+    fn hash_bytes(&self, bytes: &[u8]) -> u32;
+
+    #[inline]
+    fn hash_u64(&self, value: u64) -> u32 {
+        self.hash_bytes(&value.to_le_bytes())
+    }
+}
+
+pub trait Hash32 {
+    fn hash32<H: Hasher32>(&self, hasher: &H) -> u32;
+}
+
+/// FNV-1a: fast, but easy to find colliding keys for
+#[derive(Copy, Clone, Default)]
+pub struct Fnv;
+
+/// SipHash-1-3 keyed with 128 bits, so collisions can't be precomputed without the key
+#[derive(Copy, Clone)]
+pub struct SipHasher {
+    k0: u64,
+    k1: u64,
+}
+
+/// How the loader hashes class, method and field names. Names come from class files,
+/// so loaders running untrusted code should use `seeded()` to keep crafted names from
+/// piling up in the same slots.
+#[derive(Copy, Clone)]
+pub enum NameHasher {
+    Fnv,
+    Seeded(SipHasher),
+}
+
+impl Hasher32 for Fnv {
+    #[inline]
+    fn hash_bytes(&self, bytes: &[u8]) -> u32 {
+        const FNV_PRIME: u32 = 16777619;
+        const FNV_OFFSET: u32 = 2166136261;
+        bytes.iter().fold(FNV_OFFSET,
+            |hash, &byte| (hash ^ byte as u32).wrapping_mul(FNV_PRIME))
+    }
+}
+
+impl SipHasher {
+    #[inline]
+    pub const fn new(k0: u64, k1: u64) -> Self {
+        Self { k0, k1 }
+    }
+
+    pub fn hash64(&self, bytes: &[u8]) -> u64 {
+        let mut state = [
+            self.k0 ^ 0x736f6d6570736575,
+            self.k1 ^ 0x646f72616e646f6d,
+            self.k0 ^ 0x6c7967656e657261,
+            self.k1 ^ 0x7465646279746573,
+        ];
+
+        let chunks = bytes.chunks_exact(8);
+        let tail = chunks.remainder();
+        for chunk in chunks {
+            let word = u64::from_le_bytes(chunk.try_into().unwrap());
+            state[3] ^= word;
+            sip_round(&mut state);
+            state[0] ^= word;
+        }
+
+        // the last word holds the remaining bytes under the length's low byte
+        let last = tail.iter().enumerate().fold((bytes.len() as u64) << 56,
+            |word, (index, &byte)| word | (byte as u64) << (index * 8));
+        state[3] ^= last;
+        sip_round(&mut state);
+        state[0] ^= last;
+
+        state[2] ^= 0xff;
+        for _ in 0..3 {
+            sip_round(&mut state);
+        }
+        state[0] ^ state[1] ^ state[2] ^ state[3]
+    }
+}
+
+impl Hasher32 for SipHasher {
+    #[inline]
+    fn hash_bytes(&self, bytes: &[u8]) -> u32 {
+        let hash = self.hash64(bytes);
+        (hash ^ (hash >> 32)) as u32
+    }
+}
+
+impl NameHasher {
+    /// SipHash keyed with a seed drawn once per process
+    #[inline]
+    pub fn seeded() -> Self {
+        NameHasher::Seeded(*PROCESS_SEED)
+    }
+}
+
+impl Default for NameHasher {
+    #[inline]
+    fn default() -> Self {
+        NameHasher::Fnv
+    }
+}
+
+impl Hasher32 for NameHasher {
+    #[inline]
+    fn hash_bytes(&self, bytes: &[u8]) -> u32 {
+        match self {
+            NameHasher::Fnv => Fnv.hash_bytes(bytes),
+            NameHasher::Seeded(hasher) => hasher.hash_bytes(bytes),
+        }
+    }
+}
+
+impl Hash32 for str {
+    #[inline]
+    fn hash32<H: Hasher32>(&self, hasher: &H) -> u32 {
+        hasher.hash_bytes(self.as_bytes())
+    }
+}
+
+macro_rules! hash_int {
+    ($($type:ty),*) => {
+        $(impl Hash32 for $type {
+            #[inline]
+            fn hash32<H: Hasher32>(&self, hasher: &H) -> u32 {
+                hasher.hash_u64(*self as u64)
+            }
+        })*
+    };
+}
+
+hash_int!(u8, u16, u32, u64, usize, i8, i16, i32, i64, isize);
+
+#[inline(always)]
+fn sip_round(state: &mut [u64; 4]) {
+    state[0] = state[0].wrapping_add(state[1]);
+    state[1] = state[1].rotate_left(13) ^ state[0];
+    state[0] = state[0].rotate_left(32);
+    state[2] = state[2].wrapping_add(state[3]);
+    state[3] = state[3].rotate_left(16) ^ state[2];
+    state[0] = state[0].wrapping_add(state[3]);
+    state[3] = state[3].rotate_left(21) ^ state[0];
+    state[2] = state[2].wrapping_add(state[1]);
+    state[1] = state[1].rotate_left(17) ^ state[2];
+    state[2] = state[2].rotate_left(32);
+}
+
+/// Key from the OS's random source, falling back to the (randomized) addresses
+/// of the stack and this function if it fails
+unsafe fn random_seed() -> SipHasher {
+    let mut key = [0u64; 2];
+    if !random_bytes(key.as_mut_ptr() as *mut c_void, core::mem::size_of_val(&key)) {
+        key[0] = &key as *const _ as u64;
+        key[1] = random_seed as usize as u64;
+    }
+    SipHasher::new(key[0], key[1])
+}
+
+#[cfg(unix)]
+unsafe fn random_bytes(buffer: *mut c_void, size: usize) -> bool {
+    use super::shared::getrandom;
+    getrandom(buffer, size, 0) == size as isize
+}
+
+#[cfg(windows)]
+unsafe fn random_bytes(buffer: *mut c_void, size: usize) -> bool {
+    use super::shared::RtlGenRandom;
+    RtlGenRandom(buffer, size as u32) != 0
+}
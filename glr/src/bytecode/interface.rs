@@ -0,0 +1,99 @@
+use super::{Reader, ClassError, ClassResult, ClassLoader, Class, ClassFile, Method, Link};
+use core::slice::from_raw_parts;
+
+/// Name of the optional section listing the interfaces a struct implements:
+///
+/// ```text
+/// u16 number of interfaces
+/// per interface: u16 const index of its class
+/// ```
+///
+/// The struct implements each method of an interface with its own method of the same name
+/// and descriptor, which like every struct method that isn't static takes the struct as its
+/// first argument.
+pub const IMPLS_SECTION: &'static str = "impls";
+
+/// An interface a struct implements. Its vtable holds the struct's methods at the slots
+/// of the interface methods they implement, and is filled in when the struct is linked.
+pub struct Impl {
+    pub interface_index: u16,
+    pub interface: *mut Class,
+    pub vtable: *const *const Method,
+    pub next_impl: Option<*mut Impl>,
+}
+
+/// Read the impls section of a struct into a list of impls to link
+pub fn load_impls<'a>(reader: &mut Reader<'a>, class: *mut Class, loader: &mut ClassLoader) -> ClassResult<Option<*mut Impl>> {
+    let class_file = unsafe { (*class).class_file() };
+    if unsafe { !(*class).is_struct() } {
+        return Err(ClassError::BadSection)
+    }
+
+    let mut impls = None;
+    let num_impls = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+    for _ in 0..num_impls {
+        let interface_index = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+        class_file.const_pool.get_class(interface_index as usize).ok_or(ClassError::BadConstIndex)?;
+        impls = Some(loader.alloc(Impl {
+            interface_index,
+            interface: core::ptr::null_mut(),
+            vtable: core::ptr::null(),
+            next_impl: impls,
+        })?);
+    }
+    Ok(impls)
+}
+
+impl ClassLoader {
+    /// Resolve the interfaces of the class's impls and build their vtables
+    pub(crate) fn link_impls(&mut self, class_file: &ClassFile) -> ClassResult<()> {
+        let class_name = class_file.name.as_str();
+        let mut next_impl = class_file.impls;
+        while let Some(impl_ptr) = next_impl {
+            let implementation = unsafe { &mut *impl_ptr };
+            next_impl = implementation.next_impl;
+
+            let interface = match self.link_const(class_file, implementation.interface_index as usize)? {
+                Link::Class(class) if unsafe { (*class).is_interface() } => class,
+                _ => {
+                    let interface_name = class_file.const_pool.get_class(implementation.interface_index as usize).unwrap_or("");
+                    return Err(self.fail(ClassError::IncompatibleSymbol, interface_name, ""))
+                },
+            };
+
+            let interface_methods = unsafe { (*interface).class_file().methods.as_ref() };
+            let num_slots = interface_methods.map_or(0, |methods| methods.len());
+            let vtable = self.alloc_many::<*const Method>(num_slots)?;
+            for method in interface_methods.iter().flat_map(|methods| methods.iter()) {
+                let target = class_file.methods.as_ref().and_then(|methods| methods.find(&method.key));
+                match target {
+                    Some(target) if !target.is_static() && !target.is_native() && target.num_params == method.num_params =>
+                        unsafe { *vtable.add(method.slot as usize) = target },
+                    _ => return Err(self.fail(ClassError::UnresolvedSymbol, class_name, method.name())),
+                }
+            }
+
+            implementation.interface = interface;
+            implementation.vtable = vtable;
+        }
+        Ok(())
+    }
+}
+
+impl ClassFile {
+    /// The method of this class implementing the interface method `method`
+    pub fn implementation(&self, method: &Method) -> Option<&Method> {
+        let mut next_impl = self.impls;
+        while let Some(implementation) = next_impl {
+            let implementation = unsafe { &*implementation };
+            if implementation.interface == method.class && !implementation.vtable.is_null() {
+                let interface = unsafe { (*method.class).class_file() };
+                let num_slots = interface.methods.as_ref().map_or(0, |methods| methods.len());
+                let vtable = unsafe { from_raw_parts(implementation.vtable, num_slots) };
+                return vtable.get(method.slot as usize).map(|&target| unsafe { &*target })
+            }
+            next_impl = implementation.next_impl;
+        }
+        None
+    }
+}
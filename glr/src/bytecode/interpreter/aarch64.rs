@@ -0,0 +1,467 @@
+use super::{Cell, RuntimeError, Unwind, VM_STATE, unwind, new_object, new_closure, dispatch, array_op};
+use super::super::{Class, Native, Opcode};
+use core::slice::from_raw_parts;
+
+extern "C" {
+    static glr_dispatch_table: [usize; 256];
+}
+
+#[inline]
+pub fn dispatch_table() -> &'static [usize; 256] {
+    unsafe { &glr_dispatch_table }
+}
+
+/// Called by the `native` handler with the args on top of the operand stack
+#[no_mangle]
+pub unsafe extern "C" fn glr_call_native(native: *const Native, args: *const u64) -> u64 {
+    (*native).call(from_raw_parts(args, (*native).signature.num_args))
+}
+
+/// Called by `glr_throw` to move the registers it saved to the handler of the error
+#[no_mangle]
+pub unsafe extern "C" fn glr_unwind(state: *mut Unwind) {
+    unwind(&mut *state)
+}
+
+/// Called by the `new` handler to allocate an instance of the struct
+#[no_mangle]
+pub unsafe extern "C" fn glr_new(class: *mut Class) -> *mut u64 {
+    new_object(class)
+}
+
+/// Called by the `closure` handler to allocate a closure over the values on top of the stack
+#[no_mangle]
+pub unsafe extern "C" fn glr_closure(cell: *const Cell) -> *mut u64 {
+    new_closure(cell)
+}
+
+/// Called by the array and string handlers, returning the new sp or the `RuntimeError` to raise
+#[no_mangle]
+pub unsafe extern "C" fn glr_array_op(cell: *const Cell, opcode: u8) -> u64 {
+    let result = Opcode::from(opcode).ok_or(RuntimeError::InvalidInstruction)
+        .and_then(|opcode| array_op(opcode, cell, VM_STATE.sp as *mut u64));
+    match result {
+        Ok(sp) => sp as u64,
+        Err(error) => error as u64,
+    }
+}
+
+/// Called by the `invokeinterface` handler when the instance's class misses the inline cache
+#[no_mangle]
+pub unsafe extern "C" fn glr_dispatch(cell: *mut Cell, class: *mut Class) -> u64 {
+    dispatch(cell, class)
+}
+
+// Register assignment for the duration of `interpret`, mirroring the x86_64 backend:
+//   x19 = pc, pointer to the current Cell
+//   x20 = sp, pointer to the top slot of the operand stack (grows upwards)
+//   x21 = fp, pointer to local 0 of the current frame
+//   x22 = pointer to the next free (return pc, saved fp) pair on the frame stack
+//
+// x9 to x11 are scratch. NEXT uses a pre-indexed load to advance the pc and
+// fetch the next handler in one instruction before branching to it.
+asm_func!(interpret(code: *const Cell, stack: *mut u64, num_args: usize, frames: *mut u64) -> u64, r#"
+    stp x29, x30, [sp, #-48]!
+    stp x19, x20, [sp, #16]
+    stp x21, x22, [sp, #32]
+    mov x19, x0
+    add x20, x1, x2, lsl #3
+    sub x20, x20, #8
+    mov x21, x1
+    mov x22, x3
+    adrp x9, glr_halt_cell
+    add x9, x9, :lo12:glr_halt_cell
+    stp x9, x21, [x22], #16
+    ldr x9, [x19]
+    br x9
+
+.macro NEXT len
+    ldr x9, [x19, #16 * \len]!
+    br x9
+.endm
+
+glr_op_halt:
+    ldr x0, [x20]
+    ldp x21, x22, [sp, #32]
+    ldp x19, x20, [sp, #16]
+    ldp x29, x30, [sp], #48
+    ret
+
+glr_op_push:
+    ldr x9, [x19, #8]
+    str x9, [x20, #8]!
+    NEXT 9
+
+glr_op_pop:
+    sub x20, x20, #8
+    NEXT 1
+
+glr_op_dup:
+    ldr x9, [x20]
+    str x9, [x20, #8]!
+    NEXT 1
+
+glr_op_load:
+    ldr x9, [x19, #8]
+    ldr x9, [x21, x9]
+    str x9, [x20, #8]!
+    NEXT 3
+
+glr_op_store:
+    ldr x9, [x19, #8]
+    ldr x10, [x20], #-8
+    str x10, [x21, x9]
+    NEXT 3
+
+glr_op_add:
+    ldr x10, [x20], #-8
+    ldr x9, [x20]
+    add x9, x9, x10
+    str x9, [x20]
+    NEXT 1
+
+glr_op_sub:
+    ldr x10, [x20], #-8
+    ldr x9, [x20]
+    sub x9, x9, x10
+    str x9, [x20]
+    NEXT 1
+
+glr_op_mul:
+    ldr x10, [x20], #-8
+    ldr x9, [x20]
+    mul x9, x9, x10
+    str x9, [x20]
+    NEXT 1
+
+glr_op_div:
+    ldr x10, [x20], #-8
+    cbz x10, glr_divide_by_zero
+    ldr x9, [x20]
+    sdiv x9, x9, x10
+    str x9, [x20]
+    NEXT 1
+
+glr_op_lt:
+    ldr x10, [x20], #-8
+    ldr x9, [x20]
+    cmp x9, x10
+    cset x9, lt
+    str x9, [x20]
+    NEXT 1
+
+glr_op_eq:
+    ldr x10, [x20], #-8
+    ldr x9, [x20]
+    cmp x9, x10
+    cset x9, eq
+    str x9, [x20]
+    NEXT 1
+
+glr_op_jmp:
+    ldr x19, [x19, #8]
+    ldr x9, [x19]
+    br x9
+
+glr_op_jz:
+    ldr x10, [x20], #-8
+    cbnz x10, 1f
+    ldr x19, [x19, #8]
+    ldr x9, [x19]
+    br x9
+1:
+    NEXT 5
+
+// calls return to the cell after the instruction, so `len` differs between call and invoke.
+// x9 holds the target cell, with the number of arguments in its top 16 bits.
+.macro CALL_X9 len
+    add x10, x19, #16 * \len
+    stp x10, x21, [x22], #16
+    lsr x10, x9, #48
+    sub x21, x20, x10, lsl #3
+    add x21, x21, #8
+    and x19, x9, #0xffffffffffff
+    ldr x9, [x19]
+    br x9
+.endm
+
+.macro CALL len
+    ldr x9, [x19, #8]
+    CALL_X9 \len
+.endm
+
+glr_op_call:
+    CALL 6
+
+glr_op_enter:
+    ldr x9, [x19, #8]
+    add x20, x20, x9
+    NEXT 3
+
+glr_op_ret:
+    ldr x9, [x20]
+    str x9, [x21]
+    mov x20, x21
+    ldp x19, x21, [x22, #-16]!
+    ldr x9, [x19]
+    br x9
+
+glr_op_str:
+    ldr x9, [x19, #8]
+    str x9, [x20, #8]!
+    NEXT 3
+
+// natives can allocate and call back into bytecode, so sp is stored with the args still on it
+glr_op_native:
+    ldr x9, [x19, #8]
+    lsr x10, x9, #48
+    sub x20, x20, x10, lsl #3
+    add x1, x20, #8
+    and x0, x9, #0xffffffffffff
+    adrp x9, glr_vm_state
+    add x9, x9, :lo12:glr_vm_state
+    stp x19, x22, [x9]
+    add x10, x20, x10, lsl #3
+    str x10, [x9, #16]
+    bl glr_call_native
+    str x0, [x20, #8]!
+    NEXT 3
+
+glr_op_nop:
+    NEXT 1
+
+glr_op_invoke:
+    CALL 4
+
+glr_op_getstatic:
+    ldr x9, [x19, #8]
+    ldr x9, [x9]
+    str x9, [x20, #8]!
+    NEXT 3
+
+glr_op_putstatic:
+    ldr x9, [x19, #8]
+    ldr x10, [x20], #-8
+    str x10, [x9]
+    NEXT 3
+
+glr_op_throw:
+    ldr x0, [x19, #8]
+    ldr x1, [x20], #-8
+    b glr_throw
+
+glr_op_rethrow:
+    ldr x0, [x20], #-8
+    ldr x1, [x20], #-8
+    b glr_throw
+
+// allocating stores sp for the heap to find the values on the stack
+.macro STORE_SP
+    adrp x9, glr_vm_state
+    add x9, x9, :lo12:glr_vm_state
+    str x20, [x9, #16]
+.endm
+
+glr_op_new:
+    STORE_SP
+    ldr x0, [x19, #8]
+    bl glr_new
+    cbz x0, glr_out_of_memory
+    str x0, [x20, #8]!
+    NEXT 3
+
+// the next cell holds the class the call site last saw and the target it dispatched to
+glr_op_invokeinterface:
+    ldr x9, [x19, #8]
+    lsr x10, x9, #48
+    sub x10, x20, x10, lsl #3
+    ldr x10, [x10, #8]
+    cbz x10, glr_no_implementation
+    ldr x1, [x10]
+    ldp x10, x11, [x19, #16]
+    cmp x1, x10
+    b.ne 1f
+    mov x9, x11
+    CALL_X9 4
+1:
+    mov x0, x19
+    bl glr_dispatch
+    cbz x0, glr_no_implementation
+    mov x9, x0
+    CALL_X9 4
+
+// the next cell holds the number of values the closure captures from the top of the stack
+glr_op_closure:
+    STORE_SP
+    mov x0, x19
+    bl glr_closure
+    cbz x0, glr_out_of_memory
+    ldr x10, [x19, #24]
+    sub x20, x20, x10, lsl #3
+    str x0, [x20, #8]!
+    NEXT 4
+
+// the closure is below the args, and is called as its method's first argument
+glr_op_callclosure:
+    ldr x11, [x19, #8]
+    sub x10, x20, x11, lsl #3
+    ldr x10, [x10]
+    cbz x10, glr_bad_closure_call
+    ldrb w9, [x10]
+    cmp w9, #3
+    b.ne glr_bad_closure_call
+    ldr x9, [x10, #8]
+    lsr x10, x9, #48
+    sub x10, x10, #1
+    cmp x10, x11
+    b.ne glr_bad_closure_call
+    CALL_X9 2
+
+glr_op_capture:
+    ldr x9, [x19, #8]
+    ldr x10, [x21]
+    ldr x9, [x10, x9]
+    str x9, [x20, #8]!
+    NEXT 3
+
+// array and string instructions run in Rust, which returns the new sp, or below it
+// the RuntimeError to raise
+.macro ARRAY_OP opcode, len
+    STORE_SP
+    mov x0, x19
+    mov x1, #\opcode
+    bl glr_array_op
+    cmp x0, #16
+    b.lo 1f
+    mov x20, x0
+    NEXT \len
+1:
+    mov x1, x0
+    b glr_raise
+.endm
+
+glr_op_newarray:
+    ARRAY_OP 30, 2
+
+glr_op_arraylen:
+    ARRAY_OP 31, 1
+
+glr_op_arrayget:
+    ARRAY_OP 32, 1
+
+glr_op_arrayset:
+    ARRAY_OP 33, 1
+
+glr_op_arraypush:
+    ARRAY_OP 34, 1
+
+glr_op_arrayconcat:
+    ARRAY_OP 35, 1
+
+glr_op_arrayslice:
+    ARRAY_OP 36, 1
+
+glr_op_strlen:
+    ARRAY_OP 37, 1
+
+glr_op_strget:
+    ARRAY_OP 38, 1
+
+glr_op_strconcat:
+    ARRAY_OP 39, 1
+
+glr_op_strslice:
+    ARRAY_OP 40, 1
+
+glr_op_invalid:
+    mov x1, #1
+    b glr_raise
+
+glr_divide_by_zero:
+    mov x1, #0
+    b glr_raise
+
+glr_no_implementation:
+    mov x1, #2
+    b glr_raise
+
+glr_out_of_memory:
+    mov x1, #3
+    b glr_raise
+
+glr_bad_closure_call:
+    mov x1, #4
+
+// runtime errors are thrown with a null class and the RuntimeError as payload
+glr_raise:
+    mov x0, #0
+
+// x0 = class of the error, x1 = payload. The registers are saved as an `Unwind`
+// for `glr_unwind` to point at the handler, then reloaded to continue there.
+glr_throw:
+    sub sp, sp, #48
+    stp x19, x20, [sp]
+    stp x21, x22, [sp, #16]
+    stp x0, x1, [sp, #32]
+    mov x0, sp
+    bl glr_unwind
+    ldp x19, x20, [sp]
+    ldp x21, x22, [sp, #16]
+    add sp, sp, #48
+    ldr x9, [x19]
+    br x9
+
+.data
+.balign 8
+glr_halt_cell:
+    .quad glr_op_halt
+    .quad 0
+
+.global glr_dispatch_table
+glr_dispatch_table:
+    .quad glr_op_halt
+    .quad glr_op_push
+    .quad glr_op_pop
+    .quad glr_op_dup
+    .quad glr_op_load
+    .quad glr_op_store
+    .quad glr_op_add
+    .quad glr_op_sub
+    .quad glr_op_mul
+    .quad glr_op_div
+    .quad glr_op_lt
+    .quad glr_op_eq
+    .quad glr_op_jmp
+    .quad glr_op_jz
+    .quad glr_op_call
+    .quad glr_op_enter
+    .quad glr_op_ret
+    .quad glr_op_str
+    .quad glr_op_native
+    .quad glr_op_nop
+    .quad glr_op_invoke
+    .quad glr_op_getstatic
+    .quad glr_op_putstatic
+    .quad glr_op_throw
+    .quad glr_op_rethrow
+    .quad glr_op_new
+    .quad glr_op_invokeinterface
+    .quad glr_op_closure
+    .quad glr_op_callclosure
+    .quad glr_op_capture
+    .quad glr_op_newarray
+    .quad glr_op_arraylen
+    .quad glr_op_arrayget
+    .quad glr_op_arrayset
+    .quad glr_op_arraypush
+    .quad glr_op_arrayconcat
+    .quad glr_op_arrayslice
+    .quad glr_op_strlen
+    .quad glr_op_strget
+    .quad glr_op_strconcat
+    .quad glr_op_strslice
+    .rept 256 - 41
+    .quad glr_op_invalid
+    .endr
+.text
+"#);
@@ -0,0 +1,267 @@
+use super::{Cell, RuntimeError, VM_STATE, KIND_ARRAY, KIND_DATA, KIND_SLOTS, KIND_STRING};
+use super::{alloc, block_at, header, header_kind, header_len, str_header};
+use super::super::{Opcode, TypeSize, str_words, str_from_slot, write_str};
+
+/// Element type of arrays of references, after those of `TypeSize`
+pub const ELEMENT_REFS: u64 = 8;
+
+/// Arrays are a header holding their element type, their length and their elements, which are
+/// allocated separately to grow: a data block of packed numbers, or slots for references.
+const ARRAY_LEN: usize = 1;
+const ARRAY_DATA: usize = 2;
+
+/// Run the array or string instruction `opcode` at `cell` on the stack ending at `sp`,
+/// returning the stack's new top. Backends store `sp` in the VM state first, since
+/// these allocate.
+pub unsafe fn array_op(opcode: Opcode, cell: *const Cell, sp: *mut u64) -> Result<*mut u64, RuntimeError> {
+    match opcode {
+        Opcode::NewArray => {
+            let len = index(*sp)?;
+            *sp = new_array((*cell).operand, len, sp)? as u64;
+            Ok(sp)
+        },
+        Opcode::ArrayLen => {
+            *sp = *array(*sp)?.add(ARRAY_LEN);
+            Ok(sp)
+        },
+        Opcode::ArrayGet => {
+            let array = array(*sp.sub(1))?;
+            let index = bounded(*sp, *array.add(ARRAY_LEN) as usize)?;
+            *sp.sub(1) = get_element(array, index);
+            Ok(sp.sub(1))
+        },
+        Opcode::ArraySet => {
+            let array = array(*sp.sub(2))?;
+            let index = bounded(*sp.sub(1), *array.add(ARRAY_LEN) as usize)?;
+            set_element(array, index, *sp);
+            Ok(sp.sub(3))
+        },
+        Opcode::ArrayPush => {
+            let array = array(*sp.sub(1))?;
+            let len = *array.add(ARRAY_LEN) as usize;
+            if len == capacity(array) {
+                grow(array)?;
+            }
+            *array.add(ARRAY_LEN) = len as u64 + 1;
+            set_element(array, len, *sp);
+            Ok(sp.sub(2))
+        },
+        // the result has the element type of the first array
+        Opcode::ArrayConcat => {
+            let (first, second) = (array(*sp.sub(1))?, array(*sp)?);
+            let (first_len, second_len) = (*first.add(ARRAY_LEN) as usize, *second.add(ARRAY_LEN) as usize);
+            let result = new_array(elements(first), first_len + second_len, sp)?;
+            copy(first, 0, result, 0, first_len);
+            copy(second, 0, result, first_len, second_len);
+            *sp.sub(1) = result as u64;
+            Ok(sp.sub(1))
+        },
+        Opcode::ArraySlice => {
+            let array = array(*sp.sub(2))?;
+            let (start, end) = range(*sp.sub(1), *sp, *array.add(ARRAY_LEN) as usize)?;
+            let result = new_array(elements(array), end - start, sp)?;
+            copy(array, start, result, 0, end - start);
+            *sp.sub(2) = result as u64;
+            Ok(sp.sub(2))
+        },
+        Opcode::StrLen => {
+            *sp = string(*sp)?.len() as u64;
+            Ok(sp)
+        },
+        Opcode::StrGet => {
+            let string = string(*sp.sub(1))?;
+            *sp.sub(1) = string.as_bytes()[bounded(*sp, string.len())?] as u64;
+            Ok(sp.sub(1))
+        },
+        Opcode::StrConcat => {
+            let (first, second) = (string(*sp.sub(1))?, string(*sp)?);
+            *sp.sub(1) = new_str(&[first.as_bytes(), second.as_bytes()])?;
+            Ok(sp.sub(1))
+        },
+        // slices of strings have to start and end on char boundaries
+        Opcode::StrSlice => {
+            let string = string(*sp.sub(2))?;
+            let (start, end) = range(*sp.sub(1), *sp, string.len())?;
+            let slice = string.get(start..end).ok_or(RuntimeError::IndexOutOfBounds)?;
+            *sp.sub(2) = new_str(&[slice.as_bytes()])?;
+            Ok(sp.sub(2))
+        },
+        _ => Err(RuntimeError::InvalidInstruction),
+    }
+}
+
+#[inline]
+fn index(value: u64) -> Result<usize, RuntimeError> {
+    match value as i64 {
+        index if index < 0 => Err(RuntimeError::IndexOutOfBounds),
+        index => Ok(index as usize),
+    }
+}
+
+#[inline]
+fn bounded(value: u64, len: usize) -> Result<usize, RuntimeError> {
+    match index(value)? {
+        index if index < len => Ok(index),
+        _ => Err(RuntimeError::IndexOutOfBounds),
+    }
+}
+
+#[inline]
+fn range(start: u64, end: u64, len: usize) -> Result<(usize, usize), RuntimeError> {
+    let (start, end) = (index(start)?, index(end)?);
+    match start <= end && end <= len {
+        true => Ok((start, end)),
+        false => Err(RuntimeError::IndexOutOfBounds),
+    }
+}
+
+unsafe fn array(value: u64) -> Result<*mut u64, RuntimeError> {
+    match block_at(value) {
+        Some(array) if array as u64 == value && header_kind(*array) == Some(KIND_ARRAY) => Ok(array),
+        _ if value == 0 => Err(RuntimeError::NullReference),
+        _ => Err(RuntimeError::WrongType),
+    }
+}
+
+/// The string `value` points at the bytes of, on the heap or laid out the same way
+/// in the loader's memory for consts
+unsafe fn string<'a>(value: u64) -> Result<&'a str, RuntimeError> {
+    let valid = match block_at(value) {
+        Some(string) => string as u64 + 16 == value && header_kind(*string) == Some(KIND_STRING),
+        None if value == 0 => return Err(RuntimeError::NullReference),
+        None => match VM_STATE.loader.as_ref() {
+            Some(loader) => {
+                let (base, top) = (loader.memory.as_ptr::<u8>() as u64, loader.memory.top_ptr() as u64);
+                let words = value as *const u64;
+                value % 8 == 0 && value >= base + 16 && value < top
+                    && *words.sub(1) < top - value
+                    && *words.sub(2) == str_header(*words.sub(1) as usize)
+            },
+            None => false,
+        },
+    };
+
+    match valid {
+        true => Ok(str_from_slot(value)),
+        false => Err(RuntimeError::WrongType),
+    }
+}
+
+#[inline]
+unsafe fn elements(array: *const u64) -> u64 {
+    header_len(*array) as u64
+}
+
+/// Bytes per element of arrays of `elements`
+#[inline]
+fn element_size(elements: u64) -> usize {
+    match TypeSize::from(elements as u8) {
+        Some(TypeSize::U8) => 1,
+        Some(TypeSize::U16) => 2,
+        Some(TypeSize::U32) | Some(TypeSize::I32) | Some(TypeSize::F32) => 4,
+        _ => 8,
+    }
+}
+
+#[inline]
+unsafe fn capacity(array: *const u64) -> usize {
+    match *array.add(ARRAY_DATA) as *const u64 {
+        data if data.is_null() => 0,
+        data => header_len(*data) * 8 / element_size(elements(array)),
+    }
+}
+
+/// An array of `len` zeroed elements of type `elements`, kept above `sp` on the stack
+/// while its elements are allocated
+pub unsafe fn new_array(elements: u64, len: usize, sp: *mut u64) -> Result<*mut u64, RuntimeError> {
+    if elements > ELEMENT_REFS {
+        return Err(RuntimeError::InvalidInstruction)
+    }
+
+    let array = alloc(3);
+    if array.is_null() {
+        return Err(RuntimeError::OutOfMemory)
+    }
+    *array = header(KIND_ARRAY, elements as usize);
+    *array.add(ARRAY_LEN) = 0;
+    *array.add(ARRAY_DATA) = 0;
+
+    if len > 0 {
+        *sp.add(1) = array as u64;
+        VM_STATE.sp = sp.add(1);
+        let data = new_data(elements, len);
+        VM_STATE.sp = sp;
+        *array.add(ARRAY_DATA) = data.ok_or(RuntimeError::OutOfMemory)? as u64;
+        *array.add(ARRAY_LEN) = len as u64;
+    }
+    Ok(array)
+}
+
+/// A zeroed data block for at least `len` elements of type `elements`
+unsafe fn new_data(elements: u64, len: usize) -> Option<*mut u64> {
+    let words = len.checked_mul(element_size(elements))?.checked_add(7)? / 8;
+    let data = alloc(words.checked_add(1)?);
+    if data.is_null() {
+        return None
+    }
+
+    *data = header(if elements == ELEMENT_REFS { KIND_SLOTS } else { KIND_DATA }, words);
+    for word in 1..=words {
+        *data.add(word) = 0;
+    }
+    Some(data)
+}
+
+/// Double the capacity of the array, which has to be on the stack
+unsafe fn grow(array: *mut u64) -> Result<(), RuntimeError> {
+    let len = *array.add(ARRAY_LEN) as usize;
+    let data = new_data(elements(array), (2 * len).max(4)).ok_or(RuntimeError::OutOfMemory)?;
+    let old = *array.add(ARRAY_DATA) as *const u64;
+    if !old.is_null() {
+        core::ptr::copy_nonoverlapping(old.add(1), data.add(1), header_len(*old));
+    }
+    *array.add(ARRAY_DATA) = data as u64;
+    Ok(())
+}
+
+/// Element `index` widened to a slot, with floats as the bits of an f64 like on the stack
+unsafe fn get_element(array: *const u64, index: usize) -> u64 {
+    let bytes = (*array.add(ARRAY_DATA) as *const u64).add(1) as *const u8;
+    match TypeSize::from(elements(array) as u8) {
+        Some(TypeSize::U8) => *bytes.add(index) as u64,
+        Some(TypeSize::U16) => *(bytes as *const u16).add(index) as u64,
+        Some(TypeSize::U32) => *(bytes as *const u32).add(index) as u64,
+        Some(TypeSize::I32) => *(bytes as *const i32).add(index) as i64 as u64,
+        Some(TypeSize::F32) => (*(bytes as *const f32).add(index) as f64).to_bits(),
+        _ => *(bytes as *const u64).add(index),
+    }
+}
+
+/// Store the slot `value` as element `index`, narrowing it to the element type
+pub unsafe fn set_element(array: *const u64, index: usize, value: u64) {
+    let bytes = (*array.add(ARRAY_DATA) as *const u64).add(1) as *mut u8;
+    match TypeSize::from(elements(array) as u8) {
+        Some(TypeSize::U8) => *bytes.add(index) = value as u8,
+        Some(TypeSize::U16) => *(bytes as *mut u16).add(index) = value as u16,
+        Some(TypeSize::U32) | Some(TypeSize::I32) => *(bytes as *mut u32).add(index) = value as u32,
+        Some(TypeSize::F32) => *(bytes as *mut f32).add(index) = f64::from_bits(value) as f32,
+        _ => *(bytes as *mut u64).add(index) = value,
+    }
+}
+
+/// Copy `len` elements between arrays, converting them if the element types differ
+unsafe fn copy(from: *const u64, from_index: usize, to: *const u64, to_index: usize, len: usize) {
+    for offset in 0..len {
+        set_element(to, to_index + offset, get_element(from, from_index + offset));
+    }
+}
+
+/// A string on the heap of `parts` concatenated, as a stack slot
+unsafe fn new_str(parts: &[&[u8]]) -> Result<u64, RuntimeError> {
+    let size = parts.iter().map(|part| part.len()).sum();
+    let string = alloc(str_words(size));
+    if string.is_null() {
+        return Err(RuntimeError::OutOfMemory)
+    }
+    Ok(write_str(string, parts).as_ptr() as u64)
+}
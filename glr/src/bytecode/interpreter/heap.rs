@@ -0,0 +1,339 @@
+use super::VM_STATE;
+use super::super::{Class, Field, str_words};
+use super::super::shared::mem::{MemoryRange, HEAP_MEMORY, GC_MEMORY};
+use core::ops::Range;
+use core::ptr::null_mut;
+
+/// Blocks are allocated in granules of two words, enough for a free block's header and link
+const GRANULE: usize = 2;
+const GRANULE_BYTES: usize = GRANULE * 8;
+
+/// Words allocated between collections while little is live
+const MIN_THRESHOLD: usize = 1 << 20;
+
+/// Headers of blocks that aren't struct instances have their low bit set, which a class
+/// pointer never has, followed by the block's kind and a length above `LEN_SHIFT`
+pub const KIND_FREE: u64 = 0;
+pub const KIND_CLOSURE: u64 = 1;
+pub const KIND_ARRAY: u64 = 2;
+pub const KIND_DATA: u64 = 3;
+pub const KIND_SLOTS: u64 = 4;
+pub const KIND_STRING: u64 = 5;
+pub const KIND_MAP: u64 = 6;
+pub const KIND_SET: u64 = 7;
+const LEN_SHIFT: u64 = 8;
+
+/// Instances, closures and the other values bytecode allocates, collected by a mark and
+/// sweep of the blocks reachable from the operand stack and module fields. Slots aren't
+/// typed, so any slot holding the address of a block in use keeps it alive.
+struct Heap {
+    blocks: MemoryRange,
+    gc: MemoryRange,
+    starts: *mut u64,
+    marks: *mut u64,
+    mark_stack: *mut *mut u64,
+    free: *mut u64,
+    cursor: *mut u64,
+    limit: *mut u64,
+    allocated: usize,
+    threshold: usize,
+}
+
+static mut HEAP: Option<Heap> = None;
+
+#[inline]
+pub fn header(kind: u64, len: usize) -> u64 {
+    1 | kind << 1 | (len as u64) << LEN_SHIFT
+}
+
+/// The kind of a block's header, or None for struct instances
+#[inline]
+pub fn header_kind(header: u64) -> Option<u64> {
+    match header & 1 {
+        0 => None,
+        _ => Some(header >> 1 & 0x7f),
+    }
+}
+
+#[inline]
+pub fn header_len(header: u64) -> usize {
+    (header >> LEN_SHIFT) as usize
+}
+
+/// Header of a string of `size` bytes laid out as `str_words` describes, which
+/// strings outside the heap have too
+#[inline]
+pub fn str_header(size: usize) -> u64 {
+    header(KIND_STRING, str_words(size) - 1)
+}
+
+/// Reserve the memory blocks are allocated from, if it isn't already
+pub unsafe fn reserve_heap() -> Option<()> {
+    if HEAP.is_none() {
+        let blocks = MemoryRange::at(HEAP_MEMORY)?;
+        let gc = MemoryRange::at(GC_MEMORY)?;
+
+        // a bit per granule for block starts and marks, and room to mark every block
+        let bitmap_words = blocks.len() / GRANULE_BYTES / 64;
+        let starts = gc.as_ptr::<u64>();
+        let marks = starts.add(bitmap_words);
+        let mark_stack = marks.add(bitmap_words) as *mut *mut u64;
+        HEAP = Some(Heap {
+            blocks, gc, starts, marks, mark_stack,
+            free: null_mut(),
+            cursor: null_mut(),
+            limit: null_mut(),
+            allocated: 0,
+            threshold: MIN_THRESHOLD,
+        });
+    }
+    Some(())
+}
+
+/// Allocate a block of `words` for the caller to fill in before allocating again, collecting
+/// first once enough was allocated since the last collection. Null if out of memory.
+pub unsafe fn alloc(words: usize) -> *mut u64 {
+    let heap = match HEAP.as_mut() {
+        Some(heap) => heap,
+        None => return null_mut(),
+    };
+
+    if words >= heap.blocks.len() / 8 {
+        return null_mut()
+    }
+
+    let words = round_up(words);
+    if heap.allocated >= heap.threshold {
+        heap.collect();
+    }
+
+    let block = match heap.take(words) {
+        Some(block) => block,
+        None => {
+            heap.collect();
+            match heap.take(words) {
+                Some(block) => block,
+                None => return null_mut(),
+            }
+        },
+    };
+    heap.allocated += words;
+    block
+}
+
+/// Give back the end of a block of `words` just allocated, keeping its first `keep`
+pub unsafe fn shrink(block: *mut u64, words: usize, keep: usize) {
+    if let Some(heap) = HEAP.as_mut() {
+        let (words, keep) = (round_up(words), round_up(keep));
+        if keep < words {
+            // the next collection merges it with whatever is free around it
+            let rest = block.add(keep);
+            *rest = header(KIND_FREE, words - keep);
+            set_bit(heap.starts, heap.granule(rest));
+            heap.allocated -= words - keep;
+        }
+    }
+}
+
+/// The block in use that `value` points at, or whose bytes it points at for strings
+pub unsafe fn block_at(value: u64) -> Option<*mut u64> {
+    HEAP.as_ref()?.block_at(value)
+}
+
+/// Collect every block nothing reachable points at
+pub unsafe fn collect() {
+    if let Some(heap) = HEAP.as_mut() {
+        heap.collect();
+    }
+}
+
+#[inline]
+fn round_up(words: usize) -> usize {
+    (words.max(1) + GRANULE - 1) & !(GRANULE - 1)
+}
+
+/// The size of the block in words, and which of its words can hold references
+unsafe fn layout(block: *const u64) -> (usize, Range<usize>) {
+    let (size, refs) = match header_kind(*block) {
+        None => {
+            let class = *block as *const Class;
+            let num_fields = (*class).class_file().fields.as_ref().map_or(0, |fields| fields.len());
+            (1 + num_fields, 1..1 + num_fields)
+        },
+        Some(KIND_CLOSURE) => {
+            let num_captures = header_len(*block);
+            (2 + num_captures, 2..2 + num_captures)
+        },
+        Some(KIND_ARRAY) => (3, 2..3),
+        Some(KIND_MAP) | Some(KIND_SET) => (4, 2..3),
+        Some(KIND_SLOTS) => (1 + header_len(*block), 1..1 + header_len(*block)),
+        Some(KIND_DATA) | Some(KIND_STRING) => (1 + header_len(*block), 0..0),
+        Some(_) => (header_len(*block), 0..0),
+    };
+    (round_up(size), refs)
+}
+
+#[inline]
+unsafe fn test_bit(bitmap: *const u64, bit: usize) -> bool {
+    *bitmap.add(bit / 64) & 1 << (bit % 64) != 0
+}
+
+#[inline]
+unsafe fn set_bit(bitmap: *mut u64, bit: usize) {
+    *bitmap.add(bit / 64) |= 1 << (bit % 64);
+}
+
+#[inline]
+unsafe fn clear_bit(bitmap: *mut u64, bit: usize) {
+    *bitmap.add(bit / 64) &= !(1 << (bit % 64));
+}
+
+impl Heap {
+    #[inline]
+    fn granule(&self, block: *const u64) -> usize {
+        (block as usize - self.blocks.as_ptr::<u8>() as usize) / GRANULE_BYTES
+    }
+
+    /// The block in use starting at `value`, if it's the address of one, or the string
+    /// whose bytes start there, since strings are referred to by their bytes
+    unsafe fn block_at(&self, value: u64) -> Option<*mut u64> {
+        let base = self.blocks.as_ptr::<u8>() as u64;
+        let top = self.blocks.top_ptr() as u64;
+        if value < base || value >= top || value % GRANULE_BYTES as u64 != 0 {
+            return None
+        }
+
+        let block = value as *mut u64;
+        if test_bit(self.starts, self.granule(block)) {
+            return match header_kind(*block) {
+                Some(KIND_FREE) => None,
+                _ => Some(block),
+            }
+        }
+
+        let string = block.sub(2);
+        match string as u64 >= base && test_bit(self.starts, self.granule(string)) && header_kind(*string) == Some(KIND_STRING) {
+            true => Some(string),
+            false => None,
+        }
+    }
+
+    /// Bump allocate from the current free block, moving on to the next when it's too small
+    /// (leaving the rest for the next collection to reclaim), then from the top of the heap
+    unsafe fn take(&mut self, words: usize) -> Option<*mut u64> {
+        loop {
+            if (self.limit as usize - self.cursor as usize) / 8 >= words {
+                let block = self.cursor;
+                self.cursor = block.add(words);
+                set_bit(self.starts, self.granule(block));
+                return Some(block)
+            }
+
+            self.seal();
+            match self.free {
+                free if free.is_null() => break,
+                free => {
+                    self.cursor = free;
+                    self.limit = free.add(header_len(*free));
+                    self.free = *free.add(1) as *mut u64;
+                },
+            }
+        }
+
+        let block = self.blocks.alloc_many::<u64>(words)?;
+        set_bit(self.starts, self.granule(block));
+        Some(block)
+    }
+
+    /// Turn what's left of the current free block back into a free block
+    unsafe fn seal(&mut self) {
+        if self.cursor < self.limit {
+            *self.cursor = header(KIND_FREE, (self.limit as usize - self.cursor as usize) / 8);
+            set_bit(self.starts, self.granule(self.cursor));
+        }
+        self.cursor = null_mut();
+        self.limit = null_mut();
+    }
+
+    unsafe fn mark(&mut self, value: u64, depth: &mut usize) {
+        if let Some(block) = self.block_at(value) {
+            let granule = self.granule(block);
+            if !test_bit(self.marks, granule) {
+                set_bit(self.marks, granule);
+                *self.mark_stack.add(*depth) = block;
+                *depth += 1;
+            }
+        }
+    }
+
+    unsafe fn collect(&mut self) {
+        let mut depth = 0;
+        self.seal();
+
+        // the live part of the operand stack, as the backend stored it before allocating
+        let mut slot = VM_STATE.stack_base;
+        while !slot.is_null() && slot <= VM_STATE.sp {
+            self.mark(*slot, &mut depth);
+            slot = slot.add(1);
+        }
+
+        if let Some(loader) = VM_STATE.loader.as_ref() {
+            for class in loader.classes() {
+                for field in class.class_file().fields.iter().flat_map(|fields| fields.iter()) {
+                    if let Field::Module(_, _, value) = field {
+                        self.mark(*value, &mut depth);
+                    }
+                }
+            }
+        }
+
+        while depth > 0 {
+            depth -= 1;
+            let block = *self.mark_stack.add(depth);
+            for word in layout(block).1 {
+                self.mark(*block.add(word), &mut depth);
+            }
+        }
+
+        self.sweep();
+    }
+
+    /// Merge each run of unmarked blocks into a free block, linked in address order,
+    /// and give a run at the top back to the top
+    unsafe fn sweep(&mut self) {
+        let top = self.blocks.top_ptr() as *mut u64;
+        let mut block = self.blocks.as_ptr::<u64>();
+        let mut run: Option<*mut u64> = None;
+        let mut live = 0;
+
+        self.free = null_mut();
+        let mut tail: *mut *mut u64 = &mut self.free;
+        while block < top {
+            let size = layout(block).0;
+            let granule = self.granule(block);
+            if test_bit(self.marks, granule) {
+                clear_bit(self.marks, granule);
+                live += size;
+                if let Some(start) = run.take() {
+                    *start = header(KIND_FREE, (block as usize - start as usize) / 8);
+                    *tail = start;
+                    tail = start.add(1) as *mut *mut u64;
+                }
+            } else if run.is_none() {
+                run = Some(block);
+            } else {
+                clear_bit(self.starts, granule);
+            }
+            block = block.add(size);
+        }
+
+        if let Some(start) = run {
+            clear_bit(self.starts, self.granule(start));
+            self.blocks.release_from(start as *mut u8);
+        }
+        *tail = null_mut();
+
+        self.allocated = 0;
+        self.threshold = live.max(MIN_THRESHOLD);
+    }
+}
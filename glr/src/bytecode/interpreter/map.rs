@@ -0,0 +1,267 @@
+use super::{VM_STATE, KIND_SET, KIND_SLOTS, ELEMENT_REFS, alloc, header, header_kind, header_len, block_at, new_array, set_element, call_back};
+use super::super::{Class, ClassFile, Method, Hash32, NameHasher, ValueType, TypeSize, MAX_LOAD, MIN_CAPACITY};
+use super::super::{str_from_slot, params, return_type};
+use core::ptr::null_mut;
+
+/// How the keys of a map or set are hashed and compared: integers by value, strings by their
+/// bytes, and struct instances with their `hash` and `eq` methods if they have both, or else
+/// by identity like any other reference
+pub const KEYS_INT: u64 = 0;
+pub const KEYS_STR: u64 = 1;
+pub const KEYS_STRUCT: u64 = 2;
+
+/// Maps and sets are a header holding how their keys compare, their size, a table of
+/// entries placed like the items of a `Mapping` and a count of the changes made to the table.
+/// An entry is a word holding the key's hash and its distance from its home entry plus one
+/// (so 0 is empty), the key and, for maps, the value. Tables are slots, so the collector keeps
+/// keys and values alive.
+const MAP_SIZE: usize = 1;
+const MAP_TABLE: usize = 2;
+const MAP_CHANGES: usize = 3;
+const DISTANCE_SHIFT: u64 = 32;
+const DISTANCE_ONE: u64 = 1 << DISTANCE_SHIFT;
+
+/// An empty map or set (by `kind`) of `keys`, or null if out of memory
+pub unsafe fn new_map(kind: u64, keys: u64) -> *mut u64 {
+    let map = alloc(4);
+    if map.is_null() {
+        return null_mut()
+    }
+
+    *map = header(kind, keys as usize);
+    *map.add(MAP_SIZE) = 0;
+    *map.add(MAP_TABLE) = 0;
+    *map.add(MAP_CHANGES) = 0;
+    map
+}
+
+/// The map or set of `kind` that `value` refers to, if it is one
+pub unsafe fn as_map(value: u64, kind: u64) -> Option<*mut u64> {
+    match block_at(value) {
+        Some(map) if map as u64 == value && header_kind(*map) == Some(kind) => Some(map),
+        _ => None,
+    }
+}
+
+#[inline]
+pub unsafe fn map_len(map: *const u64) -> usize {
+    *map.add(MAP_SIZE) as usize
+}
+
+/// The entry holding `key`, whose value is its third word
+pub unsafe fn map_find(map: *mut u64, key: u64) -> Option<*mut u64> {
+    let hash = hash_key(map, key);
+    find(map, key, hash)
+}
+
+/// Add `key`, or for maps set its value if it's already there. Returns whether the key was
+/// added, or None if out of memory. Keys and values have to be on the operand stack.
+pub unsafe fn map_insert(map: *mut u64, key: u64, value: u64) -> Option<bool> {
+    let hash = hash_key(map, key);
+    if let Some(entry) = find(map, key, hash) {
+        if stride(map) > 2 {
+            *entry.add(2) = value;
+        }
+        return Some(false)
+    }
+
+    let table = *map.add(MAP_TABLE) as *mut u64;
+    let capacity = if table.is_null() { 0 } else { header_len(*table) / stride(map) };
+    if map_len(map) >= capacity * MAX_LOAD / 8 {
+        grow(map, (capacity * 2).max(MIN_CAPACITY))?;
+    }
+
+    place(map, [hash as u64 | DISTANCE_ONE, key, value]);
+    *map.add(MAP_SIZE) += 1;
+    *map.add(MAP_CHANGES) += 1;
+    Some(true)
+}
+
+/// Take `key` out, shifting the entries probed past it back like `Mapping::remove`.
+/// Returns whether it was there.
+pub unsafe fn map_remove(map: *mut u64, key: u64) -> bool {
+    let mut entry = match map_find(map, key) {
+        Some(entry) => entry,
+        None => return false,
+    };
+
+    let (table, stride) = (*map.add(MAP_TABLE) as *mut u64, stride(map));
+    let mask = header_len(*table) / stride - 1;
+    let mut index = (entry as usize - table.add(1) as usize) / 8 / stride;
+    loop {
+        index = (index + 1) & mask;
+        let next = table.add(1 + index * stride);
+        if *next >> DISTANCE_SHIFT <= 1 {
+            core::ptr::write_bytes(entry, 0, stride);
+            break
+        }
+
+        core::ptr::copy_nonoverlapping(next, entry, stride);
+        *entry -= DISTANCE_ONE;
+        entry = next;
+    }
+
+    *map.add(MAP_SIZE) -= 1;
+    *map.add(MAP_CHANGES) += 1;
+    true
+}
+
+/// An array of references holding word `word` of each entry (1 for keys, 2 for values),
+/// or null if out of memory. The map has to be on the operand stack.
+pub unsafe fn map_items(map: *mut u64, word: usize) -> *mut u64 {
+    let array = match new_array(ELEMENT_REFS, map_len(map), VM_STATE.sp as *mut u64) {
+        Ok(array) => array,
+        Err(_) => return null_mut(),
+    };
+
+    let mut index = 0;
+    for entry in entries(map) {
+        if *entry != 0 {
+            set_element(array, index, *entry.add(word));
+            index += 1;
+        }
+    }
+    array
+}
+
+#[inline]
+unsafe fn stride(map: *const u64) -> usize {
+    match header_kind(*map) {
+        Some(KIND_SET) => 2,
+        _ => 3,
+    }
+}
+
+/// Every entry of the table, empty or not
+unsafe fn entries(map: *const u64) -> impl Iterator<Item = *mut u64> {
+    let (table, stride) = (*map.add(MAP_TABLE) as *mut u64, stride(map));
+    let capacity = if table.is_null() { 0 } else { header_len(*table) / stride };
+    (0..capacity).map(move |index| table.add(1 + index * stride))
+}
+
+/// Probe for `key` from its home entry, stopping at the first entry closer to its own home.
+/// An `eq` method can change the map while comparing keys, so the probe starts over if it did.
+unsafe fn find(map: *mut u64, key: u64, hash: u32) -> Option<*mut u64> {
+    'probe: loop {
+        let table = *map.add(MAP_TABLE) as *mut u64;
+        if table.is_null() || map_len(map) == 0 {
+            return None
+        }
+
+        let stride = stride(map);
+        let mask = header_len(*table) / stride - 1;
+        let mut index = hash as usize & mask;
+        for distance in 0..=mask as u64 {
+            let entry = table.add(1 + index * stride);
+            if *entry >> DISTANCE_SHIFT <= distance {
+                return None
+            } else if *entry as u32 == hash {
+                let changes = *map.add(MAP_CHANGES);
+                let equal = keys_equal(map, *entry.add(1), key);
+                if *map.add(MAP_CHANGES) != changes {
+                    continue 'probe
+                } else if equal {
+                    return Some(entry)
+                }
+            }
+            index = (index + 1) & mask;
+        }
+        return None
+    }
+}
+
+/// Move the entries into a new table of `capacity`, by the hashes they hold
+unsafe fn grow(map: *mut u64, capacity: usize) -> Option<()> {
+    let words = capacity * stride(map);
+    let table = alloc(1 + words);
+    if table.is_null() {
+        return None
+    }
+    *table = header(KIND_SLOTS, words);
+    core::ptr::write_bytes(table.add(1), 0, words);
+
+    let (old, stride) = (entries(map), stride(map));
+    *map.add(MAP_TABLE) = table as u64;
+    *map.add(MAP_CHANGES) += 1;
+    for entry in old {
+        if *entry != 0 {
+            let value = if stride > 2 { *entry.add(2) } else { 0 };
+            place(map, [*entry & 0xffffffff | DISTANCE_ONE, *entry.add(1), value]);
+        }
+    }
+    Some(())
+}
+
+/// Put an entry in its Robin Hood place, the table having room for it
+unsafe fn place(map: *mut u64, mut entry: [u64; 3]) {
+    let (table, stride) = (*map.add(MAP_TABLE) as *mut u64, stride(map));
+    let mask = header_len(*table) / stride - 1;
+    let mut index = entry[0] as u32 as usize & mask;
+    loop {
+        let slot = core::slice::from_raw_parts_mut(table.add(1 + index * stride), stride);
+        if slot[0] == 0 {
+            return slot.copy_from_slice(&entry[..stride])
+        } else if slot[0] >> DISTANCE_SHIFT < entry[0] >> DISTANCE_SHIFT {
+            slot.swap_with_slice(&mut entry[..stride]);
+        }
+        entry[0] += DISTANCE_ONE;
+        index = (index + 1) & mask;
+    }
+}
+
+/// Keys are hashed with a key drawn per process, since they often come from input
+unsafe fn hash_key(map: *const u64, key: u64) -> u32 {
+    let hasher = NameHasher::seeded();
+    match header_len(*map) as u64 {
+        KEYS_STR if key != 0 => str_from_slot(key).hash32(&hasher),
+        KEYS_STRUCT => match protocol(key) {
+            Some((class_file, hash, _)) => call_back(class_file, hash, &[key]).hash32(&hasher),
+            None => key.hash32(&hasher),
+        },
+        _ => key.hash32(&hasher),
+    }
+}
+
+unsafe fn keys_equal(map: *const u64, first: u64, second: u64) -> bool {
+    if first == second {
+        return true
+    }
+
+    match header_len(*map) as u64 {
+        KEYS_STR => first != 0 && second != 0 && str_from_slot(first) == str_from_slot(second),
+        // instances of different structs are never equal
+        KEYS_STRUCT => match (protocol(first), protocol(second)) {
+            (Some((class_file, _, eq)), Some((other, _, _))) if class_file as *const ClassFile == other as *const ClassFile =>
+                call_back(class_file, eq, &[first, second]) != 0,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// The struct of the instance `key` and its `hash` and `eq` methods, if it has both:
+/// `hash()q`, and `eq` taking another instance of the struct and returning a `q` or `B`
+/// that's nonzero for equal instances
+unsafe fn protocol<'a>(key: u64) -> Option<(&'a ClassFile, &'a Method, &'a Method)> {
+    let object = block_at(key).filter(|&object| object as u64 == key)?;
+    if header_kind(*object).is_some() {
+        return None
+    }
+
+    let class_file = (*(*object as *const Class)).class_file();
+    let loader = VM_STATE.loader.as_ref()?;
+    let method = |name: &str, takes: &[ValueType], returns: &[ValueType]| {
+        let name = loader.symbol(name)?;
+        class_file.methods_named(name).find(|method| {
+            let descriptor = method.key.descriptor.as_str();
+            !method.is_static() && !method.is_native() && !method.is_abstract()
+                && params(descriptor).eq(takes.iter().cloned())
+                && returns.contains(&return_type(descriptor))
+        })
+    };
+
+    let (int, byte) = (ValueType::Num(TypeSize::I64), ValueType::Num(TypeSize::U8));
+    let hash = method("hash", &[], &[int])?;
+    let eq = method("eq", &[ValueType::Class(class_file.name.as_str())], &[int, byte])?;
+    Some((class_file, hash, eq))
+}
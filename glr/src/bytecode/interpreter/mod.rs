@@ -0,0 +1,226 @@
+use super::{Opcode, Reader, ClassError, ClassResult, ClassLoader, ClassFile, Method};
+use super::shared::mem::{MemoryRange, STACK_MEMORY, FRAME_MEMORY};
+
+#[cfg(target_arch = "x86_64")]
+#[allow(dead_code)]
+pub mod x86_64;
+#[cfg(target_arch = "aarch64")]
+#[allow(dead_code)]
+pub mod aarch64;
+#[allow(dead_code)]
+pub mod portable;
+#[allow(dead_code)]
+pub mod trace;
+#[allow(dead_code)]
+pub mod unwind;
+#[allow(dead_code)]
+pub mod heap;
+#[allow(dead_code)]
+pub mod object;
+#[allow(dead_code)]
+pub mod array;
+#[allow(dead_code)]
+pub mod map;
+
+pub use self::trace::*;
+pub use self::unwind::*;
+pub use self::heap::*;
+pub use self::object::*;
+pub use self::array::*;
+pub use self::map::*;
+
+#[cfg(target_arch = "x86_64")]
+use self::x86_64 as arch;
+#[cfg(target_arch = "aarch64")]
+use self::aarch64 as arch;
+
+/// A pre-decoded instruction: the address of its handler and its operand widened to 64 bits.
+/// Cells are laid out one per bytecode byte so that a bytecode offset `n` is always `cells[n]`,
+/// which keeps jump targets and method code positions valid without any translation table.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct Cell {
+    pub handler: usize,
+    pub operand: u64,
+}
+
+/// Which interpreter executes predecoded cells. Both share the same cell layout,
+/// stack layout and calling convention; only what a cell's `handler` holds differs.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Backend {
+    Assembly,
+    Portable,
+}
+
+/// The operand stack and frame stack methods run on
+pub struct Runtime {
+    stack: MemoryRange,
+    frames: MemoryRange,
+}
+
+pub const NARGS_SHIFT: u64 = 48;
+pub const TARGET_MASK: u64 = (1 << NARGS_SHIFT) - 1;
+
+impl Default for Backend {
+    fn default() -> Self {
+        if cfg!(feature = "portable") {
+            Backend::Portable
+        } else {
+            Backend::Assembly
+        }
+    }
+}
+
+impl Backend {
+    #[inline]
+    pub fn name(self) -> &'static str {
+        match self {
+            Backend::Assembly => "assembly",
+            Backend::Portable => "portable",
+        }
+    }
+
+    #[inline]
+    pub fn dispatch_table(self) -> &'static [usize; 256] {
+        match self {
+            Backend::Assembly => arch::dispatch_table(),
+            Backend::Portable => &portable::DISPATCH_TABLE,
+        }
+    }
+
+    /// Run the cells starting at `code` until the outermost frame returns, with the first
+    /// `num_args` slots of `stack` as its arguments. `stack` and `frames` must be large
+    /// enough for the deepest call chain.
+    #[inline]
+    pub unsafe fn interpret(self, code: *const Cell, stack: *mut u64, num_args: usize, frames: *mut u64) -> u64 {
+        match self {
+            Backend::Assembly => arch::interpret(code, stack, num_args, frames),
+            Backend::Portable => portable::interpret(code, stack, num_args, frames),
+        }
+    }
+}
+
+impl Runtime {
+    pub fn new() -> Option<Self> {
+        unsafe { reserve_heap()? };
+        Some(Self {
+            stack: MemoryRange::at(STACK_MEMORY)?,
+            frames: MemoryRange::at(FRAME_MEMORY)?,
+        })
+    }
+
+    /// Run the cells starting at `entry` on `backend` with empty stacks
+    pub unsafe fn run(&mut self, backend: Backend, entry: *const Cell) -> u64 {
+        VM_STATE.pc = entry;
+        VM_STATE.frame_base = self.frames.as_ptr();
+        VM_STATE.stack_base = self.stack.as_ptr();
+        let result = backend.interpret(entry, self.stack.as_ptr(), 0, self.frames.as_ptr());
+
+        // nothing left on the stacks keeps heap blocks alive once the entry point returns
+        VM_STATE.pc = core::ptr::null();
+        VM_STATE.sp = core::ptr::null();
+        result
+    }
+
+    /// Run a method of `class_file` that takes no arguments, returning None for natives.
+    /// Stack traces of runtime errors and panics name methods of classes in `loader`.
+    pub unsafe fn invoke(&mut self, loader: &ClassLoader, class_file: &ClassFile, method: &Method) -> Option<u64> {
+        if method.is_native() {
+            return None
+        }
+
+        VM_STATE.loader = loader;
+        let result = self.run(loader.backend, class_file.code.add(method.code_pos as usize));
+        VM_STATE.loader = core::ptr::null();
+        Some(result)
+    }
+}
+
+/// Call `method` with `args` from a native, on the stacks above those of the bytecode that called
+/// the native. Errors the method doesn't catch stop the program instead of unwinding through the
+/// native, though its stack trace goes on through the bytecode that called the native.
+pub unsafe fn call_back(class_file: &ClassFile, method: &Method, args: &[u64]) -> u64 {
+    let saved = VM_STATE;
+    let stack = (VM_STATE.sp as *mut u64).add(1);
+    let frames = VM_STATE.frames as *mut u64;
+    core::ptr::copy_nonoverlapping(args.as_ptr(), stack, args.len());
+
+    let code = class_file.code.add(method.code_pos as usize);
+    VM_STATE.pc = code;
+    VM_STATE.frame_base = frames;
+    VM_STATE.outer = &saved;
+    let result = (*VM_STATE.loader).backend.interpret(code, stack, args.len(), frames);
+    VM_STATE = saved;
+    result
+}
+
+/// Rewrite the raw `bytecode` into `cells`, replacing each opcode with its handler from the
+/// backend's dispatch table and each operand with the form its handler consumes directly.
+/// Operands that index the class's const pool or methods are turned into pointers by `resolve`.
+pub unsafe fn predecode(
+    bytecode: &[u8],
+    cells: *mut Cell,
+    backend: Backend,
+    resolve: &dyn Fn(Opcode, u16) -> ClassResult<u64>,
+) -> ClassResult<()> {
+    let dispatch_table = backend.dispatch_table();
+    let invalid = Cell { handler: dispatch_table[255], operand: 0 };
+    for pos in 0..bytecode.len() {
+        *cells.add(pos) = invalid;
+    }
+
+    let code_size = bytecode.len();
+    let target = |offset: u32| -> ClassResult<u64> {
+        match offset as usize {
+            offset if offset < code_size => Ok(cells.add(offset) as u64),
+            _ => Err(ClassError::BadCodePos)
+        }
+    };
+
+    let mut pos = 0;
+    while pos < code_size {
+        let opcode = Opcode::from(bytecode[pos]).ok_or(ClassError::BadOpcode)?;
+        let mut reader: Reader = bytecode.get(pos + 1..).unwrap_or(&[]).into();
+        let operand = match opcode {
+            Opcode::Push => reader.read::<i64>().ok_or(ClassError::BadCodeData)? as u64,
+            Opcode::Load |
+            Opcode::Store |
+            Opcode::Enter => reader.read::<u16>().ok_or(ClassError::BadCodeData)? as u64 * 8,
+            Opcode::Jmp |
+            Opcode::Jz => target(reader.read::<u32>().ok_or(ClassError::BadCodeData)?)?,
+            Opcode::Call => {
+                let method = target(reader.read::<u32>().ok_or(ClassError::BadCodeData)?)?;
+                let num_args = reader.read::<u8>().ok_or(ClassError::BadCodeData)? as u64;
+                method | (num_args << NARGS_SHIFT)
+            },
+            Opcode::NewArray => match reader.read::<u8>().ok_or(ClassError::BadCodeData)? as u64 {
+                elements if elements <= ELEMENT_REFS => elements,
+                _ => return Err(ClassError::BadCodeData),
+            },
+            Opcode::CallClosure => reader.read::<u8>().ok_or(ClassError::BadCodeData)? as u64,
+            // captured values follow the closure's header and call operand
+            Opcode::Capture => 16 + reader.read::<u16>().ok_or(ClassError::BadCodeData)? as u64 * 8,
+            Opcode::Str |
+            Opcode::Native => resolve(opcode, reader.read::<u16>().ok_or(ClassError::BadCodeData)?)?,
+            _ => 0,
+        };
+
+        *cells.add(pos) = Cell { handler: dispatch_table[opcode as usize], operand };
+        pos += opcode.len();
+    }
+
+    // every jump and call must land on the start of an instruction
+    let mut pos = 0;
+    while pos < code_size {
+        let opcode = Opcode::from(bytecode[pos]).ok_or(ClassError::BadOpcode)?;
+        let operand = (*cells.add(pos)).operand & TARGET_MASK;
+        match opcode {
+            Opcode::Jmp | Opcode::Jz | Opcode::Call
+                if (*(operand as *const Cell)).handler == invalid.handler =>
+                    return Err(ClassError::BadCodePos),
+            _ => pos += opcode.len(),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,68 @@
+use super::{Cell, VM_STATE, TARGET_MASK, NARGS_SHIFT, KIND_CLOSURE, alloc, header, header_kind};
+use super::super::{Class, Method};
+use core::ptr::{copy_nonoverlapping, null_mut};
+
+/// A zeroed instance of the struct `class`, which is its class followed by a slot per field,
+/// or null if out of memory
+pub unsafe fn new_object(class: *mut Class) -> *mut u64 {
+    let num_fields = (*class).class_file().fields.as_ref().map_or(0, |fields| fields.len());
+    let object = alloc(1 + num_fields);
+    if object.is_null() {
+        return null_mut()
+    }
+
+    *object = class as u64;
+    for slot in 1..=num_fields {
+        *object.add(slot) = 0;
+    }
+    object
+}
+
+/// A closure of the `Closure` at `cell` over the values on top of the operand stack: its header,
+/// the call operand of its method and the captured values, or null if out of memory
+pub unsafe fn new_closure(cell: *const Cell) -> *mut u64 {
+    let num_captures = (*cell.add(1)).operand as usize;
+    let closure = alloc(2 + num_captures);
+    if closure.is_null() {
+        return null_mut()
+    }
+
+    *closure = header(KIND_CLOSURE, num_captures);
+    *closure.add(1) = (*cell).operand;
+    copy_nonoverlapping(VM_STATE.sp.add(1).sub(num_captures), closure.add(2), num_captures);
+    closure
+}
+
+/// The call operand of `closure` called with `num_args` besides itself, or 0 if it isn't
+/// a closure taking that many
+pub unsafe fn closure_target(closure: *const u64, num_args: u64) -> u64 {
+    if closure.is_null() || header_kind(*closure) != Some(KIND_CLOSURE) {
+        return 0
+    }
+
+    match *closure.add(1) {
+        target if target >> NARGS_SHIFT == num_args + 1 => target,
+        _ => 0,
+    }
+}
+
+/// The slow path of the `InvokeInterface` at `cell` on an instance of `class`: find the method
+/// implementing the interface method in the class's vtable and cache it in the next cell.
+/// Returns it as a call operand, or 0 if the class doesn't implement the interface.
+pub unsafe fn dispatch(cell: *mut Cell, class: *mut Class) -> u64 {
+    // closures and other built in values have a header in place of a class
+    if header_kind(class as u64).is_some() {
+        return 0
+    }
+
+    let operand = (*cell).operand;
+    let method = &*((operand & TARGET_MASK) as *const Method);
+    let target = match (*class).class_file().implementation(method) {
+        Some(target) => (*target.class).class_file().code.add(target.code_pos as usize) as u64,
+        None => return 0,
+    };
+
+    let target = target | operand & !TARGET_MASK;
+    *cell.add(1) = Cell { handler: class as usize, operand: target };
+    target
+}
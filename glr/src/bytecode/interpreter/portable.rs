@@ -0,0 +1,205 @@
+use super::{Cell, Opcode, RuntimeError, Unwind, VM_STATE, NARGS_SHIFT, TARGET_MASK, unwind, new_object, new_closure, closure_target, dispatch, array_op};
+use super::super::{Class, Native};
+use core::ptr::null_mut;
+use core::slice::from_raw_parts;
+
+lazy_static! {
+    /// The portable "handlers" are the opcodes themselves, with 255 reserved for invalid cells
+    pub static ref DISPATCH_TABLE: [usize; 256] = {
+        let mut table = [255; 256];
+        for (value, handler) in table.iter_mut().enumerate() {
+            if let Some(opcode) = Opcode::from(value as u8) {
+                *handler = opcode as usize;
+            }
+        }
+        table
+    };
+}
+
+/// Pure Rust equivalent of the assembly `interpret`, using the same register roles
+/// (pc, sp, fp, frame stack) as locals and the same stack and frame layout.
+pub unsafe fn interpret(code: *const Cell, stack: *mut u64, num_args: usize, frames: *mut u64) -> u64 {
+    let halt = Cell { handler: Opcode::Halt as usize, operand: 0 };
+    let mut pc = code;
+    let mut sp = stack.add(num_args).sub(1);
+    let mut fp = stack;
+    let mut frame = frames;
+
+    *frame = &halt as *const Cell as u64;
+    *frame.add(1) = fp as u64;
+    frame = frame.add(2);
+
+    // unwind to the handler of the error and continue there
+    macro_rules! throw {
+        ($error_class:expr, $payload:expr) => {{
+            let mut state = Unwind { pc, sp, fp, frames: frame, error_class: $error_class, payload: $payload };
+            unwind(&mut state);
+            pc = state.pc;
+            sp = state.sp;
+            fp = state.fp;
+            frame = state.frames;
+            continue
+        }};
+    }
+
+    // push a frame returning past the instruction and jump to the target of the call operand
+    macro_rules! call {
+        ($opcode:expr, $operand:expr) => {{
+            *frame = pc.add($opcode.len()) as u64;
+            *frame.add(1) = fp as u64;
+            frame = frame.add(2);
+            fp = sp.sub($operand as usize >> NARGS_SHIFT).add(1);
+            pc = ($operand & TARGET_MASK) as *const Cell;
+            continue
+        }};
+    }
+
+    loop {
+        let Cell { handler, operand } = *pc;
+        let opcode = match Opcode::from(handler as u8) {
+            Some(opcode) => opcode,
+            None => throw!(null_mut(), RuntimeError::InvalidInstruction as u64),
+        };
+
+        match opcode {
+            Opcode::Halt => return *sp,
+            Opcode::Push => {
+                sp = sp.add(1);
+                *sp = operand;
+            },
+            Opcode::Pop => sp = sp.sub(1),
+            Opcode::Dup => {
+                *sp.add(1) = *sp;
+                sp = sp.add(1);
+            },
+            Opcode::Load => {
+                sp = sp.add(1);
+                *sp = *fp.add(operand as usize / 8);
+            },
+            Opcode::Store => {
+                *fp.add(operand as usize / 8) = *sp;
+                sp = sp.sub(1);
+            },
+            Opcode::Add => binary(&mut sp, |a, b| a.wrapping_add(b)),
+            Opcode::Sub => binary(&mut sp, |a, b| a.wrapping_sub(b)),
+            Opcode::Mul => binary(&mut sp, |a, b| a.wrapping_mul(b)),
+            Opcode::Div if *sp == 0 => throw!(null_mut(), RuntimeError::DivideByZero as u64),
+            Opcode::Div => binary(&mut sp, |a, b| a.wrapping_div(b)),
+            Opcode::Lt => binary(&mut sp, |a, b| (a < b) as i64),
+            Opcode::Eq => binary(&mut sp, |a, b| (a == b) as i64),
+            Opcode::Jmp => {
+                pc = operand as *const Cell;
+                continue
+            },
+            Opcode::Jz => {
+                sp = sp.sub(1);
+                if *sp.add(1) == 0 {
+                    pc = operand as *const Cell;
+                    continue
+                }
+            },
+            Opcode::Call | Opcode::Invoke => call!(opcode, operand),
+            Opcode::Enter => sp = sp.add(operand as usize / 8),
+            Opcode::Ret => {
+                *fp = *sp;
+                sp = fp;
+                frame = frame.sub(2);
+                pc = *frame as *const Cell;
+                fp = *frame.add(1) as *mut u64;
+                continue
+            },
+            Opcode::Str => {
+                sp = sp.add(1);
+                *sp = operand;
+            },
+            Opcode::Native => {
+                let native = (operand & TARGET_MASK) as *const Native;
+                sp = sp.sub(operand as usize >> NARGS_SHIFT);
+                let args = from_raw_parts(sp.add(1), (*native).signature.num_args);
+                VM_STATE.pc = pc;
+                VM_STATE.frames = frame;
+                VM_STATE.sp = sp.add(args.len());
+                sp = sp.add(1);
+                *sp = (*native).call(args);
+            },
+            Opcode::Nop => {},
+            Opcode::GetStatic => {
+                sp = sp.add(1);
+                *sp = *(operand as *const u64);
+            },
+            Opcode::PutStatic => {
+                *(operand as *mut u64) = *sp;
+                sp = sp.sub(1);
+            },
+            Opcode::Throw => {
+                sp = sp.sub(1);
+                throw!(operand as *mut Class, *sp.add(1))
+            },
+            Opcode::Rethrow => {
+                sp = sp.sub(2);
+                throw!(*sp.add(2) as *mut Class, *sp.add(1))
+            },
+            Opcode::New => {
+                VM_STATE.sp = sp;
+                let object = new_object(operand as *mut Class);
+                if object.is_null() {
+                    throw!(null_mut(), RuntimeError::OutOfMemory as u64)
+                }
+                sp = sp.add(1);
+                *sp = object as u64;
+            },
+            Opcode::InvokeInterface => {
+                let instance = *sp.sub(operand as usize >> NARGS_SHIFT).add(1) as *const u64;
+                let cache = *pc.add(1);
+                let target = match instance.is_null() {
+                    true => 0,
+                    false if *instance as usize == cache.handler => cache.operand,
+                    false => dispatch(pc as *mut Cell, *instance as *mut Class),
+                };
+                match target {
+                    0 => throw!(null_mut(), RuntimeError::NoImplementation as u64),
+                    target => call!(opcode, target),
+                }
+            },
+            Opcode::Closure => {
+                VM_STATE.sp = sp;
+                let closure = new_closure(pc);
+                if closure.is_null() {
+                    throw!(null_mut(), RuntimeError::OutOfMemory as u64)
+                }
+                sp = sp.sub((*pc.add(1)).operand as usize).add(1);
+                *sp = closure as u64;
+            },
+            Opcode::CallClosure => {
+                let closure = *sp.sub(operand as usize) as *const u64;
+                match closure_target(closure, operand) {
+                    0 => throw!(null_mut(), RuntimeError::BadClosureCall as u64),
+                    target => call!(opcode, target),
+                }
+            },
+            Opcode::Capture => {
+                let closure = *fp as *const u64;
+                sp = sp.add(1);
+                *sp = *closure.add(operand as usize / 8);
+            },
+            Opcode::NewArray | Opcode::ArrayLen | Opcode::ArrayGet | Opcode::ArraySet |
+            Opcode::ArrayPush | Opcode::ArrayConcat | Opcode::ArraySlice |
+            Opcode::StrLen | Opcode::StrGet | Opcode::StrConcat | Opcode::StrSlice => {
+                VM_STATE.sp = sp;
+                match array_op(opcode, pc, sp) {
+                    Ok(top) => sp = top,
+                    Err(error) => throw!(null_mut(), error as u64),
+                }
+            },
+        }
+
+        pc = pc.add(opcode.len());
+    }
+}
+
+#[inline(always)]
+unsafe fn binary<F: Fn(i64, i64) -> i64>(sp: &mut *mut u64, op: F) {
+    let rhs = **sp as i64;
+    *sp = sp.sub(1);
+    **sp = op(**sp as i64, rhs) as u64;
+}
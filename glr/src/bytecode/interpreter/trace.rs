@@ -0,0 +1,142 @@
+use super::Cell;
+use super::super::{ClassLoader, ClassFile, Method, Opcode};
+use super::super::shared::exit;
+use core::fmt::Arguments;
+
+/// Exit status of a process stopped by a runtime error or a panic
+pub const ABORT_STATUS: i32 = 70;
+
+/// Errors the interpreter throws while running bytecode, as the payload of an error
+/// of `RUNTIME_ERROR_CLASS`. The assembly backends throw these by value, so the order
+/// must not change.
+#[repr(u64)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum RuntimeError {
+    DivideByZero,
+    InvalidInstruction,
+    NoImplementation,
+    OutOfMemory,
+    BadClosureCall,
+    IndexOutOfBounds,
+    NullReference,
+    WrongType,
+}
+
+/// Where the interpreter is, for stack traces. `pc` and `frames` (the next free frame pair)
+/// are stored by the backends before control can leave bytecode, i.e. around native calls
+/// and when raising a runtime error, and `pc` is null while no bytecode is running.
+/// `sp` is stored before allocating, for the heap to find the values on the stack.
+/// `outer` is the state `call_back` saved while bytecode runs for a native, if it does.
+#[repr(C)]
+#[derive(Copy, Clone)]
+pub struct VmState {
+    pub pc: *const Cell,
+    pub frames: *const u64,
+    pub sp: *const u64,
+    pub frame_base: *const u64,
+    pub stack_base: *const u64,
+    pub loader: *const ClassLoader,
+    pub outer: *const VmState,
+}
+
+#[export_name = "glr_vm_state"]
+pub static mut VM_STATE: VmState = VmState {
+    pc: core::ptr::null(),
+    frames: core::ptr::null(),
+    sp: core::ptr::null(),
+    frame_base: core::ptr::null(),
+    stack_base: core::ptr::null(),
+    loader: core::ptr::null(),
+    outer: core::ptr::null(),
+};
+
+impl RuntimeError {
+    #[inline]
+    pub fn from(value: u64) -> Self {
+        match value {
+            0 => RuntimeError::DivideByZero,
+            2 => RuntimeError::NoImplementation,
+            3 => RuntimeError::OutOfMemory,
+            4 => RuntimeError::BadClosureCall,
+            5 => RuntimeError::IndexOutOfBounds,
+            6 => RuntimeError::NullReference,
+            7 => RuntimeError::WrongType,
+            _ => RuntimeError::InvalidInstruction,
+        }
+    }
+
+    pub fn message(self) -> &'static str {
+        match self {
+            RuntimeError::DivideByZero => "division by zero",
+            RuntimeError::InvalidInstruction => "invalid instruction",
+            RuntimeError::NoImplementation => "instance doesn't implement the interface",
+            RuntimeError::OutOfMemory => "out of memory",
+            RuntimeError::BadClosureCall => "called a value that isn't a closure taking those arguments",
+            RuntimeError::IndexOutOfBounds => "index out of bounds",
+            RuntimeError::NullReference => "null reference",
+            RuntimeError::WrongType => "value of the wrong type",
+        }
+    }
+}
+
+/// Report an error nothing caught, thrown by the instruction at `pc`, with a stack trace and exit
+pub unsafe fn abort(message: Arguments, pc: *const Cell, frames: *const u64) -> ! {
+    VM_STATE.pc = pc;
+    VM_STATE.frames = frames;
+    println!("error: {}", message);
+    print_stack_trace();
+    exit(ABORT_STATUS)
+}
+
+/// Print the methods on the call stack of the running bytecode, innermost first, carrying on
+/// through the bytecode that called the natives calling back into it
+pub unsafe fn print_stack_trace() {
+    let mut state = &VM_STATE;
+    while !state.pc.is_null() {
+        print_frame(state.loader, state.pc);
+
+        // frame pairs hold the pc to return to, past the call, and the first is the halt cell
+        let mut frame = state.frames;
+        while frame > state.frame_base.add(2) {
+            frame = frame.sub(2);
+            print_frame(state.loader, (*frame as *const Cell).sub(1));
+        }
+
+        match state.outer.as_ref() {
+            Some(outer) => state = outer,
+            None => break,
+        }
+    }
+}
+
+unsafe fn print_frame(loader: *const ClassLoader, cell: *const Cell) {
+    let location = match loader.is_null() {
+        true => None,
+        false => (*loader).find_code(cell),
+    };
+
+    let (class_file, method, code_pos) = match location {
+        Some(location) => location,
+        None => return println!("  at {:p}", cell),
+    };
+
+    let code_pos = instruction_at(class_file, method, code_pos);
+    match (class_file.source_file(), method.line_at(code_pos)) {
+        (Some(source_file), Some(line)) =>
+            println!("  at {}.{} ({}:{})", class_file.name, method.name(), source_file, line),
+        _ => println!("  at {}.{} (offset {})", class_file.name, method.name(), code_pos),
+    }
+}
+
+/// Start of the instruction covering `code_pos`, found by walking the method's bytecode
+unsafe fn instruction_at(class_file: &ClassFile, method: &Method, code_pos: u64) -> u64 {
+    let mut pos = method.code_pos;
+    while pos < class_file.code_size as u64 {
+        let len = Opcode::from(*class_file.bytecode.add(pos as usize)).map_or(1, Opcode::len) as u64;
+        if pos + len > code_pos {
+            break
+        }
+        pos += len;
+    }
+    pos
+}
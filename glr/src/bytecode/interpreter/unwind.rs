@@ -0,0 +1,68 @@
+use super::{Cell, VM_STATE, RuntimeError, abort};
+use super::super::{Class, Mappable};
+
+/// Module whose class runtime errors are thrown as, with the `RuntimeError` as their payload
+pub const RUNTIME_ERROR_CLASS: &'static str = "std.error";
+
+/// The interpreter's registers when an error is thrown, along with the error's class
+/// (null for runtime errors) and payload. The backends pass this to `unwind`, which
+/// rewrites the registers to resume at the handler catching the error.
+#[repr(C)]
+pub struct Unwind {
+    pub pc: *const Cell,
+    pub sp: *mut u64,
+    pub fp: *mut u64,
+    pub frames: *mut u64,
+    pub error_class: *mut Class,
+    pub payload: u64,
+}
+
+/// Find the innermost handler for the error, popping the frames of the methods without
+/// one, and push the payload and class for it. Uncaught errors are reported and exit.
+pub unsafe fn unwind(state: &mut Unwind) {
+    let loader = VM_STATE.loader;
+    let runtime_errors = match loader.is_null() {
+        true => core::ptr::null_mut(),
+        false => (*loader).find(RUNTIME_ERROR_CLASS).map_or(core::ptr::null_mut(), |class| class as *mut Class),
+    };
+    if state.error_class.is_null() {
+        state.error_class = runtime_errors;
+    }
+
+    let (mut cell, mut fp, mut frames) = (state.pc, state.fp, state.frames);
+    loop {
+        let location = match loader.is_null() {
+            true => None,
+            false => (*loader).find_code(cell),
+        };
+
+        let handler = location.and_then(|(class_file, method, code_pos)| {
+            let handler = method.find_handler(class_file, code_pos, state.error_class)?;
+            Some((class_file.code.add(handler.handler as usize), handler.stack_depth as usize))
+        });
+
+        if let Some((handler, stack_depth)) = handler {
+            *fp.add(stack_depth) = state.payload;
+            *fp.add(stack_depth + 1) = state.error_class as u64;
+            state.pc = handler;
+            state.sp = fp.add(stack_depth + 1);
+            state.fp = fp;
+            state.frames = frames;
+            return
+        }
+
+        // the first frame pair returns to the halt cell, so the error left the outermost method
+        if frames <= VM_STATE.frame_base.add(2) as *mut u64 {
+            break
+        }
+
+        frames = frames.sub(2);
+        cell = (*frames as *const Cell).sub(1);
+        fp = *frames.add(1) as *mut u64;
+    }
+
+    match state.error_class == runtime_errors {
+        true => abort(format_args!("{}", RuntimeError::from(state.payload).message()), state.pc, state.frames),
+        false => abort(format_args!("uncaught {} ({})", (*state.error_class).id(), state.payload), state.pc, state.frames),
+    }
+}
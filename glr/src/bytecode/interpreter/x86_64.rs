@@ -0,0 +1,520 @@
+use super::{Cell, RuntimeError, Unwind, VM_STATE, unwind, new_object, new_closure, dispatch, array_op};
+use super::super::{Class, Native, Opcode};
+use core::slice::from_raw_parts;
+
+extern "C" {
+    static glr_dispatch_table: [usize; 256];
+}
+
+#[inline]
+pub fn dispatch_table() -> &'static [usize; 256] {
+    unsafe { &glr_dispatch_table }
+}
+
+/// Called by the `native` handler with the args on top of the operand stack
+#[no_mangle]
+pub unsafe extern "sysv64" fn glr_call_native(native: *const Native, args: *const u64) -> u64 {
+    (*native).call(from_raw_parts(args, (*native).signature.num_args))
+}
+
+/// Called by `glr_throw` to move the registers it saved to the handler of the error
+#[no_mangle]
+pub unsafe extern "sysv64" fn glr_unwind(state: *mut Unwind) {
+    unwind(&mut *state)
+}
+
+/// Called by the `new` handler to allocate an instance of the struct
+#[no_mangle]
+pub unsafe extern "sysv64" fn glr_new(class: *mut Class) -> *mut u64 {
+    new_object(class)
+}
+
+/// Called by the `closure` handler to allocate a closure over the values on top of the stack
+#[no_mangle]
+pub unsafe extern "sysv64" fn glr_closure(cell: *const Cell) -> *mut u64 {
+    new_closure(cell)
+}
+
+/// Called by the array and string handlers, returning the new sp or the `RuntimeError` to raise
+#[no_mangle]
+pub unsafe extern "sysv64" fn glr_array_op(cell: *const Cell, opcode: u8) -> u64 {
+    let result = Opcode::from(opcode).ok_or(RuntimeError::InvalidInstruction)
+        .and_then(|opcode| array_op(opcode, cell, VM_STATE.sp as *mut u64));
+    match result {
+        Ok(sp) => sp as u64,
+        Err(error) => error as u64,
+    }
+}
+
+/// Called by the `invokeinterface` handler when the instance's class misses the inline cache
+#[no_mangle]
+pub unsafe extern "sysv64" fn glr_dispatch(cell: *mut Cell, class: *mut Class) -> u64 {
+    dispatch(cell, class)
+}
+
+// Register assignment for the duration of `interpret`:
+//   r12 = pc, pointer to the current Cell
+//   r13 = sp, pointer to the top slot of the operand stack (grows upwards)
+//   r14 = fp, pointer to local 0 of the current frame
+//   r15 = pointer to the next free (return pc, saved fp) pair on the frame stack
+//
+// Every handler ends by advancing r12 by its instruction length and jumping to the
+// handler of the next cell, so dispatch costs a single indirect jump.
+asm_func!(interpret(code: *const Cell, stack: *mut u64, num_args: usize, frames: *mut u64) -> u64, r#"
+    push rbx
+    push rbp
+    push r12
+    push r13
+    push r14
+    push r15
+    mov r12, rdi
+    lea r13, [rsi + 8 * rdx - 8]
+    mov r14, rsi
+    mov r15, rcx
+    lea rax, [rip + glr_halt_cell]
+    mov [r15], rax
+    mov [r15 + 8], r14
+    add r15, 16
+    jmp qword ptr [r12]
+
+.macro NEXT len
+    add r12, 16 * \len
+    jmp qword ptr [r12]
+.endm
+
+glr_op_halt:
+    mov rax, [r13]
+    pop r15
+    pop r14
+    pop r13
+    pop r12
+    pop rbp
+    pop rbx
+    ret
+
+glr_op_push:
+    mov rax, [r12 + 8]
+    add r13, 8
+    mov [r13], rax
+    NEXT 9
+
+glr_op_pop:
+    sub r13, 8
+    NEXT 1
+
+glr_op_dup:
+    mov rax, [r13]
+    add r13, 8
+    mov [r13], rax
+    NEXT 1
+
+glr_op_load:
+    mov rax, [r12 + 8]
+    mov rax, [r14 + rax]
+    add r13, 8
+    mov [r13], rax
+    NEXT 3
+
+glr_op_store:
+    mov rax, [r12 + 8]
+    mov rcx, [r13]
+    sub r13, 8
+    mov [r14 + rax], rcx
+    NEXT 3
+
+glr_op_add:
+    mov rax, [r13]
+    sub r13, 8
+    add [r13], rax
+    NEXT 1
+
+glr_op_sub:
+    mov rax, [r13]
+    sub r13, 8
+    sub [r13], rax
+    NEXT 1
+
+glr_op_mul:
+    mov rax, [r13]
+    sub r13, 8
+    imul rax, [r13]
+    mov [r13], rax
+    NEXT 1
+
+glr_op_div:
+    mov rcx, [r13]
+    test rcx, rcx
+    jz glr_divide_by_zero
+    sub r13, 8
+    cmp rcx, -1
+    je 1f
+    mov rax, [r13]
+    cqo
+    idiv rcx
+    mov [r13], rax
+    NEXT 1
+1:
+    // idiv traps on i64::MIN / -1, which wraps like the other backends instead
+    neg qword ptr [r13]
+    NEXT 1
+
+glr_op_lt:
+    mov rax, [r13]
+    sub r13, 8
+    xor ecx, ecx
+    cmp [r13], rax
+    setl cl
+    mov [r13], rcx
+    NEXT 1
+
+glr_op_eq:
+    mov rax, [r13]
+    sub r13, 8
+    xor ecx, ecx
+    cmp [r13], rax
+    sete cl
+    mov [r13], rcx
+    NEXT 1
+
+glr_op_jmp:
+    mov r12, [r12 + 8]
+    jmp qword ptr [r12]
+
+glr_op_jz:
+    mov rax, [r13]
+    sub r13, 8
+    test rax, rax
+    jnz 1f
+    mov r12, [r12 + 8]
+    jmp qword ptr [r12]
+1:
+    NEXT 5
+
+// calls return to the cell after the instruction, so `len` differs between call and invoke.
+// rax holds the target cell, with the number of arguments in its top 16 bits.
+.macro CALL_RAX len
+    lea rcx, [r12 + 16 * \len]
+    mov [r15], rcx
+    mov [r15 + 8], r14
+    add r15, 16
+    mov rcx, rax
+    shr rcx, 48
+    shl rcx, 3
+    mov r14, r13
+    sub r14, rcx
+    add r14, 8
+    shl rax, 16
+    shr rax, 16
+    mov r12, rax
+    jmp qword ptr [r12]
+.endm
+
+.macro CALL len
+    mov rax, [r12 + 8]
+    CALL_RAX \len
+.endm
+
+glr_op_call:
+    CALL 6
+
+glr_op_enter:
+    add r13, [r12 + 8]
+    NEXT 3
+
+glr_op_ret:
+    mov rax, [r13]
+    mov [r14], rax
+    mov r13, r14
+    sub r15, 16
+    mov r12, [r15]
+    mov r14, [r15 + 8]
+    jmp qword ptr [r12]
+
+glr_op_str:
+    mov rax, [r12 + 8]
+    add r13, 8
+    mov [r13], rax
+    NEXT 3
+
+// natives can allocate and call back into bytecode, so sp is stored with the args still on it
+glr_op_native:
+    mov rdi, [r12 + 8]
+    mov rcx, rdi
+    shr rcx, 48
+    shl rcx, 3
+    sub r13, rcx
+    lea rsi, [r13 + 8]
+    shl rdi, 16
+    shr rdi, 16
+    mov [rip + glr_vm_state], r12
+    mov [rip + glr_vm_state + 8], r15
+    lea rax, [r13 + rcx]
+    mov [rip + glr_vm_state + 16], rax
+    sub rsp, 8
+    call glr_call_native
+    add rsp, 8
+    add r13, 8
+    mov [r13], rax
+    NEXT 3
+
+glr_op_nop:
+    NEXT 1
+
+glr_op_invoke:
+    CALL 4
+
+glr_op_getstatic:
+    mov rax, [r12 + 8]
+    mov rax, [rax]
+    add r13, 8
+    mov [r13], rax
+    NEXT 3
+
+glr_op_putstatic:
+    mov rax, [r12 + 8]
+    mov rcx, [r13]
+    mov [rax], rcx
+    sub r13, 8
+    NEXT 3
+
+glr_op_throw:
+    mov rdi, [r12 + 8]
+    mov rsi, [r13]
+    sub r13, 8
+    jmp glr_throw
+
+glr_op_rethrow:
+    mov rdi, [r13]
+    mov rsi, [r13 - 8]
+    sub r13, 16
+    jmp glr_throw
+
+// allocating stores sp for the heap to find the values on the stack
+glr_op_new:
+    mov [rip + glr_vm_state + 16], r13
+    mov rdi, [r12 + 8]
+    sub rsp, 8
+    call glr_new
+    add rsp, 8
+    test rax, rax
+    jz glr_out_of_memory
+    add r13, 8
+    mov [r13], rax
+    NEXT 3
+
+// the next cell holds the class the call site last saw and the target it dispatched to
+glr_op_invokeinterface:
+    mov rax, [r12 + 8]
+    mov rcx, rax
+    shr rcx, 48
+    shl rcx, 3
+    mov rsi, r13
+    sub rsi, rcx
+    mov rsi, [rsi + 8]
+    test rsi, rsi
+    jz glr_no_implementation
+    mov rsi, [rsi]
+    cmp rsi, [r12 + 16]
+    jne 1f
+    mov rax, [r12 + 24]
+    CALL_RAX 4
+1:
+    mov rdi, r12
+    sub rsp, 8
+    call glr_dispatch
+    add rsp, 8
+    test rax, rax
+    jz glr_no_implementation
+    CALL_RAX 4
+
+// the next cell holds the number of values the closure captures from the top of the stack
+glr_op_closure:
+    mov [rip + glr_vm_state + 16], r13
+    mov rdi, r12
+    sub rsp, 8
+    call glr_closure
+    add rsp, 8
+    test rax, rax
+    jz glr_out_of_memory
+    mov rcx, [r12 + 24]
+    shl rcx, 3
+    sub r13, rcx
+    add r13, 8
+    mov [r13], rax
+    NEXT 4
+
+// the closure is below the args, and is called as its method's first argument
+glr_op_callclosure:
+    mov rcx, [r12 + 8]
+    mov rsi, r13
+    shl rcx, 3
+    sub rsi, rcx
+    shr rcx, 3
+    mov rsi, [rsi]
+    test rsi, rsi
+    jz glr_bad_closure_call
+    cmp byte ptr [rsi], 3
+    jne glr_bad_closure_call
+    mov rax, [rsi + 8]
+    mov rdx, rax
+    shr rdx, 48
+    dec rdx
+    cmp rdx, rcx
+    jne glr_bad_closure_call
+    CALL_RAX 2
+
+glr_op_capture:
+    mov rax, [r14]
+    mov rcx, [r12 + 8]
+    mov rax, [rax + rcx]
+    add r13, 8
+    mov [r13], rax
+    NEXT 3
+
+// array and string instructions run in Rust, which returns the new sp, or below it
+// the RuntimeError to raise
+.macro ARRAY_OP opcode, len
+    mov [rip + glr_vm_state + 16], r13
+    mov rdi, r12
+    mov esi, \opcode
+    sub rsp, 8
+    call glr_array_op
+    add rsp, 8
+    cmp rax, 16
+    jb 1f
+    mov r13, rax
+    NEXT \len
+1:
+    mov esi, eax
+    jmp glr_raise
+.endm
+
+glr_op_newarray:
+    ARRAY_OP 30, 2
+
+glr_op_arraylen:
+    ARRAY_OP 31, 1
+
+glr_op_arrayget:
+    ARRAY_OP 32, 1
+
+glr_op_arrayset:
+    ARRAY_OP 33, 1
+
+glr_op_arraypush:
+    ARRAY_OP 34, 1
+
+glr_op_arrayconcat:
+    ARRAY_OP 35, 1
+
+glr_op_arrayslice:
+    ARRAY_OP 36, 1
+
+glr_op_strlen:
+    ARRAY_OP 37, 1
+
+glr_op_strget:
+    ARRAY_OP 38, 1
+
+glr_op_strconcat:
+    ARRAY_OP 39, 1
+
+glr_op_strslice:
+    ARRAY_OP 40, 1
+
+glr_op_invalid:
+    mov esi, 1
+    jmp glr_raise
+
+glr_divide_by_zero:
+    xor esi, esi
+    jmp glr_raise
+
+glr_no_implementation:
+    mov esi, 2
+    jmp glr_raise
+
+glr_out_of_memory:
+    mov esi, 3
+    jmp glr_raise
+
+glr_bad_closure_call:
+    mov esi, 4
+
+// runtime errors are thrown with a null class and the RuntimeError as payload
+glr_raise:
+    xor edi, edi
+
+// rdi = class of the error, rsi = payload. The registers are saved as an `Unwind`
+// for `glr_unwind` to point at the handler, then reloaded to continue there.
+glr_throw:
+    sub rsp, 56
+    mov [rsp], r12
+    mov [rsp + 8], r13
+    mov [rsp + 16], r14
+    mov [rsp + 24], r15
+    mov [rsp + 32], rdi
+    mov [rsp + 40], rsi
+    mov rdi, rsp
+    call glr_unwind
+    mov r12, [rsp]
+    mov r13, [rsp + 8]
+    mov r14, [rsp + 16]
+    mov r15, [rsp + 24]
+    add rsp, 56
+    jmp qword ptr [r12]
+
+.data
+.balign 8
+glr_halt_cell:
+    .quad glr_op_halt
+    .quad 0
+
+.global glr_dispatch_table
+glr_dispatch_table:
+    .quad glr_op_halt
+    .quad glr_op_push
+    .quad glr_op_pop
+    .quad glr_op_dup
+    .quad glr_op_load
+    .quad glr_op_store
+    .quad glr_op_add
+    .quad glr_op_sub
+    .quad glr_op_mul
+    .quad glr_op_div
+    .quad glr_op_lt
+    .quad glr_op_eq
+    .quad glr_op_jmp
+    .quad glr_op_jz
+    .quad glr_op_call
+    .quad glr_op_enter
+    .quad glr_op_ret
+    .quad glr_op_str
+    .quad glr_op_native
+    .quad glr_op_nop
+    .quad glr_op_invoke
+    .quad glr_op_getstatic
+    .quad glr_op_putstatic
+    .quad glr_op_throw
+    .quad glr_op_rethrow
+    .quad glr_op_new
+    .quad glr_op_invokeinterface
+    .quad glr_op_closure
+    .quad glr_op_callclosure
+    .quad glr_op_capture
+    .quad glr_op_newarray
+    .quad glr_op_arraylen
+    .quad glr_op_arrayget
+    .quad glr_op_arrayset
+    .quad glr_op_arraypush
+    .quad glr_op_arrayconcat
+    .quad glr_op_arrayslice
+    .quad glr_op_strlen
+    .quad glr_op_strget
+    .quad glr_op_strconcat
+    .quad glr_op_strslice
+    .rept 256 - 41
+    .quad glr_op_invalid
+    .endr
+.text
+"#);
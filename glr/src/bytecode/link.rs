@@ -0,0 +1,227 @@
+use super::{Cell, Class, ClassFile, Field, Method, MethodKey, Const, ConstPool, Opcode, NARGS_SHIFT, ACCESS_MUTABLE};
+use super::{Reader, ClassError, ClassResult, ClassLoader};
+use core::slice::from_raw_parts;
+
+/// What a `Class` or `Member` const resolved to. Each class keeps one per const
+/// (see `ClassFile::links`) so every reference is only resolved once.
+#[derive(Copy, Clone)]
+pub enum Link {
+    Unresolved,
+    Class(*mut Class),
+    Method(*mut Method),
+    Static(*mut Field),
+    Field(*mut Field),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum LinkState {
+    Loaded,
+    Linking,
+    Linked,
+    Failed,
+}
+
+impl ClassLoader {
+    /// Resolve every class and member the class references, loading classes through the
+    /// class path as needed, then point the cells of the instructions using them at their
+    /// targets. Classes are defined before being linked so that reference cycles find the
+    /// class mid-link instead of loading it again.
+    pub fn link(&mut self, class: *mut Class) -> ClassResult<()> {
+        let class_file = unsafe { (*class).class_file_mut() };
+        match class_file.state {
+            LinkState::Linking | LinkState::Linked => return Ok(()),
+            LinkState::Failed => return Err(ClassError::LinkFailed),
+            LinkState::Loaded => class_file.state = LinkState::Linking,
+        }
+
+        let linked: ClassResult<()> = try {
+            for index in 0..class_file.const_pool.len() {
+                self.link_const(class_file, index)?;
+            }
+            self.link_impls(class_file)?;
+            self.link_code(class_file)?
+        };
+
+        class_file.state = match linked {
+            Ok(_) => LinkState::Linked,
+            Err(_) => LinkState::Failed,
+        };
+        linked
+    }
+
+    pub(crate) fn link_const(&mut self, class_file: &ClassFile, index: usize) -> ClassResult<Link> {
+        if index >= class_file.const_pool.len() {
+            return Err(ClassError::BadConstIndex)
+        }
+
+        let link = unsafe { &mut *class_file.links.add(index) };
+        if let Link::Unresolved = *link {} else {
+            return Ok(*link)
+        }
+
+        let const_pool = &class_file.const_pool;
+        *link = match const_pool.as_slice()[index] {
+            Const::Class(_) => {
+                let class_name = const_pool.get_class(index).ok_or(ClassError::BadConstIndex)?;
+                let class = self.resolve(class_name)?;
+                let owner = unsafe { (*class).class_file() };
+                if !class_file.can_access(owner, owner.access) {
+                    return Err(self.fail(ClassError::InaccessibleClass, class_name, ""))
+                }
+                Link::Class(class)
+            },
+            Const::Member(class_index, _, _) => {
+                let (_, name, descriptor) = const_pool.get_member(index).ok_or(ClassError::BadConstIndex)?;
+                match self.link_const(class_file, class_index as usize)? {
+                    Link::Class(class) => self.link_member(class_file, class, name, descriptor)?,
+                    _ => return Err(ClassError::BadConstIndex),
+                }
+            },
+            _ => Link::Unresolved,
+        };
+        Ok(*link)
+    }
+
+    /// A member with a descriptor names the method overloading `name` with it. Without one,
+    /// `name` must match exactly one of the class's methods and fields. Either way, the member
+    /// must be accessible from the class `from` referencing it.
+    fn link_member(&mut self, from: &ClassFile, class: *mut Class, name: &str, descriptor: Option<&str>) -> ClassResult<Link> {
+        let class_file = unsafe { (*class).class_file() };
+        let class_name = class_file.name.as_str();
+        let symbol = self.symbol(name);
+
+        let (link, access) = match descriptor {
+            Some(descriptor) => {
+                let key = try { MethodKey { name: symbol?, descriptor: self.symbol(descriptor)? } };
+                match class_file.methods.as_ref().and_then(|methods| methods.find(&key?)) {
+                    Some(method) => (Link::Method(method), method.access),
+                    None => return Err(self.fail(ClassError::UnresolvedSymbol, class_name, name)),
+                }
+            },
+            None => {
+                let mut methods = symbol.into_iter().flat_map(|symbol| class_file.methods_named(symbol));
+                let method = methods.next();
+                let field = class_file.fields.as_ref().and_then(|fields| fields.find(&symbol?));
+
+                match (method, field, methods.next()) {
+                    (Some(method), None, None) => (Link::Method(method as *const Method as *mut Method), method.access),
+                    (None, Some(field @ Field::Module(..)), _) => (Link::Static(field), field.access()),
+                    (None, Some(field), _) => (Link::Field(field), field.access()),
+                    (None, None, _) => return Err(self.fail(ClassError::UnresolvedSymbol, class_name, name)),
+                    _ => return Err(self.fail(ClassError::AmbiguousSymbol, class_name, name)),
+                }
+            },
+        };
+
+        match from.can_access(class_file, access) {
+            true => Ok(link),
+            false => Err(self.fail(ClassError::InaccessibleSymbol, class_name, name)),
+        }
+    }
+
+    /// Walk the raw bytecode and patch the cell of every instruction referring to a member
+    fn link_code(&mut self, class_file: &ClassFile) -> ClassResult<()> {
+        let bytecode = unsafe { from_raw_parts(class_file.bytecode, class_file.code_size) };
+        let dispatch_table = self.backend.dispatch_table();
+
+        let mut pos = 0;
+        while pos < bytecode.len() {
+            let opcode = Opcode::from(bytecode[pos]).ok_or(ClassError::BadOpcode)?;
+            let mut reader: Reader = bytecode.get(pos + 1..).unwrap_or(&[]).into();
+            let cell = unsafe { class_file.code.add(pos) as *mut Cell };
+
+            match opcode {
+                Opcode::Invoke => unsafe {
+                    let index = reader.read::<u16>().ok_or(ClassError::BadCodeData)? as usize;
+                    let num_args = reader.read::<u8>().ok_or(ClassError::BadCodeData)? as u64;
+                    let method = match self.link_const(class_file, index)? {
+                        Link::Method(method) if (*method).takes_args(num_args) && !(*method).is_abstract() => &*method,
+                        _ => return Err(self.fail_member(ClassError::IncompatibleSymbol, &class_file.const_pool, index)),
+                    };
+
+                    match method.native {
+                        // the native handler only steps over 3 cells, so the invoke's last cell steps over the rest
+                        Some(native) => {
+                            *cell = Cell { handler: dispatch_table[Opcode::Native as usize], operand: native as u64 | num_args << NARGS_SHIFT };
+                            *cell.add(3) = Cell { handler: dispatch_table[Opcode::Nop as usize], operand: 0 };
+                        },
+                        None => {
+                            let code = (*method.class).class_file().code;
+                            (*cell).operand = code.add(method.code_pos as usize) as u64 | num_args << NARGS_SHIFT;
+                        },
+                    }
+                },
+                Opcode::GetStatic |
+                Opcode::PutStatic => {
+                    let index = reader.read::<u16>().ok_or(ClassError::BadCodeData)? as usize;
+                    let field = match self.link_const(class_file, index)? {
+                        Link::Static(field) => unsafe { &mut *field },
+                        _ => return Err(self.fail_member(ClassError::IncompatibleSymbol, &class_file.const_pool, index)),
+                    };
+
+                    // only the field's own class writes it unless it's mutable
+                    if opcode == Opcode::PutStatic && field.access() & ACCESS_MUTABLE == 0 && field.class_file().name != class_file.name {
+                        return Err(self.fail_member(ClassError::ImmutableSymbol, &class_file.const_pool, index))
+                    }
+                    let value = field.static_value().ok_or(ClassError::BadConstIndex)?;
+                    unsafe { (*cell).operand = value as u64 };
+                },
+                // the cell after the invoke caches the class of the last instance it was called
+                // on in place of a handler, along with the target that class's vtable gave
+                Opcode::InvokeInterface => unsafe {
+                    let index = reader.read::<u16>().ok_or(ClassError::BadCodeData)? as usize;
+                    let num_args = reader.read::<u8>().ok_or(ClassError::BadCodeData)? as u64;
+                    match self.link_const(class_file, index)? {
+                        Link::Method(method) if (*method).num_params as u64 == num_args && (*method).is_abstract() => {
+                            (*cell).operand = method as u64 | num_args << NARGS_SHIFT;
+                            *cell.add(1) = Cell { handler: 0, operand: 0 };
+                        },
+                        _ => return Err(self.fail_member(ClassError::IncompatibleSymbol, &class_file.const_pool, index)),
+                    }
+                },
+                // the closure's method takes it as its first argument, and the cell after
+                // the instruction holds how many values on the stack it captures
+                Opcode::Closure => unsafe {
+                    let index = reader.read::<u16>().ok_or(ClassError::BadCodeData)? as usize;
+                    let num_captures = reader.read::<u8>().ok_or(ClassError::BadCodeData)? as u64;
+                    let method = match self.link_const(class_file, index)? {
+                        Link::Method(method) if (*method).num_params > 0 && !(*method).is_native() && !(*method).is_abstract() => &*method,
+                        _ => return Err(self.fail_member(ClassError::IncompatibleSymbol, &class_file.const_pool, index)),
+                    };
+
+                    let code = (*method.class).class_file().code;
+                    (*cell).operand = code.add(method.code_pos as usize) as u64 | (method.num_params as u64) << NARGS_SHIFT;
+                    (*cell.add(1)).operand = num_captures;
+                },
+                Opcode::New => {
+                    let index = reader.read::<u16>().ok_or(ClassError::BadCodeData)? as usize;
+                    match self.link_const(class_file, index)? {
+                        Link::Class(class) if unsafe { (*class).is_struct() } => unsafe { (*cell).operand = class as u64 },
+                        _ => {
+                            let class_name = class_file.const_pool.get_class(index).unwrap_or("");
+                            return Err(self.fail(ClassError::IncompatibleSymbol, class_name, ""))
+                        },
+                    }
+                },
+                Opcode::Throw => {
+                    let index = reader.read::<u16>().ok_or(ClassError::BadCodeData)? as usize;
+                    match self.link_const(class_file, index)? {
+                        Link::Class(class) => unsafe { (*cell).operand = class as u64 },
+                        _ => return Err(ClassError::BadConstIndex),
+                    }
+                },
+                _ => {},
+            }
+
+            pos += opcode.len();
+        }
+
+        Ok(())
+    }
+
+    fn fail_member(&mut self, error: ClassError, const_pool: &ConstPool, index: usize) -> ClassError {
+        let (class_index, name, _) = const_pool.get_member(index).unwrap_or((0, "", None));
+        let class_name = const_pool.get_class(class_index).unwrap_or("");
+        self.fail(error, class_name, name)
+    }
+}
@@ -1,18 +1,58 @@
-use super::TypeSize;
-use super::{Mappable, Mapping, Hash32};
+use super::{TypeSize, Opcode, predecode, NARGS_SHIFT, NO_DESCRIPTOR};
+use super::{Native, NativeSignature, NativeType, MAX_NATIVE_ARGS, Link, LinkState, DEBUG_SECTION, load_debug};
+use super::{EXCEPTIONS_SECTION, load_exceptions, IMPLS_SECTION, load_impls, Impl, Cell};
+use super::{Mappable, Mapping, NameHasher, Hash32, Symbol, MethodKey, check_descriptor, check_type};
 use super::{Reader, ClassError, ClassResult, ClassLoader, ClassLoadable};
 use super::{Class, ClassFile, Field, FieldContext, Method, Const, ConstPool};
+use super::{check_access, ACCESS_NATIVE, ACCESS_STATIC, ACCESS_PUBLIC, ACCESS_PRIVATE, ACCESS_MUTABLE};
+use super::{CLASS_ACCESS, METHOD_ACCESS, FIELD_ACCESS};
 
 use core::mem::transmute;
-use core::ptr::null_mut;
+use core::ptr::{null, null_mut};
+use core::slice::from_raw_parts;
 use core::ptr::copy_nonoverlapping as memcpy;
 
-const CLASS_TYPE_ENUM:   u8 = 0;
-const CLASS_TYPE_STRUCT: u8 = 1;
-const CLASS_TYPE_MODULE: u8 = 2;
+// newer major versions change the layout and minor versions add to it, and files of any
+// older version still load, with the defaults their version implied for what it lacks:
+//   1.0 the class header, const pool, fields, methods and code
+//   1.1 optional sections after the code
+//   2.0 method descriptors, and member consts and sections naming methods by descriptor
+//   3.0 access modifiers on fields
+//   4.0 type parameters on structs and enums, and types on enum fields
+//   4.1 interfaces, and the impls section
+//   4.2 closures, and function types in descriptors
+//   4.3 arrays and string instructions, and array types in descriptors
+pub const CLASS_VERSION: ClassVersion = (4, 3);
 
-impl<'a> ClassLoadable<'a, ()> for Class {
-    fn load(_: (), reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self> {
+/// Major and minor version of the class file format, ordered like a tuple
+pub type ClassVersion = (u16, u16);
+
+const SECTIONS_VERSION:    ClassVersion = (1, 1);
+const DESCRIPTORS_VERSION: ClassVersion = (2, 0);
+const ACCESS_VERSION:      ClassVersion = (3, 0);
+const TYPE_PARAMS_VERSION: ClassVersion = (4, 0);
+const INTERFACES_VERSION:  ClassVersion = (4, 1);
+
+pub const CLASS_TYPE_ENUM:   u8 = 0;
+pub const CLASS_TYPE_STRUCT: u8 = 1;
+pub const CLASS_TYPE_MODULE: u8 = 2;
+pub const CLASS_TYPE_INTERFACE: u8 = 3;
+
+// the low bits of a const's type byte, below its `TypeSize`
+pub const CONST_KIND_NUM:    u8 = 0;
+pub const CONST_KIND_STR:    u8 = 1;
+pub const CONST_KIND_CLASS:  u8 = 2;
+pub const CONST_KIND_MEMBER: u8 = 3;
+
+// the low bits of a 1.x native signature's type bytes, below their `TypeSize`
+const NATIVE_KIND_NUM:  u8 = 0;
+const NATIVE_KIND_STR:  u8 = 1;
+const NATIVE_KIND_VOID: u8 = 2;
+
+/// Classes load into memory the loader allocated beforehand, so that fields and
+/// methods can point back at their class while they're being mapped by name
+impl<'a> ClassLoadable<'a, *mut Class> for Class {
+    fn load(class: *mut Class, reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self> {
         const CLASS_FILE_HEADER: &'static [u8; 4] = b"$GLR";
 
         // read class magic (first 4 bytes = "$GLR")
@@ -21,49 +61,206 @@ impl<'a> ClassLoadable<'a, ()> for Class {
             return Err(ClassError::BadClassMagic)
         }
 
+        // read the format version, refusing files newer than this one, even by a minor version
+        let major = reader.read::<u16>().ok_or(ClassError::BadClassVersion)?;
+        let minor = reader.read::<u16>().ok_or(ClassError::BadClassVersion)?;
+        let version = (major, minor);
+        if major == 0 {
+            return Err(ClassError::BadClassVersion)
+        } else if version > CLASS_VERSION {
+            return Err(ClassError::UnsupportedClassVersion)
+        }
+
         // read class class type, access modifier and class const pool
         let class_type = reader.read::<u8>().ok_or(ClassError::BadClassType)?;
+        if class_type == CLASS_TYPE_INTERFACE && version < INTERFACES_VERSION {
+            return Err(ClassError::BadClassType)
+        }
         let access = reader.read::<u8>().ok_or(ClassError::BadAccessModifier)?;
-        let const_pool = ConstPool::load((), reader, loader)?;
+        let access = match version >= ACCESS_VERSION {
+            true => check_access(access, CLASS_ACCESS)?,
+            false => ACCESS_PUBLIC,
+        };
+        let const_pool = ConstPool::load(version, reader, loader)?;
+        const_pool.get_str(0).ok_or(ClassError::BadClassName)?;
+        let name = loader.intern_const(&const_pool, 0)?;
+        let type_params = match version >= TYPE_PARAMS_VERSION {
+            true => load_type_params(class_type, &const_pool, reader, loader)?,
+            false => &[],
+        };
+
+        // until it's complete, the class only holds the const pool the names of its members are in
+        unsafe {
+            class.write(wrap(class_type, ClassFile {
+                name,
+                version,
+                access,
+                type_params,
+                fields: None,
+                methods: None,
+                impls: None,
+                bytecode: null(),
+                code: null(),
+                code_size: 0,
+                const_pool,
+                source_file: None,
+                links: null_mut(),
+                state: LinkState::Loaded,
+                next_class: 0,
+            })?);
+        }
 
         // read class fields using class type and class methods using bytecode size
         let code_size = reader.read::<u32>().ok_or(ClassError::BadCodeSize)? as usize;
-        let fields = load_mapped::<u8, u16, str, Field>(class_type, ClassError::BadFieldSize, reader, loader)?;
-        let methods = load_mapped::<usize, u16, str, Method>(code_size, ClassError::BadMethodSize, reader, loader)?;
-        
+        let fields = load_mapped::<_, u16, Symbol, Field>((class_type, class), ClassError::BadFieldSize, reader, loader)?;
+        let methods = load_mapped::<_, u16, MethodKey, Method>((code_size, class), ClassError::BadMethodSize, reader, loader)?;
+
+        // interface methods are numbered for the vtables of the structs implementing them
+        if class_type == CLASS_TYPE_INTERFACE {
+            for (slot, method) in methods.iter().flat_map(|methods| methods.iter()).enumerate() {
+                unsafe { (*(method as *const Method as *mut Method)).slot = slot as u16 };
+            }
+        }
+
         // read and allocate bytecode data
         let code_data = reader.read_bytes(code_size).ok_or(ClassError::BadCodeData)?;
         let bytecode = loader.alloc_bytes_exec(code_size)?;
         unsafe { memcpy(code_data.as_ptr(), bytecode, code_size) };
 
+        // rewrite the bytecode into handler cells for the interpreter
+        let code = loader.alloc_cells(code_size)?;
+        unsafe {
+            let loader = &*loader;
+            predecode(code_data, code, loader.backend, &|opcode, index| {
+                resolve_operand(opcode, index, &const_pool, methods.as_ref(), loader)
+            })?
+        };
+
+        // invokes jump straight to a method's first cell, so like a call target it must start an instruction
+        let invalid = loader.backend.dispatch_table()[255];
+        let entries = methods.iter().flat_map(|methods| methods.iter())
+            .filter(|method| !method.is_native() && !method.is_abstract());
+        for method in entries {
+            if unsafe { (*code.add(method.code_pos as usize)).handler } == invalid {
+                return Err(ClassError::BadCodePos)
+            }
+        }
+
+        // sections this version doesn't know of are skipped, so newer minor versions can add them
+        let (source_file, impls) = match version >= SECTIONS_VERSION {
+            true => load_sections(class, code, code_size, methods.as_ref(), reader, loader)?,
+            false => (None, None),
+        };
+
+        // references to other classes stay unresolved until the class is linked
+        let num_consts = const_pool.len();
+        let links = loader.alloc_many::<Link>(num_consts)?;
+        for index in 0..num_consts {
+            unsafe { *links.add(index) = Link::Unresolved };
+        }
+
         // create the class file
         let class_file = ClassFile {
+            name,
+            version,
             access,
+            type_params,
             fields,
             methods,
+            impls,
             bytecode,
+            code,
+            code_size,
             const_pool,
+            source_file,
+            links,
+            state: LinkState::Loaded,
             next_class: 0,
         };
 
         // wrap the class file into the designated class type
-        match class_type {
-            CLASS_TYPE_ENUM => Ok(Class::Enum(class_file)),
-            CLASS_TYPE_STRUCT => Ok(Class::Struct(class_file)),
-            CLASS_TYPE_MODULE => Ok(Class::Module(class_file)),
-            _ => Err(ClassError::BadClassType)
+        wrap(class_type, class_file)
+    }
+}
+
+/// Optional sections are a u16 count, then per section the const index of its name,
+/// a u32 size and that many bytes. Returns the source file named by debug info and the
+/// interfaces the class implements. Sections newer than the file's version are skipped.
+fn load_sections<'a>(
+    class: *mut Class,
+    code: *const Cell,
+    code_size: usize,
+    methods: Option<&Mapping<MethodKey, Method, NameHasher>>,
+    reader: &mut Reader<'a>,
+    loader: &mut ClassLoader,
+) -> ClassResult<(Option<u16>, Option<*mut Impl>)> {
+    let class_file = unsafe { (*class).class_file() };
+    let num_sections = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+    let mut source_file = None;
+    let mut impls = None;
+
+    for _ in 0..num_sections {
+        let name = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+        let size = reader.read::<u32>().ok_or(ClassError::BadSection)? as usize;
+        let mut section: Reader = reader.read_bytes(size).ok_or(ClassError::BadSection)?.into();
+
+        match class_file.const_pool.get_str(name as usize).ok_or(ClassError::BadConstIndex)? {
+            DEBUG_SECTION => source_file = Some(load_debug(&mut section, code_size, methods, class_file, loader)?),
+            EXCEPTIONS_SECTION => load_exceptions(&mut section, code, code_size, methods, class_file, loader)?,
+            IMPLS_SECTION if class_file.version >= INTERFACES_VERSION => impls = load_impls(&mut section, class, loader)?,
+            _ => {},
+        }
+    }
+    Ok((source_file, impls))
+}
+
+/// Structs and enums may declare type parameters: a u8 count, then the const index of each
+/// one's name. Modules can't, so their count is always 0.
+fn load_type_params<'a>(
+    class_type: u8,
+    const_pool: &ConstPool,
+    reader: &mut Reader<'a>,
+    loader: &mut ClassLoader,
+) -> ClassResult<&'static [Symbol]> {
+    let num_params = reader.read::<u8>().ok_or(ClassError::BadTypeParams)? as usize;
+    if num_params == 0 {
+        return Ok(&[])
+    } else if class_type == CLASS_TYPE_MODULE {
+        return Err(ClassError::BadTypeParams)
+    }
+
+    let type_params = loader.alloc_many::<Symbol>(num_params)?;
+    for index in 0..num_params {
+        let name = reader.read::<u16>().ok_or(ClassError::BadTypeParams)?;
+        let name = loader.intern_const(const_pool, name as usize)?;
+        unsafe {
+            if from_raw_parts(type_params, index).contains(&name) {
+                return Err(ClassError::BadTypeParams)
+            }
+            type_params.add(index).write(name);
         }
     }
+    Ok(unsafe { from_raw_parts(type_params, num_params) })
+}
+
+fn wrap(class_type: u8, class_file: ClassFile) -> ClassResult<Class> {
+    match class_type {
+        CLASS_TYPE_ENUM => Ok(Class::Enum(class_file)),
+        CLASS_TYPE_STRUCT => Ok(Class::Struct(class_file)),
+        CLASS_TYPE_MODULE => Ok(Class::Module(class_file)),
+        CLASS_TYPE_INTERFACE => Ok(Class::Interface(class_file)),
+        _ => Err(ClassError::BadClassType)
+    }
 }
 
-impl<'a> ClassLoadable<'a, ()> for ConstPool {
-    fn load(_: (), reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self> {
+impl<'a> ClassLoadable<'a, ClassVersion> for ConstPool {
+    fn load(version: ClassVersion, reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self> {
         match reader.read::<u16>().ok_or(ClassError::BadConstSize)? as usize {
             0 => Err(ClassError::BadConstSize), // 1 constant required for class file name
             num_consts => unsafe {
                 let mut const_pool = ConstPool::new(loader.alloc_many(num_consts)?, num_consts);
                 for index in 0..num_consts {
-                    let constant = Const::load((), reader, loader)?;
+                    let constant = Const::load(version, reader, loader)?;
                     *const_pool.as_slice_mut().get_unchecked_mut(index) = constant;
                 }
                 Ok(const_pool)
@@ -72,54 +269,90 @@ impl<'a> ClassLoadable<'a, ()> for ConstPool {
     }
 }
 
-impl<'a> ClassLoadable<'a, ()> for Const {
-    fn load(_: (), reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self> {        
-        let (type_size, is_string) = TypeSize::extract(reader.read::<u8>().ok_or(ClassError::BadConstType)?);
+impl<'a> ClassLoadable<'a, ClassVersion> for Const {
+    fn load(version: ClassVersion, reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self> {
+        let (type_size, kind) = TypeSize::extract(reader.read::<u8>().ok_or(ClassError::BadConstType)?);
         let type_size = type_size.ok_or(ClassError::BadConstType)?;
 
-        if is_string == 1 {
+        if kind == CONST_KIND_CLASS {
+            let name = reader.read::<u16>().ok_or(ClassError::BadConstData)?;
+            Ok(Const::Class(name))
+        } else if kind == CONST_KIND_MEMBER {
+            let class = reader.read::<u16>().ok_or(ClassError::BadConstData)?;
+            let name = reader.read::<u16>().ok_or(ClassError::BadConstData)?;
+            let descriptor = match version >= DESCRIPTORS_VERSION {
+                true => reader.read::<u16>().ok_or(ClassError::BadConstData)?,
+                false => NO_DESCRIPTOR,
+            };
+            Ok(Const::Member(class, name, descriptor))
+        } else if kind == CONST_KIND_STR {
             let string_size = match read_const_num(type_size, reader)? {
                 Const::UInt(string_size) => string_size,
                 _ => return Err(ClassError::BadConstType)
             } as usize;
 
+            // strings are stored as [size: u64][bytes][\0] so they can be handed to the
+            // interpreter and native code as a single pointer to their bytes
             let bytes = reader.read_bytes(string_size).ok_or(ClassError::BadConstData)?;
-            let string = loader.alloc_bytes(string_size)?;
-            unsafe { memcpy(bytes.as_ptr(), string, string_size) };
-            Ok(Const::Str(string as *const _, string_size))
-        } else {
+            let string = loader.alloc_str(&[bytes])?;
+            Ok(Const::Str(string.as_ptr(), string_size))
+        } else if kind == CONST_KIND_NUM {
             read_const_num(type_size, reader)
+        } else {
+            Err(ClassError::BadConstType)
         }
     }
 }
 
-impl<'a> ClassLoadable<'a, u8> for Field {
-    fn load(class_type: u8, reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self>  {
+impl<'a> ClassLoadable<'a, (u8, *mut Class)> for Field {
+    fn load(root: (u8, *mut Class), reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self>  {
+        let (class_type, class) = root;
+        let class_file = unsafe { (*class).class_file() };
+        let const_pool = &class_file.const_pool;
+
+        // every field starts with its name and access, and only module fields are static.
+        // Before fields had access, they were all public and module fields all mutable.
+        let name = reader.read::<u16>().ok_or(ClassError::BadConstIndex)?;
+        let name = loader.intern_const(const_pool, name as usize)?;
+        let access = match (class_file.version >= ACCESS_VERSION, class_type) {
+            (false, CLASS_TYPE_MODULE) => ACCESS_PUBLIC | ACCESS_MUTABLE,
+            (false, _) => ACCESS_PUBLIC,
+            (true, _) => check_access(reader.read::<u8>().ok_or(ClassError::BadAccessModifier)?, match class_type {
+                CLASS_TYPE_MODULE => FIELD_ACCESS,
+                CLASS_TYPE_STRUCT => FIELD_ACCESS & !ACCESS_STATIC,
+                _ => ACCESS_PUBLIC | ACCESS_PRIVATE,
+            })?,
+        };
         let context = FieldContext {
             next_field: 0,
-            class: null_mut(),
+            class,
+            access,
         };
 
         match class_type {
-            CLASS_TYPE_MODULE => {
-                let module = reader.read::<u16>().ok_or(ClassError::BadConstIndex)?;
-                Ok(Field::Module(context, module))
-            },
+            CLASS_TYPE_MODULE => Ok(Field::Module(context, name, 0)),
 
             CLASS_TYPE_STRUCT => {
-                let field_name = reader.read::<u16>().ok_or(ClassError::BadConstIndex)?;
+                // field types were free-form until there were type parameters to check them against
                 let field_type = reader.read::<u16>().ok_or(ClassError::BadConstIndex)?;
-                Ok(Field::Struct(context, field_name, field_type))
+                if class_file.version >= TYPE_PARAMS_VERSION {
+                    check_field_type(class_file, field_type)?;
+                }
+                Ok(Field::Struct(context, name, field_type))
             },
 
             CLASS_TYPE_ENUM => {
-                let name = reader.read::<u16>().ok_or(ClassError::BadConstIndex)?;
                 let num_values = reader.read::<u16>().ok_or(ClassError::BadEnumSize)?;
                 let field = Field::Enum(context, name, None);
 
                 (0..num_values).fold(Ok((field, None)), |fields: ClassResult<(Field, Option<*mut Field>)>, _| unsafe {
                     let (mut head, current) = fields?;
                     let enum_name = reader.read::<u16>().ok_or(ClassError::BadEnumField)?;
+                    let enum_name = loader.intern_const(const_pool, enum_name as usize)?;
+                    if class_file.version >= TYPE_PARAMS_VERSION {
+                        let field_type = reader.read::<u16>().ok_or(ClassError::BadEnumField)?;
+                        check_field_type(class_file, field_type)?;
+                    }
                     let enum_field = loader.alloc(Field::Enum(context, enum_name, None))?;
 
                     // set the previous enum field's next to point to the created enum_field
@@ -136,27 +369,259 @@ impl<'a> ClassLoadable<'a, u8> for Field {
     }
 }
 
-impl<'a> ClassLoadable<'a, usize> for Method {
-    fn load(code_size: usize, reader: &mut Reader<'a>, _loader: &mut ClassLoader) -> ClassResult<Self> {
+impl<'a> ClassLoadable<'a, (usize, *mut Class)> for Method {
+    fn load(root: (usize, *mut Class), reader: &mut Reader<'a>, loader: &mut ClassLoader) -> ClassResult<Self> {
+        let (code_size, class) = root;
+        let class_file = unsafe { (*class).class_file() };
+        let const_pool = &class_file.const_pool;
         let name = reader.read::<u16>().ok_or(ClassError::BadConstIndex)?;
+        let name = loader.intern_const(const_pool, name as usize)?;
+        if class_file.version < DESCRIPTORS_VERSION {
+            return load_method_v1(name, code_size, class, reader, loader)
+        }
+
+        let descriptor = reader.read::<u16>().ok_or(ClassError::BadConstIndex)?;
+        let key = MethodKey { name, descriptor: loader.intern_const(const_pool, descriptor as usize)? };
         let access = reader.read::<u8>().ok_or(ClassError::BadAccessModifier)?;
-        let (type_size, _) = TypeSize::extract(reader.read::<u8>().ok_or(ClassError::BadCodePos)?);
+        let is_interface = unsafe { (*class).is_interface() };
+        let access = match class_file.version >= ACCESS_VERSION {
+            true => check_access(access, if is_interface { ACCESS_PUBLIC } else { METHOD_ACCESS })?,
+            false => access & ACCESS_NATIVE | ACCESS_PUBLIC,
+        };
+
+        // struct methods only took the instance once there were interfaces to call them through
+        let access = match class_file.version < INTERFACES_VERSION && unsafe { (*class).is_struct() } {
+            true => access | ACCESS_STATIC,
+            false => access,
+        };
+
+        // methods of structs and interfaces that aren't static take the instance as a hidden first argument
+        let takes_instance = access & ACCESS_STATIC == 0 && (is_interface || unsafe { (*class).is_struct() });
+        if takes_instance && access & ACCESS_NATIVE != 0 {
+            return Err(ClassError::BadAccessModifier)
+        }
+
+        let num_params = check_descriptor(key.descriptor.as_str(), class_file.type_params)? + takes_instance as usize;
+        let num_params = if num_params <= u8::max_value() as usize { num_params as u8 } else {
+            return Err(ClassError::BadDescriptor)
+        };
+
+        // interface methods have nothing but a name and descriptor for impls to implement
+        if is_interface {
+            return Ok(Method {
+                key,
+                access,
+                num_params,
+                max_locals: num_params as u16,
+                max_stack: 0,
+                code_pos: 0,
+                slot: 0,
+                next_method: 0,
+                class,
+                native: None,
+                debug: None,
+                exceptions: None,
+            })
+        }
+
+        // native methods take their signature from the descriptor and are bound at load
+        if access & ACCESS_NATIVE != 0 {
+            let signature = NativeSignature::from_descriptor(key.descriptor.as_str())?;
+            let target = loader.bind_native(class_file.name.as_str(), key.name.as_str(), &signature)?;
+
+            return Ok(Method {
+                key,
+                access,
+                num_params,
+                max_locals: num_params as u16,
+                max_stack: 0,
+                code_pos: 0,
+                slot: 0,
+                next_method: 0,
+                class,
+                native: Some(loader.alloc(Native { target, signature })?),
+                debug: None,
+                exceptions: None,
+            })
+        }
+
+        // the arguments are the first locals of the frame
+        let max_locals = reader.read::<u16>().ok_or(ClassError::BadMethodSize)?;
+        let max_stack = reader.read::<u16>().ok_or(ClassError::BadMethodSize)?;
+        if (max_locals as usize) < num_params as usize {
+            return Err(ClassError::BadDescriptor)
+        }
 
         // read code pos and check if in the code range
+        let (type_size, _) = TypeSize::extract(reader.read::<u8>().ok_or(ClassError::BadCodePos)?);
         let type_size = type_size.ok_or(ClassError::BadCodePos)?;
         let code_pos = match read_const_num(type_size, reader)? {
-            Const::UInt(code_pos) if code_pos as usize <= code_size => code_pos,
+            Const::UInt(code_pos) if (code_pos as usize) < code_size => code_pos,
             _ => return Err(ClassError::BadCodePos)
         };
 
         Ok(Method {
-            name,
+            key,
             access,
+            num_params,
+            max_locals,
+            max_stack,
             code_pos,
+            slot: 0,
+            next_method: 0,
+            class,
+            native: None,
+            debug: None,
+            exceptions: None,
+        })
+    }
+}
+
+/// Methods before 2.0 are a name and access byte, then for natives a signature and for the
+/// others their code pos. Having no descriptor, they're keyed by an empty one, and as they
+/// don't declare their parameters, invokes pass them whatever number of arguments they like.
+fn load_method_v1<'a>(
+    name: Symbol,
+    code_size: usize,
+    class: *mut Class,
+    reader: &mut Reader<'a>,
+    loader: &mut ClassLoader,
+) -> ClassResult<Method> {
+    let class_name = unsafe { (*class).class_file().name.as_str() };
+    let access = reader.read::<u8>().ok_or(ClassError::BadAccessModifier)?;
+    let access = access & ACCESS_NATIVE | ACCESS_PUBLIC | ACCESS_STATIC;
+
+    // natives get the descriptor their signature would have had
+    if access & ACCESS_NATIVE != 0 {
+        let signature = read_native_signature(reader)?;
+        let descriptor = loader.alloc_str(&[signature.descriptor(&mut [0; MAX_NATIVE_ARGS + 3]).as_bytes()])?;
+        let target = loader.bind_native(class_name, name.as_str(), &signature)?;
+
+        return Ok(Method {
+            key: MethodKey { name, descriptor: loader.intern(descriptor)? },
+            access,
+            num_params: signature.num_args as u8,
+            max_locals: signature.num_args as u16,
+            max_stack: 0,
+            code_pos: 0,
+            slot: 0,
             next_method: 0,
-            class: null_mut(),
+            class,
+            native: Some(loader.alloc(Native { target, signature })?),
+            debug: None,
+            exceptions: None,
         })
     }
+
+    let (type_size, _) = TypeSize::extract(reader.read::<u8>().ok_or(ClassError::BadCodePos)?);
+    let type_size = type_size.ok_or(ClassError::BadCodePos)?;
+    let code_pos = match read_const_num(type_size, reader)? {
+        Const::UInt(code_pos) if (code_pos as usize) < code_size => code_pos,
+        _ => return Err(ClassError::BadCodePos)
+    };
+
+    // with no sizes to check handlers against, any stack depth is allowed
+    Ok(Method {
+        key: MethodKey { name, descriptor: loader.intern("")? },
+        access,
+        num_params: 0,
+        max_locals: 0,
+        max_stack: u16::max_value(),
+        code_pos,
+        slot: 0,
+        next_method: 0,
+        class,
+        native: None,
+        debug: None,
+        exceptions: None,
+    })
+}
+
+/// Native signatures before 2.0 are a u8 number of arguments, then a type byte per
+/// argument and one for the return type, each a `TypeSize` above its kind
+fn read_native_signature<'a>(reader: &mut Reader<'a>) -> ClassResult<NativeSignature> {
+    let num_args = reader.read::<u8>().ok_or(ClassError::BadNativeType)? as usize;
+    if num_args > MAX_NATIVE_ARGS {
+        return Err(ClassError::BadNativeType)
+    }
+
+    let mut read_type = || match TypeSize::extract(reader.read::<u8>().ok_or(ClassError::BadNativeType)?) {
+        (Some(type_size), NATIVE_KIND_NUM) => Ok(NativeType::Num(type_size)),
+        (_, NATIVE_KIND_STR) => Ok(NativeType::Str),
+        (_, NATIVE_KIND_VOID) => Ok(NativeType::Void),
+        _ => Err(ClassError::BadNativeType),
+    };
+
+    let mut args = [NativeType::Void; MAX_NATIVE_ARGS];
+    for arg in args.iter_mut().take(num_args) {
+        *arg = match read_type()? {
+            NativeType::Void => return Err(ClassError::BadNativeType),
+            arg_type => arg_type,
+        };
+    }
+    Ok(NativeSignature::new(&args[..num_args], read_type()?))
+}
+
+/// Operand for instructions that refer to the class's const pool or methods by index
+fn resolve_operand(
+    opcode: Opcode,
+    index: u16,
+    const_pool: &ConstPool,
+    methods: Option<&Mapping<MethodKey, Method, NameHasher>>,
+    loader: &ClassLoader,
+) -> ClassResult<u64> {
+    match opcode {
+        Opcode::Str => {
+            let string = const_pool.get_str(index as usize).ok_or(ClassError::BadConstIndex)?;
+            Ok(string.as_ptr() as u64)
+        },
+        Opcode::Native => {
+            let name = const_pool.get_str(index as usize).ok_or(ClassError::BadConstIndex)?;
+            let method = method_named(methods, loader.symbol(name)).ok_or(ClassError::UnboundNative)?;
+            let native = method.native.ok_or(ClassError::UnboundNative)?;
+            Ok(native as u64 | (unsafe { (*native).signature.num_args as u64 } << NARGS_SHIFT))
+        },
+        _ => Err(ClassError::BadOpcode)
+    }
+}
+
+/// The method called `name`, which mustn't be overloaded where methods are named without
+/// descriptors, like by the `Native` instruction and 1.x sections
+fn method_named<'m>(methods: Option<&'m Mapping<MethodKey, Method, NameHasher>>, name: Option<Symbol>) -> Option<&'m Method> {
+    let name = name?;
+    let mut named = methods?.iter().filter(|method| method.key.name == name);
+    match (named.next(), named.next()) {
+        (Some(method), None) => Some(method),
+        _ => None,
+    }
+}
+
+/// Read the const indices of the name and descriptor of a method an optional section refers to,
+/// or in 1.x files only its name
+pub fn read_section_method<'m>(
+    reader: &mut Reader,
+    methods: Option<&'m Mapping<MethodKey, Method, NameHasher>>,
+    class_file: &ClassFile,
+    loader: &ClassLoader,
+) -> ClassResult<&'m mut Method> {
+    let name = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+    let key = match class_file.version >= DESCRIPTORS_VERSION {
+        true => {
+            let descriptor = reader.read::<u16>().ok_or(ClassError::BadSection)?;
+            loader.method_key(&class_file.const_pool, name, descriptor)?
+        },
+        false => {
+            let name = class_file.const_pool.get_str(name as usize).ok_or(ClassError::BadConstIndex)?;
+            method_named(methods, loader.symbol(name)).map(|method| method.key)
+        },
+    };
+    methods.and_then(|methods| methods.find(&key?)).ok_or(ClassError::BadSection)
+}
+
+/// Check the string const at `index` is a type fields of the class can have
+fn check_field_type(class_file: &ClassFile, index: u16) -> ClassResult<()> {
+    let field_type = class_file.const_pool.get_str(index as usize).ok_or(ClassError::BadConstIndex)?;
+    check_type(field_type, class_file.type_params)
 }
 
 fn read_const_num<'a>(type_size: TypeSize, reader: &mut Reader<'a>) -> ClassResult<Const> {
@@ -177,7 +642,7 @@ fn load_mapped<'a, Root, Size, Key, Value>(
     error: ClassError,
     reader: &mut Reader<'a>,
     loader: &mut ClassLoader,
-) -> ClassResult<Option<Mapping<Key, Value>>> where
+) -> ClassResult<Option<Mapping<Key, Value, NameHasher>>> where
     Root: Copy + Clone,
     Size: Sized, usize: From<Size>,
     Key: ?Sized + PartialEq + Hash32,
@@ -186,11 +651,11 @@ fn load_mapped<'a, Root, Size, Key, Value>(
     match usize::from(reader.read::<Size>().ok_or(error)?) {
         0 => Ok(None),
         num_items => {
-            let mut mapping = Mapping::from(&mut loader.memory, num_items).ok_or(ClassError::OutOfMemory)?;
+            let mut mapping = loader.alloc_mapping(num_items)?;
             for _ in 0..num_items {
                 let item = Value::load(root, reader, loader)?;
                 let item = loader.alloc(item)?;
-                mapping.insert(item).ok_or(ClassError::OutOfMemory)?;
+                mapping.insert(item).map_err(|error| error.into_error(ClassError::DuplicateMember))?;
             }
             Ok(Some(mapping))
         }
@@ -1,9 +1,9 @@
-use super::shared::unlikely;
+use super::{ClassError, Hash32, Hasher32, Fnv, shared::unlikely};
 use super::shared::mem::MemoryRange;
 
-pub trait Hash32 {
-    fn hash32(&self) -> u32;
-}
+/// Mappings hold at most this many items per 8 slots, keeping probe sequences short
+pub const MAX_LOAD: usize = 7;
+pub const MIN_CAPACITY: usize = 2;
 
 pub trait Mappable<K: PartialEq + Hash32 + ?Sized>: Sized {
     fn id(&self) -> &K;
@@ -11,109 +11,242 @@ pub trait Mappable<K: PartialEq + Hash32 + ?Sized>: Sized {
     fn next_mut(&mut self) -> &mut usize;
 }
 
-pub struct MappingIter<'a, K, V> where K: PartialEq + Hash32 + ?Sized, V: Mappable<K> {
+/// Why `insert` didn't add an item
+pub enum InsertError<V> {
+    Full,
+    Duplicate(*mut V),
+}
+
+impl<V> InsertError<V> {
+    /// The loading error for a failed insert: out of memory, or `duplicate` if the key was taken
+    #[inline]
+    pub fn into_error(self, duplicate: ClassError) -> ClassError {
+        match self {
+            InsertError::Full => ClassError::OutOfMemory,
+            InsertError::Duplicate(_) => duplicate,
+        }
+    }
+}
+
+pub struct MappingIter<'a, K, V, H> where K: PartialEq + Hash32 + ?Sized, V: Mappable<K>, H: Hasher32 {
     pos: usize,
-    mapping: &'a Mapping<K, V>,
+    mapping: &'a Mapping<K, V, H>,
 }
 
-pub struct Mapping<K, V> where K: PartialEq + Hash32 + ?Sized, V: Mappable<K> {
+/// Items keyed by `K`, placed by the hashes of `H`. The default FNV is fastest, but
+/// mappings keyed by untrusted data should use a seeded hasher (see `NameHasher`).
+pub struct Mapping<K, V, H = Fnv> where K: PartialEq + Hash32 + ?Sized, V: Mappable<K>, H: Hasher32 {
     size: usize,
     capacity: usize,
     items: *mut *mut V,
+    hasher: H,
     phantom: core::marker::PhantomData<*mut K>,
 }
 
-impl<'a, K, V> Iterator for MappingIter<'a, K, V> 
-    where K: PartialEq + Hash32 + ?Sized, V: Mappable<K>
+impl<'a, K, V, H> Iterator for MappingIter<'a, K, V, H>
+    where K: PartialEq + Hash32 + ?Sized, V: Mappable<K>, H: Hasher32
 {
     type Item = &'a V;
 
     fn next(&mut self) -> Option<Self::Item> {
         unsafe {
-            let item = self.mapping.items.offset(self.pos as isize);
-            if self.pos >= self.mapping.capacity {
-                None
-            } else if !(*item).is_null() {
-                Some(& **item)
-            } else {
-                self.next()
+            while self.pos < self.mapping.capacity {
+                let item = *self.mapping.items.add(self.pos);
+                self.pos += 1;
+                if !item.is_null() {
+                    return Some(&*item)
+                }
             }
+            None
         }
     }
 }
 
-impl<K, V> Mapping<K, V> where K: PartialEq + Hash32 + ?Sized, V: Mappable<K> {
-    pub fn from(allocator: &mut MemoryRange, capacity: usize) -> Option<Self> {
-        allocator.alloc_many(capacity).and_then(|items| Some(Self {
-            size: 0,
-            items: items,
-            capacity: capacity,
-            phantom: core::marker::PhantomData,
-        }))
+impl<K, V, H> Mapping<K, V, H> where K: PartialEq + Hash32 + ?Sized, V: Mappable<K>, H: Hasher32 + Default {
+    #[inline]
+    pub fn from(allocator: &mut MemoryRange, num_items: usize) -> Option<Self> {
+        Self::with_hasher(allocator, num_items, H::default())
+    }
+}
+
+impl<K, V, H> Mapping<K, V, H> where K: PartialEq + Hash32 + ?Sized, V: Mappable<K>, H: Hasher32 {
+    /// A mapping with room for `num_items` before it's full. The slots are a power of two
+    /// kept below the load limit, so lookups can mask hashes instead of dividing them.
+    pub fn with_hasher(allocator: &mut MemoryRange, num_items: usize, hasher: H) -> Option<Self> {
+        let capacity = (num_items * 8 + MAX_LOAD - 1) / MAX_LOAD;
+        Self::with_slots(allocator, capacity.max(MIN_CAPACITY).next_power_of_two(), hasher)
+    }
+
+    fn with_slots(allocator: &mut MemoryRange, capacity: usize, hasher: H) -> Option<Self> {
+        allocator.alloc_many::<*mut V>(capacity).and_then(|items| unsafe {
+            // memory given back to the allocator is reused as is, so slots are cleared here
+            core::ptr::write_bytes(items, 0, capacity);
+            Some(Self {
+                size: 0,
+                items: items,
+                capacity: capacity,
+                hasher: hasher,
+                phantom: core::marker::PhantomData,
+            })
+        })
     }
 
     #[inline]
-    pub fn iter<'a>(&'a self) -> MappingIter<'a, K, V> {
+    pub fn iter<'a>(&'a self) -> MappingIter<'a, K, V, H> {
         MappingIter {
             pos: 0,
             mapping: self,
         }
     }
 
-    pub unsafe fn expand(&mut self, maximum: usize) -> bool {
-        let capacity = (self.capacity << 1).min(maximum);
-        if capacity >= self.capacity {
-            self.capacity = capacity;
+    /// Rehash every item into a table of twice the capacity allocated from `allocator`.
+    /// If the current table was the last thing allocated from it, the new table takes
+    /// its place and the rest is given back, otherwise the current table is abandoned.
+    pub fn expand(&mut self, allocator: &mut MemoryRange) -> bool {
+        let mut expanded = match Self::with_slots(allocator, self.capacity << 1, self.hasher) {
+            Some(expanded) => expanded,
+            None => return false,
+        };
+
+        unsafe {
+            for index in 0..self.capacity {
+                let item = *self.items.add(index);
+                if !item.is_null() {
+                    expanded.place(item);
+                }
+            }
+
+            let expanded_end = expanded.items.add(expanded.capacity);
+            if self.items.add(self.capacity) == expanded.items && expanded_end as *mut u8 == allocator.top_ptr() {
+                core::ptr::copy(expanded.items, self.items, expanded.capacity);
+                allocator.release_from(self.items.add(expanded.capacity) as *mut u8);
+                expanded.items = self.items;
+            }
         }
-        capacity >= self.capacity
+
+        *self = expanded;
+        true
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.size
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    /// How many items fit before `insert` reports the mapping full
+    #[inline]
+    pub fn capacity(&self) -> usize {
+        self.capacity * MAX_LOAD / 8
     }
 
     pub fn find(&self, key: &K) -> Option<&mut V> {
+        self.position(key).map(|index| unsafe { &mut **self.items.add(index) })
+    }
+
+    /// Add an item whose key isn't in the mapping yet
+    pub fn insert(&mut self, item: *mut V) -> Result<(), InsertError<V>> {
         unsafe {
-            if unlikely(self.size == 0) {
-                return None
-            } else {
-                let mask = self.capacity - 1;
-                let start = key.hash32() as usize & mask;
-                let mut index = start;
-
-                let mut slot = self.items.offset(index as isize);
-                while !(*slot).is_null() && !(**slot).id().eq(key) {
-                    index = (index + 1) & mask;
-                    if index == start {
-                        return None
-                    } else {
-                        slot = self.items.offset(index as isize);
-                    }
+            match self.position((*item).id()) {
+                Some(index) => Err(InsertError::Duplicate(*self.items.add(index))),
+                None if unlikely(self.size >= self.capacity()) => Err(InsertError::Full),
+                None => Ok(self.place(item)),
+            }
+        }
+    }
+
+    /// Add an item, replacing and returning the one with the same key if there is one
+    pub fn upsert(&mut self, item: *mut V) -> Result<Option<*mut V>, InsertError<V>> {
+        unsafe {
+            match self.position((*item).id()) {
+                Some(index) => {
+                    let slot = self.items.add(index);
+                    let replaced = *slot;
+                    *(*item).next_mut() = (*replaced).next();
+                    *(*replaced).next_mut() = 0;
+                    *slot = item;
+                    Ok(Some(replaced))
+                },
+                None if unlikely(self.size >= self.capacity()) => Err(InsertError::Full),
+                None => {
+                    self.place(item);
+                    Ok(None)
+                },
+            }
+        }
+    }
+
+    /// Take the item with `key` out of the mapping, shifting the items probed
+    /// past it back a slot so that no lookup stops early on the hole it left
+    pub fn remove(&mut self, key: &K) -> Option<*mut V> {
+        unsafe {
+            let mask = self.capacity - 1;
+            let mut index = self.position(key)?;
+            let removed = *self.items.add(index);
+
+            loop {
+                let next = (index + 1) & mask;
+                let item = *self.items.add(next);
+                if item.is_null() || (*item).next() == 0 {
+                    *self.items.add(index) = core::ptr::null_mut();
+                    break
                 }
 
-                Some(&mut **slot)
+                *(*item).next_mut() -= 1;
+                *self.items.add(index) = item;
+                index = next;
             }
+
+            self.size -= 1;
+            *(*removed).next_mut() = 0;
+            Some(removed)
         }
     }
 
-    pub fn insert(&mut self, mut item: *mut V) -> Option<()> {
+    /// Index of the slot holding `key`. Items are kept no further from their home slot
+    /// than the ones after them (Robin Hood), so the probe stops at the first item that's
+    /// closer to home than `key` would be.
+    fn position(&self, key: &K) -> Option<usize> {
         unsafe {
-            if unlikely(self.size == self.capacity) {
-                None
-            } else {
-                self.size += 1;
-                let mask = self.capacity - 1;
-                let mut index = (*item).id().hash32() as usize & mask;
-
-                for _ in 0..self.capacity {
-                    let slot = self.items.offset(index as isize);
-                    if (*slot).is_null() {
-                        return Some(*slot = item);
-                    } else if (**slot).next() < (*item).next() {
-                        core::mem::swap(&mut *slot, &mut item);
-                    }
-                    *(*item).next_mut() += 1;
-                    index = (index + 1) & mask;
+            if unlikely(self.size == 0) {
+                return None
+            }
+
+            let mask = self.capacity - 1;
+            let mut index = key.hash32(&self.hasher) as usize & mask;
+            for distance in 0..self.capacity {
+                let item = *self.items.add(index);
+                if item.is_null() || (*item).next() < distance {
+                    return None
+                } else if (*item).id().eq(key) {
+                    return Some(index)
                 }
+                index = (index + 1) & mask;
+            }
+            None
+        }
+    }
+
+    /// Put an item in its Robin Hood slot, the mapping having room for it and not its key
+    unsafe fn place(&mut self, mut item: *mut V) {
+        self.size += 1;
+        let mask = self.capacity - 1;
+        let mut index = (*item).id().hash32(&self.hasher) as usize & mask;
+        *(*item).next_mut() = 0;
 
-                None
+        loop {
+            let slot = self.items.add(index);
+            if (*slot).is_null() {
+                return *slot = item
+            } else if (**slot).next() < (*item).next() {
+                core::mem::swap(&mut *slot, &mut item);
             }
+            *(*item).next_mut() += 1;
+            index = (index + 1) & mask;
         }
     }
-}
\ No newline at end of file
+}
@@ -3,8 +3,12 @@ pub mod reader;
 #[allow(dead_code)]
 pub mod loader;
 #[allow(dead_code)]
+pub mod hash;
+#[allow(dead_code)]
 pub mod mapping;
 #[allow(dead_code)]
+pub mod symbol;
+#[allow(dead_code)]
 pub mod opcodes;
 #[allow(dead_code)]
 pub mod class_load;
@@ -13,39 +17,90 @@ pub mod class_file;
 #[allow(dead_code)]
 pub mod const_pool;
 #[allow(dead_code)]
+pub mod native;
+#[allow(dead_code)]
+pub mod descriptor;
+#[allow(dead_code)]
 pub mod interpreter;
+#[allow(dead_code)]
+pub mod link;
+#[allow(dead_code)]
+pub mod archive;
+#[allow(dead_code)]
+pub mod debug;
+#[allow(dead_code)]
+pub mod exception;
+#[allow(dead_code)]
+pub mod interface;
 
 pub use super::*;
 
 pub use self::reader::*;
 pub use self::loader::*;
+pub use self::hash::*;
 pub use self::mapping::*;
+pub use self::symbol::*;
 pub use self::opcodes::*;
 pub use self::class_load::*;
 pub use self::class_file::*;
 pub use self::const_pool::*;
+pub use self::native::*;
+pub use self::descriptor::*;
+pub use self::interpreter::*;
+pub use self::link::*;
+pub use self::archive::*;
+pub use self::debug::*;
+pub use self::exception::*;
+pub use self::interface::*;
 
 pub type ClassResult<T> = Result<T, ClassError>;
 
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum ClassError {
     OutOfMemory,
 
+    ClassNotFound,
+    ClassUnreadable,
+    ArchiveNotFound,
+    BadArchive,
+    BadCompressedData,
+
     BadClassType,
     BadClassName,
     BadClassMagic,
+    BadClassVersion,
+    UnsupportedClassVersion,
+    BadSection,
     BadAccessModifier,
 
     BadCodePos,
     BadCodeSize,
     BadCodeData,
+    BadOpcode,
 
     BadEnumSize,
     BadEnumField,
     BadFieldSize,
     BadMethodSize,
+    BadDescriptor,
+    BadTypeParams,
+    DuplicateMember,
 
     BadConstSize,
     BadConstType,
     BadConstData,
     BadConstIndex,
+
+    BadNativeType,
+    UnboundNative,
+    LibraryNotFound,
+
+    UnresolvedSymbol,
+    AmbiguousSymbol,
+    IncompatibleSymbol,
+    InaccessibleSymbol,
+    InaccessibleClass,
+    ImmutableSymbol,
+    LinkFailed,
+    DuplicateClass,
 }
\ No newline at end of file
@@ -0,0 +1,282 @@
+use super::{TypeSize, str_header};
+use core::str::from_utf8_unchecked;
+use core::slice::from_raw_parts;
+
+pub const MAX_NATIVE_ARGS: usize = 8;
+// argument registers of the System V and AAPCS64 conventions, the only ones `call_symbol` supports
+const MAX_INT_REGISTERS: usize = 6;
+const MAX_FLOAT_REGISTERS: usize = 8;
+
+/// Rust function registered on the `ClassLoader` to back a native method
+pub type NativeFn = fn(&NativeArgs) -> NativeValue<'static>;
+
+type IntSymbol = unsafe extern "C" fn(u64, u64, u64, u64, u64, u64,
+    f64, f64, f64, f64, f64, f64, f64, f64) -> u64;
+type FloatSymbol = unsafe extern "C" fn(u64, u64, u64, u64, u64, u64,
+    f64, f64, f64, f64, f64, f64, f64, f64) -> f64;
+
+#[derive(Copy, Clone, PartialEq)]
+pub enum NativeType {
+    Num(TypeSize),
+    Str,
+    Void,
+}
+
+#[derive(Copy, Clone)]
+pub struct NativeSignature {
+    pub num_args: usize,
+    pub args: [NativeType; MAX_NATIVE_ARGS],
+    pub returns: NativeType,
+}
+
+#[derive(Copy, Clone)]
+pub enum NativeTarget {
+    Rust(NativeFn),
+    Symbol(*const u8),
+}
+
+pub struct Native {
+    pub target: NativeTarget,
+    pub signature: NativeSignature,
+}
+
+/// Values exchanged with Rust natives. A returned `Str` must already be laid out as
+/// a GLR string (see `str_from_slot`), such as one the native received as an argument.
+pub enum NativeValue<'a> {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(&'a str),
+    Void,
+}
+
+pub struct NativeArgs<'a> {
+    slots: &'a [u64],
+    signature: &'a NativeSignature,
+}
+
+impl NativeType {
+    #[inline]
+    fn is_float(self) -> bool {
+        match self {
+            NativeType::Num(TypeSize::F32) |
+            NativeType::Num(TypeSize::F64) => true,
+            _ => false
+        }
+    }
+}
+
+impl NativeSignature {
+    /// Signature for natives declared by the host or by a 1.x class file
+    pub fn new(args: &[NativeType], returns: NativeType) -> Self {
+        let num_args = args.len().min(MAX_NATIVE_ARGS);
+        let mut signature = NativeSignature { num_args, args: [NativeType::Void; MAX_NATIVE_ARGS], returns };
+        signature.args[..num_args].copy_from_slice(&args[..num_args]);
+        signature
+    }
+
+    #[inline]
+    pub fn args(&self) -> &[NativeType] {
+        &self.args[..self.num_args]
+    }
+
+    /// Whether a C symbol with this signature can be called through `call_symbol`:
+    /// arguments must fit in registers and strings can't be returned yet.
+    pub fn is_callable_symbol(&self) -> bool {
+        let floats = self.args().iter().filter(|arg| arg.is_float()).count();
+        self.returns != NativeType::Str
+            && floats <= MAX_FLOAT_REGISTERS
+            && self.num_args - floats <= MAX_INT_REGISTERS
+    }
+}
+
+impl<'a> NativeArgs<'a> {
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.slots.len()
+    }
+
+    /// The argument at `index` converted from its stack slot according to the declared signature
+    pub fn get(&self, index: usize) -> NativeValue<'a> {
+        match (self.slots.get(index), self.signature.args().get(index)) {
+            (Some(&slot), Some(&NativeType::Num(type_size))) => from_slot(slot, type_size),
+            (Some(&slot), Some(&NativeType::Str)) => NativeValue::Str(unsafe { str_from_slot(slot) }),
+            _ => NativeValue::Void,
+        }
+    }
+
+    #[inline]
+    pub fn int(&self, index: usize) -> i64 {
+        match self.get(index) {
+            NativeValue::Int(value) => value,
+            NativeValue::UInt(value) => value as i64,
+            NativeValue::Float(value) => value as i64,
+            _ => 0
+        }
+    }
+
+    #[inline]
+    pub fn uint(&self, index: usize) -> u64 {
+        self.int(index) as u64
+    }
+
+    #[inline]
+    pub fn float(&self, index: usize) -> f64 {
+        match self.get(index) {
+            NativeValue::Int(value) => value as f64,
+            NativeValue::UInt(value) => value as f64,
+            NativeValue::Float(value) => value,
+            _ => 0.0
+        }
+    }
+
+    #[inline]
+    pub fn str(&self, index: usize) -> &'a str {
+        match self.get(index) {
+            NativeValue::Str(value) => value,
+            _ => ""
+        }
+    }
+}
+
+impl<'a> NativeValue<'a> {
+    /// Convert the value into a stack slot holding the declared return type
+    fn into_slot(self, returns: NativeType) -> u64 {
+        let value = match self {
+            NativeValue::Int(value) => value as u64,
+            NativeValue::UInt(value) => value,
+            NativeValue::Float(value) => return match returns {
+                NativeType::Num(TypeSize::F32) |
+                NativeType::Num(TypeSize::F64) => value.to_bits(),
+                _ => value as i64 as u64,
+            },
+            NativeValue::Str(value) => value.as_ptr() as u64,
+            NativeValue::Void => 0,
+        };
+
+        match returns {
+            NativeType::Num(type_size) => narrow(value, type_size),
+            NativeType::Str => value,
+            NativeType::Void => 0,
+        }
+    }
+}
+
+/// Strings on the stack point at their NUL-terminated bytes, with the length stored in the 8 bytes
+/// before, after the header every string has like the heap's strings (see `KIND_STRING`)
+#[inline]
+pub unsafe fn str_from_slot<'a>(slot: u64) -> &'a str {
+    let bytes = slot as *const u8;
+    let size = *(bytes as *const u64).sub(1) as usize;
+    from_utf8_unchecked(from_raw_parts(bytes, size))
+}
+
+/// Number of u64 words needed to hold a string of `size` bytes in that layout
+#[inline]
+pub fn str_words(size: usize) -> usize {
+    2 + (size + 8) / 8
+}
+
+/// Lay out `parts` concatenated as a string in `header`, which must be `str_words` long
+pub unsafe fn write_str<'a>(header: *mut u64, parts: &[&[u8]]) -> &'a str {
+    let bytes = header.add(2) as *mut u8;
+    let size = parts.iter().fold(0, |size, part| {
+        core::ptr::copy_nonoverlapping(part.as_ptr(), bytes.add(size), part.len());
+        size + part.len()
+    });
+
+    *header = str_header(size);
+    *header.add(1) = size as u64;
+    *bytes.add(size) = 0;
+    from_utf8_unchecked(from_raw_parts(bytes, size))
+}
+
+/// Truncate and re-extend an integer slot to the range of `type_size`. Floats are
+/// always kept on the stack as the bits of an f64.
+#[inline]
+fn narrow(slot: u64, type_size: TypeSize) -> u64 {
+    match type_size {
+        TypeSize::U8 => slot as u8 as u64,
+        TypeSize::U16 => slot as u16 as u64,
+        TypeSize::U32 => slot as u32 as u64,
+        TypeSize::I32 => slot as i32 as i64 as u64,
+        TypeSize::U64 | TypeSize::I64 |
+        TypeSize::F32 | TypeSize::F64 => slot,
+    }
+}
+
+#[inline]
+fn from_slot<'a>(slot: u64, type_size: TypeSize) -> NativeValue<'a> {
+    match type_size {
+        TypeSize::I32 | TypeSize::I64 => NativeValue::Int(narrow(slot, type_size) as i64),
+        TypeSize::F32 | TypeSize::F64 => NativeValue::Float(f64::from_bits(slot)),
+        _ => NativeValue::UInt(narrow(slot, type_size)),
+    }
+}
+
+impl Native {
+    /// Invoke the native with its arguments taken from `slots`, returning the result as a stack slot
+    pub unsafe fn call(&self, slots: &[u64]) -> u64 {
+        match self.target {
+            NativeTarget::Rust(function) => {
+                let args = NativeArgs { slots, signature: &self.signature };
+                function(&args).into_slot(self.signature.returns)
+            },
+            NativeTarget::Symbol(symbol) => self.call_symbol(symbol, slots),
+        }
+    }
+
+    /// Call a C function by passing every integer register and every float register: both the
+    /// System V and AAPCS64 conventions assign integer and float arguments to their registers
+    /// independently, so the callee only reads the ones its real signature declares. Win64 shares
+    /// four registers between both kinds, which is why `find_symbol` finds nothing on windows.
+    unsafe fn call_symbol(&self, symbol: *const u8, slots: &[u64]) -> u64 {
+        let mut ints = [0u64; MAX_INT_REGISTERS];
+        let mut floats = [0f64; MAX_FLOAT_REGISTERS];
+        let (mut num_ints, mut num_floats) = (0, 0);
+
+        for (&slot, &arg_type) in slots.iter().zip(self.signature.args()) {
+            match arg_type {
+                NativeType::Num(TypeSize::F32) => {
+                    let value = f64::from_bits(slot) as f32;
+                    floats[num_floats] = f64::from_bits(value.to_bits() as u64);
+                    num_floats += 1;
+                },
+                NativeType::Num(TypeSize::F64) => {
+                    floats[num_floats] = f64::from_bits(slot);
+                    num_floats += 1;
+                },
+                NativeType::Num(type_size) => {
+                    ints[num_ints] = narrow(slot, type_size);
+                    num_ints += 1;
+                },
+                NativeType::Str | NativeType::Void => {
+                    ints[num_ints] = slot;
+                    num_ints += 1;
+                },
+            }
+        }
+
+        let [i0, i1, i2, i3, i4, i5] = ints;
+        let [f0, f1, f2, f3, f4, f5, f6, f7] = floats;
+        match self.signature.returns {
+            NativeType::Num(TypeSize::F32) => {
+                let function: FloatSymbol = core::mem::transmute(symbol);
+                let value = function(i0, i1, i2, i3, i4, i5, f0, f1, f2, f3, f4, f5, f6, f7);
+                (f32::from_bits(value.to_bits() as u32) as f64).to_bits()
+            },
+            NativeType::Num(TypeSize::F64) => {
+                let function: FloatSymbol = core::mem::transmute(symbol);
+                function(i0, i1, i2, i3, i4, i5, f0, f1, f2, f3, f4, f5, f6, f7).to_bits()
+            },
+            returns => {
+                let function: IntSymbol = core::mem::transmute(symbol);
+                let value = function(i0, i1, i2, i3, i4, i5, f0, f1, f2, f3, f4, f5, f6, f7);
+                match returns {
+                    NativeType::Num(type_size) => narrow(value, type_size),
+                    _ => 0,
+                }
+            },
+        }
+    }
+}
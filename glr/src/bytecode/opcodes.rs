@@ -1,7 +1,7 @@
 use self::TypeSize::*;
 
 #[repr(u8)]
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, PartialEq)]
 pub enum TypeSize {
     U8,
     U16,
@@ -34,4 +34,122 @@ impl TypeSize {
     pub fn extract(opcode: u8) -> (Option<Self>, u8) {
         (Self::from(opcode >> 5), opcode & 0b111)
     }
-}
\ No newline at end of file
+}
+
+#[repr(u8)]
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Opcode {
+    Halt,
+    Push,
+    Pop,
+    Dup,
+    Load,
+    Store,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Lt,
+    Eq,
+    Jmp,
+    Jz,
+    Call,
+    Enter,
+    Ret,
+    Str,
+    Native,
+    Nop,
+    Invoke,
+    GetStatic,
+    PutStatic,
+    Throw,
+    Rethrow,
+    New,
+    InvokeInterface,
+    Closure,
+    CallClosure,
+    Capture,
+    NewArray,
+    ArrayLen,
+    ArrayGet,
+    ArraySet,
+    ArrayPush,
+    ArrayConcat,
+    ArraySlice,
+    StrLen,
+    StrGet,
+    StrConcat,
+    StrSlice,
+}
+
+static OPCODES: [Opcode; 41] = [
+    Opcode::Halt,
+    Opcode::Push,
+    Opcode::Pop,
+    Opcode::Dup,
+    Opcode::Load,
+    Opcode::Store,
+    Opcode::Add,
+    Opcode::Sub,
+    Opcode::Mul,
+    Opcode::Div,
+    Opcode::Lt,
+    Opcode::Eq,
+    Opcode::Jmp,
+    Opcode::Jz,
+    Opcode::Call,
+    Opcode::Enter,
+    Opcode::Ret,
+    Opcode::Str,
+    Opcode::Native,
+    Opcode::Nop,
+    Opcode::Invoke,
+    Opcode::GetStatic,
+    Opcode::PutStatic,
+    Opcode::Throw,
+    Opcode::Rethrow,
+    Opcode::New,
+    Opcode::InvokeInterface,
+    Opcode::Closure,
+    Opcode::CallClosure,
+    Opcode::Capture,
+    Opcode::NewArray,
+    Opcode::ArrayLen,
+    Opcode::ArrayGet,
+    Opcode::ArraySet,
+    Opcode::ArrayPush,
+    Opcode::ArrayConcat,
+    Opcode::ArraySlice,
+    Opcode::StrLen,
+    Opcode::StrGet,
+    Opcode::StrConcat,
+    Opcode::StrSlice,
+];
+
+impl Opcode {
+    #[inline]
+    pub fn from(value: u8) -> Option<Self> {
+        try { OPCODES.get(value as usize)?.clone() }
+    }
+
+    /// Number of operand bytes following the opcode byte in the class file
+    #[inline]
+    pub fn operand_size(self) -> usize {
+        match self {
+            Opcode::Push => 8,
+            Opcode::CallClosure | Opcode::NewArray => 1,
+            Opcode::Load | Opcode::Store | Opcode::Enter | Opcode::Capture => 2,
+            Opcode::Str | Opcode::Native => 2,
+            Opcode::GetStatic | Opcode::PutStatic | Opcode::Throw | Opcode::New => 2,
+            Opcode::Invoke | Opcode::InvokeInterface | Opcode::Closure => 3,
+            Opcode::Jmp | Opcode::Jz => 4,
+            Opcode::Call => 5,
+            _ => 0,
+        }
+    }
+
+    #[inline]
+    pub fn len(self) -> usize {
+        1 + self.operand_size()
+    }
+}
@@ -18,7 +18,7 @@ impl<'a> Reader<'a> {
         })
     }
 
-    pub fn read_bytes(&mut self, bytes: usize) -> Option<&[u8]> {
+    pub fn read_bytes(&mut self, bytes: usize) -> Option<&'a [u8]> {
         if self.pos + bytes <= self.bytes.len() {
             let pos = self.pos;
             self.pos += bytes;
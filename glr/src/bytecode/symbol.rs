@@ -0,0 +1,83 @@
+use super::{ClassLoader, ClassError, ClassResult, ConstPool, Mappable, Hash32, Hasher32, InsertError};
+use core::fmt;
+
+/// A name interned by the loader. Every class, method and field name is interned once
+/// no matter how many classes share it, so symbols compare and hash as pointers.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub struct Symbol(*const SymbolEntry);
+
+pub struct SymbolEntry {
+    name: &'static str,
+    next_symbol: usize,
+}
+
+impl Mappable<str> for SymbolEntry {
+    fn id(&self) -> &str {
+        self.name
+    }
+
+    fn next(&self) -> usize {
+        self.next_symbol
+    }
+
+    fn next_mut(&mut self) -> &mut usize {
+        &mut self.next_symbol
+    }
+}
+
+impl Symbol {
+    #[inline]
+    pub fn as_str(self) -> &'static str {
+        unsafe { (*self.0).name }
+    }
+}
+
+impl Hash32 for Symbol {
+    #[inline]
+    fn hash32<H: Hasher32>(&self, hasher: &H) -> u32 {
+        hasher.hash_u64(self.0 as u64)
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:?}", self.as_str())
+    }
+}
+
+impl ClassLoader {
+    /// The symbol for `name` if it was interned, which it must have been to name anything loaded
+    #[inline]
+    pub fn symbol(&self, name: &str) -> Option<Symbol> {
+        self.symbols.find(name).map(|entry| Symbol(entry))
+    }
+
+    /// The symbol for `name`, interning it if it's new. `name` has to outlive the loader,
+    /// as it becomes the symbol's text.
+    pub fn intern(&mut self, name: &'static str) -> ClassResult<Symbol> {
+        if let Some(symbol) = self.symbol(name) {
+            return Ok(symbol)
+        }
+
+        let entry = self.alloc(SymbolEntry { name, next_symbol: 0 })?;
+        let inserted = match self.symbols.insert(entry) {
+            Err(InsertError::Full) if self.symbols.expand(&mut self.memory) => self.symbols.insert(entry),
+            inserted => inserted,
+        };
+        inserted.map(|_| Symbol(entry)).map_err(|error| error.into_error(ClassError::OutOfMemory))
+    }
+
+    /// Intern the string const at `index` of a loaded class's const pool
+    pub fn intern_const(&mut self, const_pool: &ConstPool, index: usize) -> ClassResult<Symbol> {
+        let name = const_pool.get_str(index).ok_or(ClassError::BadConstIndex)?;
+
+        // const pools live as long as the loader, as nothing it loads is freed
+        self.intern(unsafe { &*(name as *const str) })
+    }
+}
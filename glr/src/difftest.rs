@@ -0,0 +1,222 @@
+use crate::bytecode::{Backend, Mapping, Mappable, InsertError, Hasher32, Fnv, SipHasher};
+use crate::programs::{Machine, counter, fibonacci, arithmetic, signed, frames};
+#[cfg(not(windows))]
+use crate::programs::natives;
+use crate::programs::{run_classes, chain, fault, unwind, dispatch, closures, arrays, maps};
+use crate::programs::{refuse, entry_past_code, entry_in_operand};
+use crate::shared::mem::{MemoryRange, CLASS_MAPPING, CLASS_MEMORY};
+
+/// Keys of the mapping check, few enough that most operations find the key already there
+const MAP_KEYS: usize = 512;
+const MAP_OPERATIONS: usize = 20_000;
+
+/// How often the mapping check iterates the mapping and looks up every key
+const MAP_SWEEP: usize = 256;
+
+/// Run every sample program through both backends and report any program
+/// where they disagree with each other or with the expected result
+pub fn run() -> i32 {
+    // the mapping checks take memory the machine's loader maps later
+    let mappings: [(&str, fn(bool) -> Result<(), &'static str>); 3] = [
+        ("map fnv", |interleaved| check_mapping(Fnv, interleaved)),
+        ("map sip", |interleaved| check_mapping(SipHasher::new(0x0706050403020100, 0x0f0e0d0c0b0a0908), interleaved)),
+        ("map clus", |interleaved| check_mapping(Clustered, interleaved)),
+    ];
+
+    let status = mappings.iter().fold(0, |status, &(name, check)| {
+        match check(false).and_then(|_| check(true)) {
+            Ok(()) => {
+                println!("{:<8} ok", name);
+                status
+            },
+            Err(error) => {
+                println!("{:<8} FAILED: {}", name, error);
+                1
+            },
+        }
+    });
+
+    let mut machine = match Machine::new() {
+        Some(machine) => machine,
+        None => {
+            println!("difftest: failed to reserve vm memory");
+            return 1
+        }
+    };
+
+    let programs = [
+        counter(1000),
+        fibonacci(20),
+        arithmetic(1000),
+        signed(),
+        frames(),
+        #[cfg(not(windows))]
+        natives(),
+    ];
+
+    let status = programs.iter().fold(status, |status, program| {
+        let assembly = machine.run(program, Backend::Assembly);
+        let portable = machine.run(program, Backend::Portable);
+        compare(program.name, assembly, portable, program.expected, status)
+    });
+
+    // class programs map the memory the machine's loader and runtime had
+    drop(machine);
+    let class_programs = [
+        chain(),
+        fault(),
+        unwind(),
+        dispatch(),
+        closures(),
+        arrays(),
+        maps(),
+    ];
+
+    let status = class_programs.iter().fold(status, |status, program| {
+        let assembly = run_classes(program, Backend::Assembly);
+        let portable = run_classes(program, Backend::Portable);
+        compare(program.name, assembly, portable, program.expected, status)
+    });
+
+    // malformed classes must be refused before anything can jump into them
+    let bad_classes = [
+        entry_past_code(),
+        entry_in_operand(),
+    ];
+
+    bad_classes.iter().fold(status, |status, class| {
+        let assembly = refuse(class, Backend::Assembly);
+        let portable = refuse(class, Backend::Portable);
+        if assembly == portable && assembly == Some(class.error) {
+            println!("{:<8} ok", class.name);
+            status
+        } else {
+            println!("{:<8} FAILED: assembly = {:?}, portable = {:?}, expected = {:?}",
+                class.name, assembly, portable, class.error);
+            1
+        }
+    })
+}
+
+fn compare(name: &str, assembly: Option<u64>, portable: Option<u64>, expected: u64, status: i32) -> i32 {
+    if assembly == portable && assembly == Some(expected) {
+        println!("{:<8} ok", name);
+        status
+    } else {
+        println!("{:<8} FAILED: assembly = {:?}, portable = {:?}, expected = {}",
+            name, assembly, portable, expected);
+        1
+    }
+}
+
+struct Entry {
+    key: u64,
+    value: u64,
+    next: usize,
+}
+
+impl Mappable<u64> for Entry {
+    fn id(&self) -> &u64 {
+        &self.key
+    }
+
+    fn next(&self) -> usize {
+        self.next
+    }
+
+    fn next_mut(&mut self) -> &mut usize {
+        &mut self.next
+    }
+}
+
+/// Hashes every key to one of 8 slots, so probes run long and removals shift items far back
+#[derive(Copy, Clone)]
+struct Clustered;
+
+impl Hasher32 for Clustered {
+    fn hash_bytes(&self, bytes: &[u8]) -> u32 {
+        bytes[0] as u32 & 7
+    }
+}
+
+/// Insert, upsert, remove and look up keys picked with a fixed seed, checking the mapping against
+/// an array indexed by key after each operation, and its iteration and every lookup now and then.
+/// The mapping starts empty and grows as it fills. Its items are allocated after its table unless
+/// `interleaved`, so it grows in place, or between its tables, so it moves when it grows.
+fn check_mapping<H: Hasher32>(hasher: H, interleaved: bool) -> Result<(), &'static str> {
+    let mut tables = MemoryRange::at(CLASS_MAPPING).ok_or("failed to reserve memory")?;
+    let mut items = MemoryRange::at(CLASS_MEMORY).ok_or("failed to reserve memory")?;
+    let mut mapping = Mapping::<u64, Entry, H>::with_hasher(&mut tables, 0, hasher).ok_or("failed to allocate")?;
+    let mut reference = [None; MAP_KEYS];
+    let mut size = 0;
+    let mut random = 0x2545f4914f6cdd1du64;
+
+    for operation in 0..MAP_OPERATIONS {
+        random ^= random << 13;
+        random ^= random >> 7;
+        random ^= random << 17;
+        let key = random % MAP_KEYS as u64;
+        let value = random >> 32;
+        let allocator = if interleaved { &mut tables } else { &mut items };
+        let item = allocator.alloc(Entry { key, value, next: 0 }).ok_or("failed to allocate")?;
+        let expected = &mut reference[key as usize];
+
+        unsafe {
+            match random >> 61 {
+                0 | 1 => {
+                    let inserted = match mapping.insert(item) {
+                        Err(InsertError::Full) if mapping.expand(&mut tables) => mapping.insert(item),
+                        inserted => inserted,
+                    };
+                    match (inserted, *expected) {
+                        (Ok(()), None) => {
+                            *expected = Some(value);
+                            size += 1;
+                        },
+                        (Err(InsertError::Duplicate(found)), Some(value)) if (*found).value == value => {},
+                        _ => return Err("insert disagrees with the reference"),
+                    }
+                },
+                2 | 3 => {
+                    let upserted = match mapping.upsert(item) {
+                        Err(InsertError::Full) if mapping.expand(&mut tables) => mapping.upsert(item),
+                        upserted => upserted,
+                    };
+                    match (upserted, expected.replace(value)) {
+                        (Ok(None), None) => size += 1,
+                        (Ok(Some(replaced)), Some(value)) if (*replaced).value == value => {},
+                        _ => return Err("upsert disagrees with the reference"),
+                    }
+                },
+                4 | 5 => match (mapping.remove(&key), expected.take()) {
+                    (None, None) => {},
+                    (Some(removed), Some(value)) if (*removed).key == key && (*removed).value == value => size -= 1,
+                    _ => return Err("remove disagrees with the reference"),
+                },
+                _ => if mapping.find(&key).map(|item| item.value) != *expected {
+                    return Err("find disagrees with the reference")
+                },
+            }
+        }
+
+        if mapping.len() != size {
+            return Err("len disagrees with the reference")
+        } else if operation % MAP_SWEEP == MAP_SWEEP - 1 {
+            let mut seen = [false; MAP_KEYS];
+            for item in mapping.iter() {
+                let key = item.key as usize;
+                if seen[key] || reference[key] != Some(item.value) {
+                    return Err("iteration disagrees with the reference")
+                }
+                seen[key] = true;
+            }
+
+            if seen.iter().filter(|&&seen| seen).count() != size {
+                return Err("iteration missed items")
+            } else if (0..MAP_KEYS as u64).any(|key| mapping.find(&key).map(|item| item.value) != reference[key as usize]) {
+                return Err("find disagrees with the reference")
+            }
+        }
+    }
+    Ok(())
+}
@@ -20,16 +20,141 @@ pub mod shared;
 pub mod panic;
 #[allow(dead_code)]
 pub mod bytecode;
+#[allow(dead_code)]
+pub mod stdlib;
+#[cfg(any(feature = "bench", feature = "difftest"))]
+pub mod programs;
+#[cfg(feature = "bench")]
+pub mod bench;
+#[cfg(feature = "difftest")]
+pub mod difftest;
+
+use bytecode::{ClassLoader, ClassError, Runtime, Mappable, Backend, NameHasher, ARCHIVE_EXTENSION};
+use shared::{c_char, from_c_str};
+
+const USAGE: &'static str = "usage: glr [-cp <path>[:<path>...]] [-l <library>] [-hash <fnv | seeded>] <class | archive.glra>";
+const PATH_SEPARATOR: char = if cfg!(windows) { ';' } else { ':' };
 
 #[no_mangle]
-pub extern fn main(_argc: i32, _argv: *const *const u8) -> i32 {
-    #[cfg(not(target_arch = "x86_64"))]
-    compile_error!("GLR only supports x86_64");
+pub extern fn main(argc: i32, argv: *const *const c_char) -> i32 {
+    #[cfg(not(any(target_arch = "x86_64", target_arch = "aarch64")))]
+    compile_error!("GLR only supports x86_64 and aarch64");
     
     #[cfg(not(any(target_os = "linux", target_os = "windows")))]
     compile_error!("GLR only supports windows and linux");
 
-    let x = unsafe { bytecode::interpreter::interpret() };
-    println!("Hello world {}", x);
-    0
-}
\ No newline at end of file
+    #[cfg(all(target_arch = "aarch64", not(target_os = "linux")))]
+    compile_error!("GLR only supports aarch64 on linux");
+
+    #[cfg(feature = "bench")]
+    return bench::run();
+
+    #[cfg(feature = "difftest")]
+    return difftest::run();
+
+    #[cfg(not(any(feature = "bench", feature = "difftest")))]
+    unsafe { launch((1..argc as usize).map(|arg| from_c_str(*argv.add(arg)))) }
+}
+
+/// Resolve the class named on the command line through the class path and run its `main` method
+fn launch<'a>(mut args: impl Iterator<Item = &'a str> + Clone) -> i32 {
+    // names are hashed as classes load, so the hasher is picked before anything else
+    let mut options = args.clone();
+    let hasher = match options.position(|arg| arg == "-hash").map(|_| options.next()) {
+        None | Some(Some("fnv")) => NameHasher::Fnv,
+        Some(Some("seeded")) => NameHasher::seeded(),
+        Some(_) => {
+            println!("{}", USAGE);
+            return 1
+        }
+    };
+
+    let mut loader = match ClassLoader::with_hasher(Backend::default(), hasher) {
+        Ok(loader) => loader,
+        Err(error) => {
+            println!("error: failed to initialize the class loader: {:?}", error);
+            return 1
+        }
+    };
+
+    let mut class_name = None;
+    while let Some(arg) = args.next() {
+        let loaded = match (arg, args.next()) {
+            ("-cp", Some(paths)) => paths.split(PATH_SEPARATOR)
+                .try_for_each(|path| loader.add_class_path(path)),
+            ("-l", Some(library)) => loader.load_library(library),
+            ("-hash", Some(_)) => Ok(()),
+            (archive, None) if archive.ends_with(ARCHIVE_EXTENSION) => loader.add_class_path(archive),
+            (name, None) if !name.starts_with('-') => {
+                class_name = Some(name);
+                Ok(())
+            },
+            _ => {
+                println!("{}", USAGE);
+                return 1
+            }
+        };
+
+        if let Err(error) = loaded {
+            println!("error: {}: {:?}", arg, error);
+            return 1
+        }
+    }
+
+    // running an archive runs the entry class named in its manifest
+    let class_name = match class_name.or_else(|| loader.entry_class()) {
+        Some(class_name) => class_name,
+        None => {
+            println!("{}", USAGE);
+            return 1
+        }
+    };
+
+    if loader.class_path().is_empty() {
+        let _ = loader.add_class_path(".");
+    }
+
+    let class = match loader.resolve(class_name) {
+        Ok(class) => unsafe { &*class },
+        Err(error) => return report(&loader, class_name, error),
+    };
+
+    let class_file = class.class_file();
+    let method = loader.symbol("main").and_then(|main| class_file.methods_named(main).find(|method| method.num_params == 0 && !method.is_private()));
+    let result = match (method, Runtime::new()) {
+        (Some(method), Some(mut runtime)) => unsafe { runtime.invoke(&loader, class_file, method) },
+        (_, None) => {
+            println!("error: failed to reserve vm memory");
+            return 1
+        },
+        (None, _) => None,
+    };
+
+    match result {
+        Some(result) => result as i32,
+        None => {
+            println!("error: class {} has no main method", class.id());
+            1
+        }
+    }
+}
+
+fn report(loader: &ClassLoader, class_name: &str, error: ClassError) -> i32 {
+    match (error, loader.failed_symbol()) {
+        (ClassError::ClassNotFound, Some((missing, _))) => {
+            println!("error: class not found: {}", missing);
+            loader.searched_paths(missing, &mut |path| println!("  searched {}", path));
+        },
+        (ClassError::UnresolvedSymbol, Some((class, member))) |
+        (ClassError::AmbiguousSymbol, Some((class, member))) |
+        (ClassError::IncompatibleSymbol, Some((class, member))) |
+        (ClassError::InaccessibleSymbol, Some((class, member))) |
+        (ClassError::ImmutableSymbol, Some((class, member))) |
+        (ClassError::InaccessibleClass, Some((class, member))) => match member {
+            "" => println!("error: failed to link class {}: {:?} {}", class_name, error, class),
+            member => println!("error: failed to link class {}: {:?} {}.{}", class_name, error, class, member),
+        },
+        _ => println!("error: failed to load class {}: {:?}", class_name, error),
+    }
+    1
+}
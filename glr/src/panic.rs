@@ -5,7 +5,15 @@ pub extern "C" fn rust_eh_personality() {}
 #[no_mangle]
 pub extern "C" fn rust_eh_unwind_resume() {}
 
+use crate::bytecode::{print_stack_trace, ABORT_STATUS};
+use crate::shared::exit;
+
+/// Print the panic and, if it happened while running bytecode, the GLR stack trace
 #[panic_handler]
-fn panic_handler(_info: &core::panic::PanicInfo) -> ! {
-    unsafe { core::intrinsics::abort() }
+fn panic_handler(info: &core::panic::PanicInfo) -> ! {
+    println!("error: {}", info);
+    unsafe {
+        print_stack_trace();
+        exit(ABORT_STATUS)
+    }
 }
\ No newline at end of file
@@ -0,0 +1,910 @@
+use crate::bytecode::{Opcode, Backend, ClassError, ClassResult, ClassLoader, Class, Runtime, predecode, collect, NARGS_SHIFT};
+use crate::bytecode::{Native, NativeArgs, NativeValue, NativeTarget, NativeSignature, NativeType, TypeSize};
+use crate::bytecode::{ACCESS_PUBLIC, ACCESS_STATIC, ErrorHandler, EXCEPTIONS_SECTION, CATCH_ALL, IMPLS_SECTION};
+use crate::bytecode::{CLASS_VERSION, CLASS_TYPE_MODULE, CLASS_TYPE_STRUCT, CLASS_TYPE_INTERFACE, CONST_KIND_STR, CONST_KIND_CLASS, CONST_KIND_MEMBER, NO_DESCRIPTOR};
+#[cfg(not(windows))]
+use crate::shared::{c_char, dylib::find_symbol};
+
+const CODE_LIMIT: usize = 1024;
+const CLASS_LIMIT: usize = 4096;
+
+/// Stack slots every method of a sample class declares it needs
+const MAX_STACK: u16 = 64;
+
+/// Classes in the `chain` program
+const CHAIN_LENGTH: usize = 300;
+
+/// Closures in the list the `closures` program builds, and closures it drops after adding
+/// each, which come to more than the heap allocates between collections a few times over
+const LIST_LENGTH: i64 = 200;
+const GARBAGE_PER_NODE: i64 = 2000;
+
+/// Keys the `maps` program puts arrays under, and keys it puts in all. Growing the tables for
+/// the rest is all it allocates after the arrays, and comes to a few collections' worth.
+const MAP_ARRAYS: i64 = 1000;
+const MAP_SIZE: i64 = 1 << 18;
+
+/// Tiny bytecode emitter for writing the sample programs by hand
+pub struct Assembler {
+    len: usize,
+    code: [u8; CODE_LIMIT],
+}
+
+pub struct Program {
+    pub name: &'static str,
+    pub entry: u32,
+    pub expected: u64,
+    pub code: Assembler,
+}
+
+/// Tiny class file writer for the sample programs run through a class loader. The const pool,
+/// fields, methods and sections are kept apart until the class is loaded, as the file lists
+/// each after its count.
+pub struct ClassWriter {
+    class_type: u8,
+    num_consts: u16,
+    consts: Assembler,
+    num_fields: u16,
+    fields: Assembler,
+    num_methods: u16,
+    methods: Assembler,
+    num_sections: u16,
+    sections: Assembler,
+}
+
+/// A program of classes written and loaded by `load`, which returns the class
+/// whose `main` method runs it
+pub struct ClassProgram {
+    pub name: &'static str,
+    pub expected: u64,
+    pub load: fn(&mut ClassLoader) -> ClassResult<*mut Class>,
+}
+
+/// A class file the loader must refuse, written and loaded by `load`, and the error it's refused with
+pub struct BadClass {
+    pub name: &'static str,
+    pub error: ClassError,
+    pub load: fn(&mut ClassLoader) -> ClassResult<*mut Class>,
+}
+
+impl Assembler {
+    pub fn new() -> Self {
+        Self { len: 0, code: [0; CODE_LIMIT] }
+    }
+
+    pub fn pos(&self) -> u32 {
+        self.len as u32
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.code[..self.len]
+    }
+
+    pub fn emit(&mut self, bytes: &[u8]) -> &mut Self {
+        self.code[self.len..self.len + bytes.len()].copy_from_slice(bytes);
+        self.len += bytes.len();
+        self
+    }
+
+    pub fn op(&mut self, opcode: Opcode) -> &mut Self {
+        self.emit(&[opcode as u8])
+    }
+
+    pub fn push(&mut self, value: i64) -> &mut Self {
+        self.op(Opcode::Push).emit(&value.to_le_bytes())
+    }
+
+    pub fn local(&mut self, opcode: Opcode, index: u16) -> &mut Self {
+        self.op(opcode).emit(&index.to_le_bytes())
+    }
+
+    pub fn jump(&mut self, opcode: Opcode, target: u32) -> &mut Self {
+        self.op(opcode).emit(&target.to_le_bytes())
+    }
+
+    pub fn string(&mut self, index: u16) -> &mut Self {
+        self.op(Opcode::Str).emit(&index.to_le_bytes())
+    }
+
+    pub fn native(&mut self, index: u16) -> &mut Self {
+        self.op(Opcode::Native).emit(&index.to_le_bytes())
+    }
+
+    pub fn call(&mut self, target: u32, num_args: u8) -> &mut Self {
+        self.jump(Opcode::Call, target).emit(&[num_args])
+    }
+
+    /// Call the closure below the `num_args` values on top of the stack
+    pub fn call_closure(&mut self, num_args: u8) -> &mut Self {
+        self.emit(&[Opcode::CallClosure as u8, num_args])
+    }
+
+    /// Emit an instruction naming a const, like `Throw`, `New` or `GetStatic`
+    pub fn constant(&mut self, opcode: Opcode, index: u16) -> &mut Self {
+        self.local(opcode, index)
+    }
+
+    /// Emit an `Invoke` or another instruction naming a const and a number of values
+    pub fn invoke(&mut self, opcode: Opcode, index: u16, num_args: u8) -> &mut Self {
+        self.local(opcode, index).emit(&[num_args])
+    }
+
+    /// Rewrite the jump target of the instruction emitted at `at`
+    pub fn patch(&mut self, at: u32, target: u32) {
+        let at = at as usize + 1;
+        self.code[at..at + 4].copy_from_slice(&target.to_le_bytes());
+    }
+}
+
+impl ClassWriter {
+    /// A class of `class_type` named `name`, which is its first const
+    pub fn new(class_type: u8, name: &str) -> Self {
+        let mut class = Self {
+            class_type,
+            num_consts: 0,
+            consts: Assembler::new(),
+            num_fields: 0,
+            fields: Assembler::new(),
+            num_methods: 0,
+            methods: Assembler::new(),
+            num_sections: 0,
+            sections: Assembler::new(),
+        };
+        class.string(name);
+        class
+    }
+
+    pub fn string(&mut self, value: &str) -> u16 {
+        self.consts.emit(&[(TypeSize::U8 as u8) << 5 | CONST_KIND_STR, value.len() as u8]).emit(value.as_bytes());
+        self.add_const()
+    }
+
+    /// Add the string `name` and a class const naming it, returning the latter's index
+    pub fn class(&mut self, name: &str) -> u16 {
+        let name = self.string(name);
+        self.consts.emit(&[CONST_KIND_CLASS]).emit(&name.to_le_bytes());
+        self.add_const()
+    }
+
+    /// A member of the class const at `class`, naming a field or the only method called `name`
+    /// unless it has a `descriptor`
+    pub fn member(&mut self, class: u16, name: &str, descriptor: Option<&str>) -> u16 {
+        let name = self.string(name);
+        let descriptor = descriptor.map_or(NO_DESCRIPTOR, |descriptor| self.string(descriptor));
+        self.consts.emit(&[CONST_KIND_MEMBER]).emit(&class.to_le_bytes())
+            .emit(&name.to_le_bytes()).emit(&descriptor.to_le_bytes());
+        self.add_const()
+    }
+
+    /// A field of a module or, with its type, of a struct
+    pub fn field(&mut self, name: &str, access: u8, field_type: Option<&str>) -> &mut Self {
+        let name = self.string(name);
+        self.fields.emit(&name.to_le_bytes()).emit(&[access]);
+        if let Some(field_type) = field_type {
+            let field_type = self.string(field_type);
+            self.fields.emit(&field_type.to_le_bytes());
+        }
+        self.num_fields += 1;
+        self
+    }
+
+    /// A method whose code starts at `code_pos` of the code the class is loaded with
+    pub fn method(&mut self, name: &str, descriptor: &str, access: u8, max_locals: u16, code_pos: u32) -> &mut Self {
+        self.declare(name, descriptor, access);
+        self.methods.emit(&max_locals.to_le_bytes()).emit(&MAX_STACK.to_le_bytes())
+            .emit(&[(TypeSize::U32 as u8) << 5]).emit(&code_pos.to_le_bytes());
+        self
+    }
+
+    /// A method without code, which natives and the methods of interfaces are
+    pub fn declare(&mut self, name: &str, descriptor: &str, access: u8) -> &mut Self {
+        let name = self.string(name);
+        let descriptor = self.string(descriptor);
+        self.methods.emit(&name.to_le_bytes()).emit(&descriptor.to_le_bytes()).emit(&[access]);
+        self.num_methods += 1;
+        self
+    }
+
+    /// An optional section called `name`
+    pub fn section(&mut self, name: &str, data: &[u8]) -> &mut Self {
+        let name = self.string(name);
+        self.sections.emit(&name.to_le_bytes()).emit(&(data.len() as u32).to_le_bytes()).emit(data);
+        self.num_sections += 1;
+        self
+    }
+
+    /// An exceptions section giving `handlers` to the method called `name` with `descriptor`
+    pub fn exceptions(&mut self, name: &str, descriptor: &str, handlers: &[ErrorHandler]) -> &mut Self {
+        let mut data = Assembler::new();
+        let (name, descriptor) = (self.string(name), self.string(descriptor));
+        data.emit(&1u16.to_le_bytes()).emit(&name.to_le_bytes()).emit(&descriptor.to_le_bytes())
+            .emit(&(handlers.len() as u16).to_le_bytes());
+        for handler in handlers {
+            data.emit(&handler.start.to_le_bytes()).emit(&handler.end.to_le_bytes())
+                .emit(&handler.handler.to_le_bytes()).emit(&handler.error_class.to_le_bytes())
+                .emit(&handler.stack_depth.to_le_bytes());
+        }
+        self.section(EXCEPTIONS_SECTION, data.bytes())
+    }
+
+    /// Write out the class with `code` and load it, linking it to the classes loaded before it
+    pub fn load(&self, code: &Assembler, loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut file = [0u8; CLASS_LIMIT];
+        let mut len = 0;
+        {
+            let mut write = |bytes: &[u8]| {
+                file[len..len + bytes.len()].copy_from_slice(bytes);
+                len += bytes.len();
+            };
+
+            write(b"$GLR");
+            write(&CLASS_VERSION.0.to_le_bytes());
+            write(&CLASS_VERSION.1.to_le_bytes());
+            write(&[self.class_type, ACCESS_PUBLIC]);
+            write(&self.num_consts.to_le_bytes());
+            write(self.consts.bytes());
+            write(&[0]);
+            write(&(code.bytes().len() as u32).to_le_bytes());
+            write(&self.num_fields.to_le_bytes());
+            write(self.fields.bytes());
+            write(&self.num_methods.to_le_bytes());
+            write(self.methods.bytes());
+            write(code.bytes());
+            write(&self.num_sections.to_le_bytes());
+            write(self.sections.bytes());
+        }
+        loader.load_class(&file[..len])
+    }
+
+    fn add_const(&mut self) -> u16 {
+        self.num_consts += 1;
+        self.num_consts - 1
+    }
+}
+
+/// i = 0; while i < n { i = i + 1 }; return i
+pub fn counter(n: i64) -> Program {
+    let mut code = Assembler::new();
+    code.local(Opcode::Enter, 1).push(0).local(Opcode::Store, 0);
+
+    let head = code.pos();
+    code.local(Opcode::Load, 0).push(n).op(Opcode::Lt);
+    let exit = code.pos();
+    code.jump(Opcode::Jz, 0)
+        .local(Opcode::Load, 0).push(1).op(Opcode::Add).local(Opcode::Store, 0)
+        .jump(Opcode::Jmp, head);
+
+    let end = code.pos();
+    code.local(Opcode::Load, 0).op(Opcode::Ret);
+    code.patch(exit, end);
+
+    Program { name: "counter", entry: 0, expected: n as u64, code }
+}
+
+/// fib(n) = if n < 2 { n } else { fib(n - 1) + fib(n - 2) }
+pub fn fibonacci(n: i64) -> Program {
+    let mut code = Assembler::new();
+    let fib = code.pos();
+    code.local(Opcode::Load, 0).push(2).op(Opcode::Lt);
+    let recurse = code.pos();
+    code.jump(Opcode::Jz, 0).local(Opcode::Load, 0).op(Opcode::Ret);
+
+    let target = code.pos();
+    code.local(Opcode::Load, 0).push(1).op(Opcode::Sub).call(fib, 1)
+        .local(Opcode::Load, 0).push(2).op(Opcode::Sub).call(fib, 1)
+        .op(Opcode::Add).op(Opcode::Ret);
+    code.patch(recurse, target);
+
+    let entry = code.pos();
+    code.push(n).call(fib, 1).op(Opcode::Ret);
+
+    let expected = (0..n).fold((0u64, 1u64), |(a, b), _| (b, a + b)).0;
+    Program { name: "fib", entry, expected, code }
+}
+
+/// acc = 0; i = 0; while i < n { acc = acc + i * 7 / 3 - i; i = i + 1 }; return acc
+pub fn arithmetic(n: i64) -> Program {
+    let mut code = Assembler::new();
+    code.local(Opcode::Enter, 2)
+        .push(0).local(Opcode::Store, 0)
+        .push(0).local(Opcode::Store, 1);
+
+    let head = code.pos();
+    code.local(Opcode::Load, 1).push(n).op(Opcode::Lt);
+    let exit = code.pos();
+    code.jump(Opcode::Jz, 0)
+        .local(Opcode::Load, 0)
+        .local(Opcode::Load, 1).push(7).op(Opcode::Mul).push(3).op(Opcode::Div)
+        .op(Opcode::Add).local(Opcode::Load, 1).op(Opcode::Sub).local(Opcode::Store, 0)
+        .local(Opcode::Load, 1).push(1).op(Opcode::Add).local(Opcode::Store, 1)
+        .jump(Opcode::Jmp, head);
+
+    let end = code.pos();
+    code.local(Opcode::Load, 0).op(Opcode::Ret);
+    code.patch(exit, end);
+
+    let expected = (0..n).fold(0i64, |acc, i| acc + i * 7 / 3 - i) as u64;
+    Program { name: "arith", entry: 0, expected, code }
+}
+
+/// (0 - 7) / 2 * 10 + (5 < -3) + (4 == 4) * 100, exercising signed comparison and truncating division
+pub fn signed() -> Program {
+    let mut code = Assembler::new();
+    code.push(0).push(7).op(Opcode::Sub).push(2).op(Opcode::Div).push(10).op(Opcode::Mul)
+        .push(5).push(-3).op(Opcode::Lt).op(Opcode::Add)
+        .push(4).op(Opcode::Dup).op(Opcode::Eq).push(100).op(Opcode::Mul).op(Opcode::Add)
+        .op(Opcode::Ret);
+
+    Program { name: "signed", entry: 0, expected: 70, code }
+}
+
+/// f(a, b, c) { let d = a * b; d - c }; f(6, 7, 2) + f(3, 3, 1), exercising argument and local slots
+pub fn frames() -> Program {
+    let mut code = Assembler::new();
+    let f = code.pos();
+    code.local(Opcode::Enter, 1)
+        .local(Opcode::Load, 0).local(Opcode::Load, 1).op(Opcode::Mul).local(Opcode::Store, 3)
+        .local(Opcode::Load, 3).local(Opcode::Load, 2).op(Opcode::Sub).op(Opcode::Ret);
+
+    let entry = code.pos();
+    code.push(6).push(7).push(2).call(f, 3)
+        .push(3).op(Opcode::Dup).push(1).call(f, 3)
+        .op(Opcode::Add).op(Opcode::Ret);
+
+    Program { name: "frames", entry, expected: 48, code }
+}
+
+/// digits(1, 2, 3) + labs(-5) + strlen("hello"), calling a Rust native and two libc symbols
+#[cfg(not(windows))]
+pub fn natives() -> Program {
+    let mut code = Assembler::new();
+    code.push(1).push(2).push(3).native(0)
+        .push(-5).native(1).op(Opcode::Add)
+        .string(0).native(2).op(Opcode::Add)
+        .op(Opcode::Ret);
+
+    Program { name: "natives", entry: 0, expected: 133, code }
+}
+
+/// Chain0.f() = 1 and ChainN.f() = Chain(N-1).f() + 1, run from main in the last class,
+/// which loads and links hundreds of classes each resolving the one before it
+pub fn chain() -> ClassProgram {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut name = [0u8; 16];
+        let mut class = core::ptr::null_mut();
+        for index in 0..CHAIN_LENGTH {
+            let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, numbered(&mut name, "Chain", index));
+            let mut code = Assembler::new();
+            match index {
+                0 => code.push(1),
+                _ => {
+                    let previous = writer.class(numbered(&mut name, "Chain", index - 1));
+                    let f = writer.member(previous, "f", Some("()q"));
+                    code.invoke(Opcode::Invoke, f, 0).push(1).op(Opcode::Add)
+                },
+            }.op(Opcode::Ret);
+
+            let own = writer.class(numbered(&mut name, "Chain", index));
+            let own_f = writer.member(own, "f", Some("()q"));
+            let main = code.pos();
+            code.invoke(Opcode::Invoke, own_f, 0).op(Opcode::Ret);
+
+            writer.method("f", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 0, 0)
+                .method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 0, main);
+            class = writer.load(&code, loader)?;
+        }
+        Ok(class)
+    }
+
+    ClassProgram { name: "chain", expected: CHAIN_LENGTH as u64, load }
+}
+
+/// main() { let x = 100; try { div(1, 0) } catch std.error (code) { x + code } } where div
+/// calls quot, which divides, past a handler for another class: a runtime error thrown in a
+/// callee unwinds to the handler for `std.error` with its `RuntimeError` as the payload
+pub fn fault() -> ClassProgram {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "Fault");
+        let fault = writer.class("Fault");
+        let runtime_error = writer.class("std.error");
+        let div = writer.member(fault, "div", Some("(qq)q"));
+        let quot = writer.member(fault, "quot", Some("(qq)q"));
+
+        let mut code = Assembler::new();
+        code.local(Opcode::Enter, 1).push(100).local(Opcode::Store, 0);
+        let main_try = code.pos();
+        code.push(1).push(0).invoke(Opcode::Invoke, div, 2);
+        let main_end = code.pos();
+        code.op(Opcode::Ret);
+        let main_catch = code.pos();
+        code.op(Opcode::Pop).local(Opcode::Load, 0).op(Opcode::Add).op(Opcode::Ret);
+
+        let div_pos = code.pos();
+        code.local(Opcode::Load, 0).local(Opcode::Load, 1).invoke(Opcode::Invoke, quot, 2);
+        let div_end = code.pos();
+        code.op(Opcode::Ret);
+        let div_catch = code.pos();
+        code.push(999).op(Opcode::Ret);
+
+        let quot_pos = code.pos();
+        code.local(Opcode::Load, 0).local(Opcode::Load, 1).op(Opcode::Div).op(Opcode::Ret);
+
+        writer.method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 1, 0)
+            .method("div", "(qq)q", ACCESS_STATIC, 2, div_pos)
+            .method("quot", "(qq)q", ACCESS_STATIC, 2, quot_pos)
+            .exceptions("main", "()q", &[
+                ErrorHandler { start: main_try, end: main_end, handler: main_catch, error_class: runtime_error, stack_depth: 1 },
+            ])
+            .exceptions("div", "(qq)q", &[
+                ErrorHandler { start: div_pos, end: div_end, handler: div_catch, error_class: fault, stack_depth: 2 },
+            ]);
+        writer.load(&code, loader)
+    }
+
+    ClassProgram { name: "fault", expected: 100, load }
+}
+
+/// main() { let x = 7; try { inner(5) } catch Raise (payload) { payload + x } }, where
+/// inner(a) { try { Raise.raise(a) } catch (payload) { rethrow payload + 1000 } } and
+/// raise(a) throws a * 3 past a handler for `std.error`: errors unwind across frames of
+/// two classes, and a catch-all handler rethrows with the frame it unwound to intact
+pub fn unwind() -> ClassProgram {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "Raise");
+        let raise_class = writer.class("Raise");
+        let runtime_error = writer.class("std.error");
+
+        let mut code = Assembler::new();
+        code.local(Opcode::Load, 0).push(3).op(Opcode::Mul).constant(Opcode::Throw, raise_class);
+        let raise_end = code.pos();
+        code.push(999).op(Opcode::Ret);
+
+        writer.method("raise", "(q)q", ACCESS_PUBLIC | ACCESS_STATIC, 1, 0)
+            .exceptions("raise", "(q)q", &[
+                ErrorHandler { start: 0, end: raise_end, handler: raise_end, error_class: runtime_error, stack_depth: 1 },
+            ]);
+        writer.load(&code, loader)?;
+
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "Unwind");
+        let unwind = writer.class("Unwind");
+        let raise_class = writer.class("Raise");
+        let inner = writer.member(unwind, "inner", Some("(q)q"));
+        let raise = writer.member(raise_class, "raise", Some("(q)q"));
+
+        let mut code = Assembler::new();
+        code.local(Opcode::Enter, 1).push(7).local(Opcode::Store, 0);
+        let main_try = code.pos();
+        code.push(5).invoke(Opcode::Invoke, inner, 1);
+        let main_end = code.pos();
+        code.op(Opcode::Ret);
+        let main_catch = code.pos();
+        code.op(Opcode::Pop).local(Opcode::Load, 0).op(Opcode::Add).op(Opcode::Ret);
+
+        let inner_pos = code.pos();
+        code.local(Opcode::Enter, 1);
+        let inner_try = code.pos();
+        code.local(Opcode::Load, 0).invoke(Opcode::Invoke, raise, 1);
+        let inner_end = code.pos();
+        code.op(Opcode::Ret);
+        let inner_catch = code.pos();
+        code.local(Opcode::Store, 1).push(1000).op(Opcode::Add).local(Opcode::Load, 1).op(Opcode::Rethrow);
+
+        writer.method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 1, 0)
+            .method("inner", "(q)q", ACCESS_STATIC, 2, inner_pos)
+            .exceptions("main", "()q", &[
+                ErrorHandler { start: main_try, end: main_end, handler: main_catch, error_class: raise_class, stack_depth: 1 },
+            ])
+            .exceptions("inner", "(q)q", &[
+                ErrorHandler { start: inner_try, end: inner_end, handler: inner_catch, error_class: CATCH_ALL, stack_depth: 2 },
+            ]);
+        writer.load(&code, loader)
+    }
+
+    ClassProgram { name: "unwind", expected: 1022, load }
+}
+
+/// main() sums measure(shape) over a Square, a Square, a Triangle, a Triangle, a Hexagon, an
+/// Octagon and a Square, whose `area`s are 1, 10, 100 and 1000, then adds the error code of
+/// calling it on a Plain struct that doesn't implement Shape. The one `InvokeInterface` in
+/// measure misses its inline cache, hits it, misses and hits again, then misses on every call
+/// as the site turns megamorphic.
+pub fn dispatch() -> ClassProgram {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut writer = ClassWriter::new(CLASS_TYPE_INTERFACE, "Shape");
+        writer.declare("area", "()q", ACCESS_PUBLIC);
+        writer.load(&Assembler::new(), loader)?;
+
+        let shapes = [("Square", 1), ("Triangle", 10), ("Hexagon", 100), ("Octagon", 1000), ("Plain", 0)];
+        for &(name, area) in shapes.iter() {
+            let mut writer = ClassWriter::new(CLASS_TYPE_STRUCT, name);
+            let mut code = Assembler::new();
+            code.push(area).op(Opcode::Ret);
+            writer.method("area", "()q", ACCESS_PUBLIC, 1, 0);
+            if name != "Plain" {
+                let shape = writer.class("Shape");
+                let mut impls = Assembler::new();
+                impls.emit(&1u16.to_le_bytes()).emit(&shape.to_le_bytes());
+                writer.section(IMPLS_SECTION, impls.bytes());
+            }
+            writer.load(&code, loader)?;
+        }
+
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "Dispatch");
+        let dispatch = writer.class("Dispatch");
+        let shape = writer.class("Shape");
+        let area = writer.member(shape, "area", Some("()q"));
+        let measure = writer.member(dispatch, "measure", Some("(LShape;)q"));
+        let runtime_error = writer.class("std.error");
+
+        let mut code = Assembler::new();
+        code.push(0);
+        for &name in ["Square", "Square", "Triangle", "Triangle", "Hexagon", "Octagon", "Square"].iter() {
+            let class = writer.class(name);
+            code.constant(Opcode::New, class).invoke(Opcode::Invoke, measure, 1).op(Opcode::Add);
+        }
+        let plain = writer.class("Plain");
+        let main_try = code.pos();
+        code.constant(Opcode::New, plain).invoke(Opcode::Invoke, measure, 1).op(Opcode::Add);
+        let main_end = code.pos();
+        code.op(Opcode::Ret);
+        let main_catch = code.pos();
+        code.op(Opcode::Pop).op(Opcode::Add).op(Opcode::Ret);
+
+        let measure_pos = code.pos();
+        code.local(Opcode::Load, 0).invoke(Opcode::InvokeInterface, area, 1).op(Opcode::Ret);
+
+        writer.method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 0, 0)
+            .method("measure", "(LShape;)q", ACCESS_STATIC, 1, measure_pos)
+            .exceptions("main", "()q", &[
+                ErrorHandler { start: main_try, end: main_end, handler: main_catch, error_class: runtime_error, stack_depth: 1 },
+            ]);
+        writer.load(&code, loader)
+    }
+
+    ClassProgram { name: "dispatch", expected: 1125, load }
+}
+
+/// main() builds a list of closures node(i, next) for i = 1 to LIST_LENGTH, each returning
+/// i + next(), dropping GARBAGE_PER_NODE closures over three values after adding each, and
+/// keeps a closure times(7) only in a module field. The garbage sets off collections while
+/// the list is live on the stack and inside the closures it captures; then main returns
+/// the sum of the list plus times(7)(6).
+pub fn closures() -> ClassProgram {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "Closures");
+        let closures = writer.class("Closures");
+        let node = writer.member(closures, "node", Some("(F()q)q"));
+        let garbage = writer.member(closures, "garbage", Some("(F()q)q"));
+        let times = writer.member(closures, "times", Some("(F(q)qq)q"));
+        let keep = writer.member(closures, "keep", None);
+
+        let mut code = Assembler::new();
+        let node_pos = code.pos();
+        code.local(Opcode::Capture, 1);
+        let last = code.pos();
+        code.jump(Opcode::Jz, 0)
+            .local(Opcode::Capture, 0).local(Opcode::Capture, 1).call_closure(0)
+            .op(Opcode::Add).op(Opcode::Ret);
+        let end = code.pos();
+        code.local(Opcode::Capture, 0).op(Opcode::Ret);
+        code.patch(last, end);
+
+        let garbage_pos = code.pos();
+        code.local(Opcode::Capture, 0).op(Opcode::Ret);
+
+        let times_pos = code.pos();
+        code.local(Opcode::Capture, 0).local(Opcode::Load, 1).op(Opcode::Mul).op(Opcode::Ret);
+
+        // locals: 0 = list, 1 = i, 2 = garbage left to drop
+        let main_pos = code.pos();
+        code.local(Opcode::Enter, 3)
+            .push(7).invoke(Opcode::Closure, times, 1).constant(Opcode::PutStatic, keep)
+            .push(LIST_LENGTH).local(Opcode::Store, 1);
+        let outer = code.pos();
+        code.local(Opcode::Load, 1);
+        let done = code.pos();
+        code.jump(Opcode::Jz, 0)
+            .local(Opcode::Load, 1).local(Opcode::Load, 0).invoke(Opcode::Closure, node, 2).local(Opcode::Store, 0)
+            .push(GARBAGE_PER_NODE).local(Opcode::Store, 2);
+        let inner = code.pos();
+        code.local(Opcode::Load, 2);
+        let next = code.pos();
+        code.jump(Opcode::Jz, 0)
+            .local(Opcode::Load, 2).push(3).push(4).invoke(Opcode::Closure, garbage, 3).op(Opcode::Pop)
+            .local(Opcode::Load, 2).push(1).op(Opcode::Sub).local(Opcode::Store, 2)
+            .jump(Opcode::Jmp, inner);
+        let next_target = code.pos();
+        code.local(Opcode::Load, 1).push(1).op(Opcode::Sub).local(Opcode::Store, 1)
+            .jump(Opcode::Jmp, outer);
+        let done_target = code.pos();
+        code.local(Opcode::Load, 0).call_closure(0)
+            .constant(Opcode::GetStatic, keep).push(6).call_closure(1)
+            .op(Opcode::Add).op(Opcode::Ret);
+        code.patch(next, next_target);
+        code.patch(done, done_target);
+
+        writer.field("keep", 0, None)
+            .method("node", "(F()q)q", ACCESS_STATIC, 1, node_pos)
+            .method("garbage", "(F()q)q", ACCESS_STATIC, 1, garbage_pos)
+            .method("times", "(F(q)qq)q", ACCESS_STATIC, 2, times_pos)
+            .method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 3, main_pos);
+        writer.load(&code, loader)
+    }
+
+    ClassProgram { name: "closures", expected: (LIST_LENGTH * (LIST_LENGTH + 1) / 2 + 42) as u64, load }
+}
+
+/// main() fills an array of i64 with 10, 20 and 30, pushes 40 and 50 past its capacity, and
+/// concatenates it with its slice from 1 to 4; slices "world" out of "hello" + " world";
+/// then reads the array past its end, slices the string past its end and takes the length
+/// of null, catching each error. It sums the lengths of both arrays, the concatenation's
+/// last element, the slice's first byte and length, and the error codes.
+pub fn arrays() -> ClassProgram {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "Arrays");
+        let hello = writer.string("hello");
+        let world = writer.string(" world");
+        let runtime_error = writer.class("std.error");
+
+        // locals: 0 = array, 1 = concatenation, 2 = slice of the string
+        let mut code = Assembler::new();
+        code.local(Opcode::Enter, 3)
+            .push(3).emit(&[Opcode::NewArray as u8, TypeSize::I64 as u8]).local(Opcode::Store, 0);
+        for (index, &value) in [10, 20, 30].iter().enumerate() {
+            code.local(Opcode::Load, 0).push(index as i64).push(value).op(Opcode::ArraySet);
+        }
+        for &value in [40, 50].iter() {
+            code.local(Opcode::Load, 0).push(value).op(Opcode::ArrayPush);
+        }
+        code.local(Opcode::Load, 0)
+            .local(Opcode::Load, 0).push(1).push(4).op(Opcode::ArraySlice)
+            .op(Opcode::ArrayConcat).local(Opcode::Store, 1)
+            .string(hello).string(world).op(Opcode::StrConcat).push(6).push(11).op(Opcode::StrSlice)
+            .local(Opcode::Store, 2);
+
+        code.local(Opcode::Load, 0).op(Opcode::ArrayLen)
+            .local(Opcode::Load, 1).op(Opcode::ArrayLen).op(Opcode::Add)
+            .local(Opcode::Load, 1).push(7).op(Opcode::ArrayGet).op(Opcode::Add)
+            .local(Opcode::Load, 2).push(0).op(Opcode::StrGet).op(Opcode::Add)
+            .local(Opcode::Load, 2).op(Opcode::StrLen).op(Opcode::Add);
+
+        // each error is caught with the locals and the sum so far left on the stack, and its
+        // code added to the sum
+        let faults: [fn(&mut Assembler); 3] = [
+            |code| { code.local(Opcode::Load, 0).push(5).op(Opcode::ArrayGet); },
+            |code| { code.local(Opcode::Load, 2).push(0).push(99).op(Opcode::StrSlice); },
+            |code| { code.push(0).op(Opcode::ArrayLen); },
+        ];
+        let mut handlers = [ErrorHandler { start: 0, end: 0, handler: 0, error_class: runtime_error, stack_depth: 4 }; 3];
+        for (fault, handler) in faults.iter().zip(handlers.iter_mut()) {
+            handler.start = code.pos();
+            fault(&mut code);
+            code.op(Opcode::Add);
+            handler.end = code.pos();
+            let skip = code.pos();
+            code.jump(Opcode::Jmp, 0);
+            handler.handler = code.pos();
+            code.op(Opcode::Pop).op(Opcode::Add);
+            let resume = code.pos();
+            code.patch(skip, resume);
+        }
+        code.op(Opcode::Ret);
+
+        writer.method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 3, 0)
+            .exceptions("main", "()q", &handlers);
+        writer.load(&code, loader)
+    }
+
+    ClassProgram { name: "arrays", expected: 193, load }
+}
+
+/// main() puts i -> [i] in a map of ints for i = MAP_ARRAYS down to 1, then i -> i in the map
+/// and i in a set of ints for i = MAP_SIZE down to MAP_ARRAYS + 1. Only the tables of the map
+/// and set are allocated in the second loop, so every collection it sets off runs while one
+/// of them grows, with the arrays held by nothing but the map's old table. It returns the sum
+/// of map[i][0] over the arrays, map[MAP_SIZE] and both lengths.
+pub fn maps() -> ClassProgram {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "Maps");
+        let map = writer.class("std.map");
+        let set = writer.class("std.set");
+        let (new_map, put, get, map_len) = (
+            writer.member(map, "new_int", None), writer.member(map, "put", None),
+            writer.member(map, "get", None), writer.member(map, "len", None),
+        );
+        let (new_set, add, set_len) = (
+            writer.member(set, "new_int", None), writer.member(set, "add", None), writer.member(set, "len", None),
+        );
+
+        // locals: 0 = map, 1 = set, 2 = i, 3 = sum
+        let mut code = Assembler::new();
+        code.local(Opcode::Enter, 4)
+            .invoke(Opcode::Invoke, new_map, 0).local(Opcode::Store, 0)
+            .invoke(Opcode::Invoke, new_set, 0).local(Opcode::Store, 1)
+            .push(MAP_ARRAYS).local(Opcode::Store, 2);
+        let arrays = code.pos();
+        code.local(Opcode::Load, 2);
+        let arrays_done = code.pos();
+        code.jump(Opcode::Jz, 0)
+            .local(Opcode::Load, 0).local(Opcode::Load, 2)
+            .push(1).emit(&[Opcode::NewArray as u8, TypeSize::I64 as u8])
+            .op(Opcode::Dup).push(0).local(Opcode::Load, 2).op(Opcode::ArraySet)
+            .invoke(Opcode::Invoke, put, 3)
+            .local(Opcode::Load, 2).push(1).op(Opcode::Sub).local(Opcode::Store, 2)
+            .jump(Opcode::Jmp, arrays);
+        let fill = code.pos();
+        code.patch(arrays_done, fill);
+
+        code.push(MAP_SIZE).local(Opcode::Store, 2);
+        let head = code.pos();
+        code.push(MAP_ARRAYS).local(Opcode::Load, 2).op(Opcode::Lt);
+        let filled = code.pos();
+        code.jump(Opcode::Jz, 0)
+            .local(Opcode::Load, 0).local(Opcode::Load, 2).local(Opcode::Load, 2).invoke(Opcode::Invoke, put, 3)
+            .local(Opcode::Load, 1).local(Opcode::Load, 2).invoke(Opcode::Invoke, add, 2).op(Opcode::Pop)
+            .local(Opcode::Load, 2).push(1).op(Opcode::Sub).local(Opcode::Store, 2)
+            .jump(Opcode::Jmp, head);
+        let sum = code.pos();
+        code.patch(filled, sum);
+
+        code.push(MAP_ARRAYS).local(Opcode::Store, 2);
+        let head = code.pos();
+        code.local(Opcode::Load, 2);
+        let summed = code.pos();
+        code.jump(Opcode::Jz, 0)
+            .local(Opcode::Load, 3)
+            .local(Opcode::Load, 0).local(Opcode::Load, 2).invoke(Opcode::Invoke, get, 2)
+            .push(0).op(Opcode::ArrayGet).op(Opcode::Add).local(Opcode::Store, 3)
+            .local(Opcode::Load, 2).push(1).op(Opcode::Sub).local(Opcode::Store, 2)
+            .jump(Opcode::Jmp, head);
+        let end = code.pos();
+        code.patch(summed, end);
+        code.local(Opcode::Load, 3)
+            .local(Opcode::Load, 0).push(MAP_SIZE).invoke(Opcode::Invoke, get, 2).op(Opcode::Add)
+            .local(Opcode::Load, 0).invoke(Opcode::Invoke, map_len, 1).op(Opcode::Add)
+            .local(Opcode::Load, 1).invoke(Opcode::Invoke, set_len, 1).op(Opcode::Add)
+            .op(Opcode::Ret);
+
+        writer.method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 4, 0);
+        writer.load(&code, loader)
+    }
+
+    let expected = MAP_ARRAYS * (MAP_ARRAYS + 1) / 2 + MAP_SIZE + MAP_SIZE + (MAP_SIZE - MAP_ARRAYS);
+    ClassProgram { name: "maps", expected: expected as u64, load }
+}
+
+/// A method starting one past the end of its class's code
+pub fn entry_past_code() -> BadClass {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut code = Assembler::new();
+        code.push(1).op(Opcode::Ret);
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "PastCode");
+        writer.method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 0, code.pos());
+        writer.load(&code, loader)
+    }
+
+    BadClass { name: "past end", error: ClassError::BadCodePos, load }
+}
+
+/// A method starting in the operand of a `push`
+pub fn entry_in_operand() -> BadClass {
+    fn load(loader: &mut ClassLoader) -> ClassResult<*mut Class> {
+        let mut code = Assembler::new();
+        code.push(1).op(Opcode::Ret);
+        let mut writer = ClassWriter::new(CLASS_TYPE_MODULE, "InOperand");
+        writer.method("main", "()q", ACCESS_PUBLIC | ACCESS_STATIC, 0, 1);
+        writer.load(&code, loader)
+    }
+
+    BadClass { name: "in push", error: ClassError::BadCodePos, load }
+}
+
+/// `prefix` followed by `number` in decimal
+fn numbered<'a>(buffer: &'a mut [u8; 16], prefix: &str, number: usize) -> &'a str {
+    let mut digits = [0u8; 20];
+    let mut start = digits.len();
+    let mut rest = number;
+    loop {
+        start -= 1;
+        digits[start] = b'0' + (rest % 10) as u8;
+        rest /= 10;
+        if rest == 0 {
+            break
+        }
+    }
+
+    let len = prefix.len() + digits.len() - start;
+    buffer[..prefix.len()].copy_from_slice(prefix.as_bytes());
+    buffer[prefix.len()..len].copy_from_slice(&digits[start..]);
+    unsafe { core::str::from_utf8_unchecked(&buffer[..len]) }
+}
+
+fn digits(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(args.int(0) * 100 + args.int(1) * 10 + args.int(2))
+}
+
+/// The memory a program needs to run: code cells, the operand stack and the frame stack,
+/// along with the natives and string constant that `Native` and `Str` instructions refer to
+pub struct Machine {
+    loader: ClassLoader,
+    runtime: Runtime,
+    string: u64,
+    natives: [Option<*mut Native>; 3],
+}
+
+impl Machine {
+    pub fn new() -> Option<Self> {
+        let mut loader = ClassLoader::new().ok()?;
+        let int = NativeType::Num(TypeSize::I64);
+
+        let digits = Some((NativeTarget::Rust(digits), NativeSignature::new(&[int, int, int], int)));
+
+        // windows has no symbols to call, and doesn't run the natives program
+        #[cfg(windows)]
+        let natives = [digits, None, None];
+        #[cfg(not(windows))]
+        let natives = {
+            let symbol = |name: &str| unsafe { find_symbol(None, name.as_ptr() as *const c_char) };
+            [
+                digits,
+                Some((NativeTarget::Symbol(symbol("labs\0")?), NativeSignature::new(&[int], int))),
+                Some((NativeTarget::Symbol(symbol("strlen\0")?), NativeSignature::new(&[NativeType::Str], int))),
+            ]
+        };
+
+        let mut allocated = [None; 3];
+        for (slot, &native) in allocated.iter_mut().zip(natives.iter()) {
+            if let Some((target, signature)) = native {
+                *slot = Some(loader.alloc(Native { target, signature }).ok()?);
+            }
+        }
+
+        let string = loader.alloc_str(&[b"hello"]).ok()?.as_ptr() as u64;
+
+        Some(Self {
+            loader,
+            string,
+            natives: allocated,
+            runtime: Runtime::new()?,
+        })
+    }
+
+    /// Predecode `program` for `backend` and run it, returning its entry point's result
+    pub fn run(&mut self, program: &Program, backend: Backend) -> Option<u64> {
+        unsafe {
+            let bytecode = program.code.bytes();
+            let cells = self.loader.alloc_cells(bytecode.len()).ok()?;
+            predecode(bytecode, cells, backend, &|opcode, index| match opcode {
+                Opcode::Str => Ok(self.string),
+                _ => self.natives.get(index as usize).and_then(|&native| native)
+                    .map(|native| native as u64 | ((*native).signature.num_args as u64) << NARGS_SHIFT)
+                    .ok_or(ClassError::UnboundNative),
+            }).ok()?;
+
+            let entry = cells.add(program.entry as usize);
+            Some(self.runtime.run(backend, entry))
+        }
+    }
+}
+
+/// Load `class` with a loader of its own for `backend`, returning the error it's refused with.
+/// Loaders map their memory at fixed addresses, so this can't run while a `Machine` is alive.
+pub fn refuse(class: &BadClass, backend: Backend) -> Option<ClassError> {
+    let mut loader = ClassLoader::with_backend(backend).ok()?;
+    (class.load)(&mut loader).err()
+}
+
+/// Load `program` with a loader of its own for `backend` and run it. Loaders and runtimes map their
+/// memory at fixed addresses, so this can't run while a `Machine` or another program is running.
+pub fn run_classes(program: &ClassProgram, backend: Backend) -> Option<u64> {
+    let mut loader = ClassLoader::with_backend(backend).ok()?;
+    let mut runtime = Runtime::new()?;
+    let class = unsafe { &*(program.load)(&mut loader).ok()? };
+    let class_file = class.class_file();
+    let main = loader.symbol("main").and_then(|main| class_file.methods_named(main).next())?;
+
+    // blocks of struct instances point at their class, so they're freed while it's loaded
+    let result = unsafe { runtime.invoke(&loader, class_file, main) };
+    unsafe { collect() };
+    result
+}
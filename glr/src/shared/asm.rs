@@ -0,0 +1,29 @@
+/// Declare an `extern` function whose body is the given assembly, written in
+/// intel syntax on x86_64 and in the standard syntax on aarch64. The function
+/// follows the target's C calling convention (System V on x86_64, even on windows).
+#[cfg(target_arch = "x86_64")]
+macro_rules! asm_func {
+    ($name:ident($($arg:ident: $type:ty),*) -> $ret:ty, $asm:expr) => {
+        extern "sysv64" { pub fn $name($($arg: $type),*) -> $ret; }
+        global_asm!(concat!(
+            ".intel_syntax noprefix\n",
+            concat!(".global ", stringify!($name), "\n"),
+            concat!(stringify!($name), ": \n"),
+            $asm,
+            "\n.att_syntax\n"
+        ));
+    };
+}
+
+#[cfg(target_arch = "aarch64")]
+macro_rules! asm_func {
+    ($name:ident($($arg:ident: $type:ty),*) -> $ret:ty, $asm:expr) => {
+        extern "C" { pub fn $name($($arg: $type),*) -> $ret; }
+        global_asm!(concat!(
+            concat!(".global ", stringify!($name), "\n"),
+            ".balign 4\n",
+            concat!(stringify!($name), ": \n"),
+            $asm
+        ));
+    };
+}
@@ -0,0 +1,32 @@
+use super::*;
+
+/// Open a shared library by its NUL-terminated `path`
+#[cfg(unix)]
+pub unsafe fn open_library(path: *const c_char) -> Option<*mut c_void> {
+    match dlopen(path, RTLD_NOW | RTLD_LOCAL) {
+        library if library.is_null() => None,
+        library => Some(library),
+    }
+}
+
+/// Find the NUL-terminated symbol `name` in `library`, or in the process and
+/// its already loaded libraries when `library` is `None`
+#[cfg(unix)]
+pub unsafe fn find_symbol(library: Option<*mut c_void>, name: *const c_char) -> Option<*const u8> {
+    match dlsym(library.unwrap_or(RTLD_DEFAULT), name) {
+        symbol if symbol.is_null() => None,
+        symbol => Some(symbol as *const u8),
+    }
+}
+
+// calling C symbols relies on the System V / AAPCS64 register assignment,
+// so on windows native methods can only be bound to registered Rust functions
+#[cfg(windows)]
+pub unsafe fn open_library(_path: *const c_char) -> Option<*mut c_void> {
+    None
+}
+
+#[cfg(windows)]
+pub unsafe fn find_symbol(_library: Option<*mut c_void>, _name: *const c_char) -> Option<*const u8> {
+    None
+}
@@ -14,6 +14,13 @@ impl CString for str {
     }
 }
 
+/// View a NUL-terminated C string such as a command line argument, assuming it is utf8
+#[inline]
+pub unsafe fn from_c_str<'a>(string: *const c_char) -> &'a str {
+    let bytes = core::slice::from_raw_parts(string as *const u8, strlen(string) as usize);
+    core::str::from_utf8_unchecked(bytes)
+}
+
 #[cfg(unix)]
 pub mod ffi {
     pub use libc::*;
@@ -28,8 +35,32 @@ pub mod ffi {
     pub use winapi::um::winnt::*;
     pub use winapi::um::memoryapi::*;
     pub use winapi::um::sysinfoapi::*;
+    pub use winapi::um::fileapi::{CreateFileA, GetFileSizeEx, OPEN_EXISTING};
+    pub use winapi::um::handleapi::{CloseHandle, INVALID_HANDLE_VALUE};
+    pub use winapi::um::winbase::CreateFileMappingA;
+
+    pub enum FILE {}
+
+    pub const EOF: c_int = -1;
+    pub const SEEK_SET: c_int = 0;
+    pub const SEEK_END: c_int = 2;
 
     extern "C" {
         pub fn printf(format: *const c_char, ...) -> i32;
+        pub fn strlen(string: *const c_char) -> usize;
+        pub fn getchar() -> c_int;
+        pub fn exit(status: c_int) -> !;
+        pub fn fopen(path: *const c_char, mode: *const c_char) -> *mut FILE;
+        pub fn fclose(file: *mut FILE) -> c_int;
+        pub fn fseek(file: *mut FILE, offset: c_long, origin: c_int) -> c_int;
+        pub fn ftell(file: *mut FILE) -> c_long;
+        pub fn fread(buffer: *mut c_void, size: usize, count: usize, file: *mut FILE) -> usize;
+        pub fn fwrite(buffer: *const c_void, size: usize, count: usize, file: *mut FILE) -> usize;
+    }
+
+    #[link(name = "advapi32")]
+    extern "system" {
+        #[link_name = "SystemFunction036"]
+        pub fn RtlGenRandom(buffer: *mut c_void, length: c_ulong) -> BOOLEAN;
     }
 }
\ No newline at end of file
@@ -1,8 +1,14 @@
 use super::*;
 
 pub const CLASS_MAPPING: usize = (1 << 25); // 32mb of addressable memory
+pub const CODE_INDEX:    usize = (1 << 26); // 64mb of addressable memory
 pub const CLASS_MEMORY:  usize = (1 << 30); // 1gb of addressable memory
 pub const CODE_MEMORY:   usize = (1 << 32); // 4gb of addressable memory
+pub const FRAME_MEMORY:  usize = (1 << 27); // 128mb of addressable memory
+pub const STACK_MEMORY:  usize = (1 << 28); // 256mb of addressable memory
+pub const STRING_MEMORY: usize = (1 << 29); // 512mb of addressable memory
+pub const HEAP_MEMORY:   usize = (1 << 33); // 8gb of addressable memory
+pub const GC_MEMORY:     usize = (1 << 34); // 16gb of addressable memory
 
 lazy_static! {
     static ref PAGE_SIZES: (usize, usize) = unsafe { get_page_sizes() };
@@ -70,6 +76,18 @@ impl MemoryRange {
         self.addr as *mut _
     } 
 
+    /// Where the next allocation will start
+    #[inline]
+    pub fn top_ptr(&self) -> *mut u8 {
+        (self.addr + self.top) as *mut _
+    }
+
+    /// Give back everything allocated at or after `ptr`, which must be in this range
+    #[inline]
+    pub fn release_from(&mut self, ptr: *mut u8) {
+        self.top = ptr as usize - self.addr;
+    }
+
     #[inline]
     pub fn as_bytes(&self) -> &[u8] {
         unsafe { core::slice::from_raw_parts(self.addr as *const _, self.size) }
@@ -77,6 +95,8 @@ impl MemoryRange {
 
     #[inline]
     pub fn alloc_many<T: Sized>(&mut self, amount: usize) -> Option<*mut T> {
+        let align = core::mem::align_of::<T>();
+        self.top = (self.top + align - 1) & !(align - 1);
         self.alloc_bytes(core::mem::size_of::<T>() * amount)
             .and_then(|bytes| Some(bytes as *mut _))
     }
@@ -112,16 +132,22 @@ impl MemoryRange {
     #[cfg(unix)]
     unsafe fn mmap(addr: *mut c_void, size: usize, executable: bool) -> Option<usize> {
         let mut protect = PROT_READ | PROT_WRITE;
-        let mut memory = MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE | MAP_FIXED;
+        let memory = MAP_PRIVATE | MAP_ANONYMOUS | MAP_FIXED;
 
         if executable {
             protect |= PROT_EXEC;
         }
+
+        // huge pages must be reserved up front: a MAP_NORESERVE huge page mapping
+        // raises SIGBUS on first touch when the system (or qemu-user) has none to give
         if size >= Self::huge_page_size() {
-            memory |= MAP_HUGETLB;
+            match mmap(addr, size, protect, memory | MAP_HUGETLB, -1, 0) {
+                MAP_FAILED => {},
+                addr => return Some(addr as usize)
+            }
         }
         
-        match mmap(addr, size, protect, memory, -1, 0) {
+        match mmap(addr, size, protect, memory | MAP_NORESERVE, -1, 0) {
             MAP_FAILED => None,
             addr => Some(addr as usize)
         }
@@ -144,4 +170,80 @@ impl MemoryRange {
             addr => Some(addr as usize),
         }
     }
-}
\ No newline at end of file
+}
+
+/// A file mapped read only into memory for as long as this lives
+pub struct MappedFile {
+    addr: usize,
+    size: usize,
+}
+
+impl core::ops::Drop for MappedFile {
+    fn drop(&mut self) {
+        unsafe {
+            #[cfg(unix)] munmap(self.addr as *mut c_void, self.size);
+            #[cfg(windows)] UnmapViewOfFile(self.addr as *mut c_void);
+        }
+    }
+}
+
+impl MappedFile {
+    #[inline]
+    pub fn as_bytes(&self) -> &[u8] {
+        unsafe { core::slice::from_raw_parts(self.addr as *const _, self.size) }
+    }
+
+    /// Map the file at the NUL-terminated `path`, or None if it can't be opened or is empty
+    #[cfg(unix)]
+    pub unsafe fn open(path: *const c_char) -> Option<Self> {
+        let file = open(path, O_RDONLY);
+        if file < 0 {
+            return None
+        }
+
+        let mut info: stat = core::mem::zeroed();
+        let size = match fstat(file, &mut info) {
+            0 if info.st_size > 0 => info.st_size as usize,
+            _ => 0,
+        };
+
+        let addr = match size {
+            0 => MAP_FAILED,
+            size => mmap(null_mut(), size, PROT_READ, MAP_PRIVATE, file, 0),
+        };
+
+        close(file);
+        match addr {
+            MAP_FAILED => None,
+            addr => Some(Self { addr: addr as usize, size }),
+        }
+    }
+
+    #[cfg(windows)]
+    pub unsafe fn open(path: *const c_char) -> Option<Self> {
+        let file = CreateFileA(path, GENERIC_READ, FILE_SHARE_READ, null_mut(),
+            OPEN_EXISTING, FILE_ATTRIBUTE_NORMAL, null_mut());
+        if file == INVALID_HANDLE_VALUE {
+            return None
+        }
+
+        let mut size: LARGE_INTEGER = core::mem::zeroed();
+        let mapping = match GetFileSizeEx(file, &mut size) {
+            0 => null_mut(),
+            _ if *size.QuadPart() <= 0 => null_mut(),
+            _ => CreateFileMappingA(file, null_mut(), PAGE_READONLY, 0, 0, core::ptr::null()),
+        };
+
+        CloseHandle(file);
+        if mapping.is_null() {
+            return None
+        }
+
+        let addr = MapViewOfFile(mapping, FILE_MAP_READ, 0, 0, 0);
+        CloseHandle(mapping);
+        match addr {
+            NULL => None,
+            addr => Some(Self { addr: addr as usize, size: *size.QuadPart() as usize }),
+        }
+    }
+}
@@ -1,10 +1,15 @@
 #[macro_use]
 #[allow(dead_code)]
+pub mod asm;
+#[macro_use]
+#[allow(dead_code)]
 pub mod print;
 #[allow(dead_code)]
 pub mod ffi;
 #[allow(dead_code)]
 pub mod mem;
+#[allow(dead_code)]
+pub mod dylib;
 
 pub use self::ffi::*;
 pub use self::print::print;
\ No newline at end of file
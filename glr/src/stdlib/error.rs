@@ -0,0 +1,8 @@
+use super::{new_string, empty_string};
+use crate::bytecode::{NativeArgs, NativeValue, RuntimeError};
+
+/// Describe the payload of a runtime error caught as a `std.error`
+pub fn message(args: &NativeArgs) -> NativeValue<'static> {
+    let message = RuntimeError::from(args.int(0) as u64).message();
+    NativeValue::Str(new_string(&[message.as_bytes()]).unwrap_or_else(empty_string))
+}
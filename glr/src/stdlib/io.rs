@@ -0,0 +1,82 @@
+use super::{new_string_with, empty_string};
+use crate::bytecode::{NativeArgs, NativeValue};
+use crate::shared::*;
+
+const LINE_LIMIT: usize = 4096;
+
+pub fn print(args: &NativeArgs) -> NativeValue<'static> {
+    print!("{}", args.str(0));
+    NativeValue::Void
+}
+
+pub fn println(args: &NativeArgs) -> NativeValue<'static> {
+    println!("{}", args.str(0));
+    NativeValue::Void
+}
+
+pub fn print_int(args: &NativeArgs) -> NativeValue<'static> {
+    println!("{}", args.int(0));
+    NativeValue::Void
+}
+
+pub fn print_float(args: &NativeArgs) -> NativeValue<'static> {
+    println!("{}", args.float(0));
+    NativeValue::Void
+}
+
+/// Read a line from stdin without its trailing newline, or an empty string at end of input
+pub fn read_line(_args: &NativeArgs) -> NativeValue<'static> {
+    let line = new_string_with(LINE_LIMIT, |line| {
+        let mut size = 0;
+        while size < line.len() {
+            match unsafe { getchar() } {
+                EOF | 10 => break,
+                byte => line[size] = byte as u8,
+            }
+            size += 1;
+        }
+        size
+    });
+    NativeValue::Str(line.unwrap_or_else(empty_string))
+}
+
+/// Read the whole file at the given path, or an empty string if it can't be read
+pub fn read_file(args: &NativeArgs) -> NativeValue<'static> {
+    unsafe {
+        let file = fopen(args.str(0).c_str(), "rb\0".c_str());
+        if file.is_null() {
+            return NativeValue::Str(empty_string())
+        }
+
+        let contents = match fseek(file, 0, SEEK_END) {
+            0 => match ftell(file) {
+                size if size >= 0 => read_contents(file, size as usize),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        fclose(file);
+        NativeValue::Str(contents.unwrap_or_else(empty_string))
+    }
+}
+
+unsafe fn read_contents(file: *mut FILE, size: usize) -> Option<&'static str> {
+    fseek(file, 0, SEEK_SET);
+    new_string_with(size, |bytes| fread(bytes.as_mut_ptr() as *mut c_void, 1, size, file) as usize)
+}
+
+/// Write the string to the file at the given path, returning the bytes written or -1
+pub fn write_file(args: &NativeArgs) -> NativeValue<'static> {
+    unsafe {
+        let file = fopen(args.str(0).c_str(), "wb\0".c_str());
+        if file.is_null() {
+            return NativeValue::Int(-1)
+        }
+
+        let contents = args.str(1);
+        let written = fwrite(contents.as_ptr() as *const c_void, 1, contents.len(), file);
+        fclose(file);
+        NativeValue::Int(written as i64)
+    }
+}
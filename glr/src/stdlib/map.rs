@@ -0,0 +1,69 @@
+use crate::bytecode::{NativeArgs, NativeValue, KIND_MAP, KEYS_INT, KEYS_STR, KEYS_STRUCT};
+use crate::bytecode::{new_map, as_map, map_len, map_find, map_insert, map_remove, map_items};
+
+// Maps are passed around as i64 handles to the heap. Keys and values are raw slots,
+// so the same functions work whatever their types are.
+
+pub fn new_int(_: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(unsafe { new_map(KIND_MAP, KEYS_INT) } as u64)
+}
+
+pub fn new_str(_: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(unsafe { new_map(KIND_MAP, KEYS_STR) } as u64)
+}
+
+pub fn new_struct(_: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(unsafe { new_map(KIND_MAP, KEYS_STRUCT) } as u64)
+}
+
+pub fn len(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(map(args).map_or(0, |map| unsafe { map_len(map) }) as i64)
+}
+
+pub fn has(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(value(args).is_some() as i64)
+}
+
+/// The value of the key, or 0 if it isn't in the map
+pub fn get(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(value(args).unwrap_or(0))
+}
+
+/// The value of the key, or the third argument if it isn't in the map
+pub fn get_or(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(value(args).unwrap_or(args.uint(2)))
+}
+
+/// Set the value of the key, leaving the map as it was if out of memory
+pub fn put(args: &NativeArgs) -> NativeValue<'static> {
+    if let Some(map) = map(args) {
+        unsafe { map_insert(map, args.uint(1), args.uint(2)) };
+    }
+    NativeValue::Void
+}
+
+/// Remove the key, returning whether it was in the map
+pub fn remove(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(map(args).map_or(false, |map| unsafe { map_remove(map, args.uint(1)) }) as i64)
+}
+
+/// An array of the keys, in no particular order
+pub fn keys(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(map(args).map_or(0, |map| unsafe { map_items(map, 1) } as u64))
+}
+
+/// An array of the values, in the order `keys` returns their keys
+pub fn values(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(map(args).map_or(0, |map| unsafe { map_items(map, 2) } as u64))
+}
+
+/// The map the first argument refers to. Anything else acts as an empty map.
+#[inline]
+fn map(args: &NativeArgs) -> Option<*mut u64> {
+    unsafe { as_map(args.uint(0), KIND_MAP) }
+}
+
+#[inline]
+fn value(args: &NativeArgs) -> Option<u64> {
+    map(args).and_then(|map| unsafe { map_find(map, args.uint(1)).map(|entry| *entry.add(2)) })
+}
@@ -0,0 +1,70 @@
+use crate::bytecode::{NativeArgs, NativeValue};
+
+#[cfg_attr(unix, link(name = "m"))]
+extern "C" {
+    #[link_name = "sqrt"]
+    fn c_sqrt(value: f64) -> f64;
+    #[link_name = "floor"]
+    fn c_floor(value: f64) -> f64;
+    #[link_name = "ceil"]
+    fn c_ceil(value: f64) -> f64;
+    #[link_name = "sin"]
+    fn c_sin(value: f64) -> f64;
+    #[link_name = "cos"]
+    fn c_cos(value: f64) -> f64;
+}
+
+pub fn abs(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(args.int(0).wrapping_abs())
+}
+
+pub fn min(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(args.int(0).min(args.int(1)))
+}
+
+pub fn max(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(args.int(0).max(args.int(1)))
+}
+
+/// Integer power with wrapping overflow; negative exponents give 0 as in integer division
+pub fn pow(args: &NativeArgs) -> NativeValue<'static> {
+    let (mut base, exponent) = (args.int(0), args.int(1));
+    if exponent < 0 {
+        return NativeValue::Int(match base {
+            1 => 1,
+            -1 => if exponent & 1 == 0 { 1 } else { -1 },
+            _ => 0,
+        })
+    }
+
+    // exponentiation by squaring
+    let (mut value, mut exponent) = (1i64, exponent as u64);
+    while exponent > 0 {
+        if exponent & 1 == 1 {
+            value = value.wrapping_mul(base);
+        }
+        base = base.wrapping_mul(base);
+        exponent >>= 1;
+    }
+    NativeValue::Int(value)
+}
+
+pub fn sqrt(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Float(unsafe { c_sqrt(args.float(0)) })
+}
+
+pub fn floor(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Float(unsafe { c_floor(args.float(0)) })
+}
+
+pub fn ceil(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Float(unsafe { c_ceil(args.float(0)) })
+}
+
+pub fn sin(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Float(unsafe { c_sin(args.float(0)) })
+}
+
+pub fn cos(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Float(unsafe { c_cos(args.float(0)) })
+}
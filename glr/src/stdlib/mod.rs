@@ -0,0 +1,247 @@
+use crate::bytecode::{Class, ClassFile, ClassLoader, ClassResult, ClassError, Const, ConstPool};
+use crate::bytecode::{Method, MethodKey, Native, NativeFn, NativeTarget, NativeSignature, NativeType, MAX_NATIVE_ARGS};
+use crate::bytecode::{TypeSize, LinkState, ACCESS_PUBLIC, ACCESS_STATIC, ACCESS_NATIVE, str_words, str_header, write_str};
+use crate::bytecode::{alloc, shrink, str_from_slot, CLASS_VERSION};
+use core::ptr::{null, null_mut};
+
+#[allow(dead_code)]
+pub mod io;
+#[allow(dead_code)]
+pub mod math;
+#[allow(dead_code)]
+pub mod string;
+#[allow(dead_code)]
+pub mod error;
+#[allow(dead_code)]
+pub mod map;
+#[allow(dead_code)]
+pub mod set;
+
+const INT: NativeType = NativeType::Num(TypeSize::I64);
+const FLOAT: NativeType = NativeType::Num(TypeSize::F64);
+const STR: NativeType = NativeType::Str;
+const VOID: NativeType = NativeType::Void;
+
+/// The string natives return when they fail, allocated by the latest loader like its consts
+static mut EMPTY: Option<&'static str> = None;
+
+pub struct Module {
+    pub name: &'static str,
+    pub functions: &'static [Function],
+}
+
+pub struct Function {
+    pub name: &'static str,
+    pub native_name: &'static str,
+    pub args: &'static [NativeType],
+    pub returns: NativeType,
+    pub function: NativeFn,
+}
+
+macro_rules! function {
+    ($module:literal, $name:literal ($($arg:expr),*) -> $returns:expr, $function:expr) => {
+        Function {
+            name: $name,
+            native_name: concat!($module, ".", $name),
+            args: &[$($arg),*],
+            returns: $returns,
+            function: $function,
+        }
+    };
+}
+
+pub static MODULES: [Module; 6] = [
+    Module {
+        name: "std.io",
+        functions: &[
+            function!("std.io", "print" (STR) -> VOID, io::print),
+            function!("std.io", "println" (STR) -> VOID, io::println),
+            function!("std.io", "print_int" (INT) -> VOID, io::print_int),
+            function!("std.io", "print_float" (FLOAT) -> VOID, io::print_float),
+            function!("std.io", "read_line" () -> STR, io::read_line),
+            function!("std.io", "read_file" (STR) -> STR, io::read_file),
+            function!("std.io", "write_file" (STR, STR) -> INT, io::write_file),
+        ],
+    },
+    Module {
+        name: "std.math",
+        functions: &[
+            function!("std.math", "abs" (INT) -> INT, math::abs),
+            function!("std.math", "min" (INT, INT) -> INT, math::min),
+            function!("std.math", "max" (INT, INT) -> INT, math::max),
+            function!("std.math", "pow" (INT, INT) -> INT, math::pow),
+            function!("std.math", "sqrt" (FLOAT) -> FLOAT, math::sqrt),
+            function!("std.math", "floor" (FLOAT) -> FLOAT, math::floor),
+            function!("std.math", "ceil" (FLOAT) -> FLOAT, math::ceil),
+            function!("std.math", "sin" (FLOAT) -> FLOAT, math::sin),
+            function!("std.math", "cos" (FLOAT) -> FLOAT, math::cos),
+        ],
+    },
+    Module {
+        name: "std.string",
+        functions: &[
+            function!("std.string", "len" (STR) -> INT, string::len),
+            function!("std.string", "eq" (STR, STR) -> INT, string::eq),
+            function!("std.string", "concat" (STR, STR) -> STR, string::concat),
+            function!("std.string", "slice" (STR, INT, INT) -> STR, string::slice),
+            function!("std.string", "find" (STR, STR) -> INT, string::find),
+            function!("std.string", "to_int" (STR) -> INT, string::to_int),
+            function!("std.string", "from_int" (INT) -> STR, string::from_int),
+        ],
+    },
+    Module {
+        name: "std.error",
+        functions: &[
+            function!("std.error", "message" (INT) -> STR, error::message),
+        ],
+    },
+    Module {
+        name: "std.map",
+        functions: &[
+            function!("std.map", "new_int" () -> INT, map::new_int),
+            function!("std.map", "new_str" () -> INT, map::new_str),
+            function!("std.map", "new_struct" () -> INT, map::new_struct),
+            function!("std.map", "len" (INT) -> INT, map::len),
+            function!("std.map", "has" (INT, INT) -> INT, map::has),
+            function!("std.map", "get" (INT, INT) -> INT, map::get),
+            function!("std.map", "get_or" (INT, INT, INT) -> INT, map::get_or),
+            function!("std.map", "put" (INT, INT, INT) -> VOID, map::put),
+            function!("std.map", "remove" (INT, INT) -> INT, map::remove),
+            function!("std.map", "keys" (INT) -> INT, map::keys),
+            function!("std.map", "values" (INT) -> INT, map::values),
+        ],
+    },
+    Module {
+        name: "std.set",
+        functions: &[
+            function!("std.set", "new_int" () -> INT, set::new_int),
+            function!("std.set", "new_str" () -> INT, set::new_str),
+            function!("std.set", "new_struct" () -> INT, set::new_struct),
+            function!("std.set", "len" (INT) -> INT, set::len),
+            function!("std.set", "has" (INT, INT) -> INT, set::has),
+            function!("std.set", "add" (INT, INT) -> INT, set::add),
+            function!("std.set", "remove" (INT, INT) -> INT, set::remove),
+            function!("std.set", "items" (INT) -> INT, set::items),
+        ],
+    },
+];
+
+/// Register every std function as a native and define each std module as a `Class::Module`
+pub fn register(loader: &mut ClassLoader) -> ClassResult<()> {
+    unsafe { EMPTY = Some(loader.alloc_str(&[])?) };
+
+    for module in MODULES.iter() {
+        for function in module.functions {
+            loader.register_native(function.native_name, function.function)?;
+        }
+        let class = define_module(loader, module)?;
+        loader.define_class(class)?;
+    }
+    Ok(())
+}
+
+/// Build the module's class directly: const 0 is the module name and
+/// const `n` the name of the `n`th function, each bound as a native method
+fn define_module(loader: &mut ClassLoader, module: &Module) -> ClassResult<*mut Class> {
+    unsafe {
+        let num_consts = 1 + module.functions.len();
+        let mut const_pool = ConstPool::new(loader.alloc_many(num_consts)?, num_consts);
+        let names = core::iter::once(module.name).chain(module.functions.iter().map(|function| function.name));
+        for (constant, name) in const_pool.as_slice_mut().iter_mut().zip(names) {
+            let name = loader.alloc_str(&[name.as_bytes()])?;
+            *constant = Const::Str(name.as_ptr(), name.len());
+        }
+
+        let name = loader.intern_const(&const_pool, 0)?;
+        let class = loader.alloc(Class::Module(ClassFile {
+            name,
+            version: CLASS_VERSION,
+            access: ACCESS_PUBLIC,
+            type_params: &[],
+            next_class: 0,
+            bytecode: null(),
+            code: null(),
+            code_size: 0,
+            const_pool,
+            source_file: None,
+            links: null_mut(),
+            state: LinkState::Linked,
+            fields: None,
+            methods: None,
+            impls: None,
+        }))?;
+
+        let mut methods = loader.alloc_mapping(module.functions.len())?;
+        for (index, function) in module.functions.iter().enumerate() {
+            let signature = NativeSignature::new(function.args, function.returns);
+            let native = loader.alloc(Native { target: NativeTarget::Rust(function.function), signature })?;
+
+            let descriptor = loader.alloc_str(&[signature.descriptor(&mut [0; MAX_NATIVE_ARGS + 3]).as_bytes()])?;
+            let key = MethodKey {
+                name: loader.intern_const(&const_pool, 1 + index)?,
+                descriptor: loader.intern(descriptor)?,
+            };
+            let method = loader.alloc(Method {
+                key,
+                access: ACCESS_PUBLIC | ACCESS_STATIC | ACCESS_NATIVE,
+                num_params: signature.num_args as u8,
+                max_locals: signature.num_args as u16,
+                max_stack: 0,
+                code_pos: 0,
+                slot: 0,
+                next_method: 0,
+                native: Some(native),
+                debug: None,
+                exceptions: None,
+                class,
+            })?;
+            methods.insert(method).map_err(|error| error.into_error(ClassError::DuplicateMember))?;
+        }
+
+        (*class).class_file_mut().methods = Some(methods);
+        Ok(class)
+    }
+}
+
+/// A laid out empty string for natives to return when they can't make the one they should
+pub fn empty_string() -> &'static str {
+    unsafe { EMPTY.expect("natives run after the std modules are registered") }
+}
+
+/// Allocate `parts` concatenated as a string on the heap for natives to return. The
+/// strings natives get as arguments stay alive while they allocate.
+pub fn new_string(parts: &[&[u8]]) -> Option<&'static str> {
+    unsafe {
+        let size = parts.iter().map(|part| part.len()).sum();
+        let header = alloc(str_words(size));
+        match header.is_null() {
+            true => None,
+            false => Some(write_str(header, parts)),
+        }
+    }
+}
+
+/// Allocate a string of up to `capacity` bytes filled in by `fill`, which returns
+/// how many it wrote, giving back the rest. Returns `None` if out of memory or the
+/// bytes aren't valid utf8.
+pub fn new_string_with<F: FnOnce(&mut [u8]) -> usize>(capacity: usize, fill: F) -> Option<&'static str> {
+    unsafe {
+        let header = alloc(str_words(capacity));
+        if header.is_null() {
+            return None
+        }
+
+        let bytes = core::slice::from_raw_parts_mut(header.add(2) as *mut u8, capacity);
+        let size = fill(bytes).min(capacity);
+        let valid = core::str::from_utf8(&bytes[..size]).is_ok();
+        let size = if valid { size } else { 0 };
+        *header = str_header(size);
+        *header.add(1) = size as u64;
+        *(header.add(2) as *mut u8).add(size) = 0;
+        shrink(header, str_words(capacity), str_words(size));
+        match valid {
+            true => Some(str_from_slot(header.add(2) as u64)),
+            false => None,
+        }
+    }
+}
@@ -0,0 +1,45 @@
+use crate::bytecode::{NativeArgs, NativeValue, KIND_SET, KEYS_INT, KEYS_STR, KEYS_STRUCT};
+use crate::bytecode::{new_map, as_map, map_len, map_find, map_insert, map_remove, map_items};
+
+// Sets are maps without values, passed around as i64 handles the same way
+
+pub fn new_int(_: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(unsafe { new_map(KIND_SET, KEYS_INT) } as u64)
+}
+
+pub fn new_str(_: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(unsafe { new_map(KIND_SET, KEYS_STR) } as u64)
+}
+
+pub fn new_struct(_: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(unsafe { new_map(KIND_SET, KEYS_STRUCT) } as u64)
+}
+
+pub fn len(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(set(args).map_or(0, |set| unsafe { map_len(set) }) as i64)
+}
+
+pub fn has(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(set(args).and_then(|set| unsafe { map_find(set, args.uint(1)) }).is_some() as i64)
+}
+
+/// Add the item, returning whether it was added: 0 if it was already in the set or out of memory
+pub fn add(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(set(args).and_then(|set| unsafe { map_insert(set, args.uint(1), 0) }).unwrap_or(false) as i64)
+}
+
+/// Remove the item, returning whether it was in the set
+pub fn remove(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(set(args).map_or(false, |set| unsafe { map_remove(set, args.uint(1)) }) as i64)
+}
+
+/// An array of the items, in no particular order
+pub fn items(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::UInt(set(args).map_or(0, |set| unsafe { map_items(set, 1) } as u64))
+}
+
+/// The set the first argument refers to. Anything else acts as an empty set.
+#[inline]
+fn set(args: &NativeArgs) -> Option<*mut u64> {
+    unsafe { as_map(args.uint(0), KIND_SET) }
+}
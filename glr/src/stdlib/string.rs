@@ -0,0 +1,58 @@
+use super::{new_string, empty_string};
+use crate::bytecode::{NativeArgs, NativeValue};
+use core::fmt::Write;
+
+pub fn len(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(args.str(0).len() as i64)
+}
+
+pub fn eq(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int((args.str(0) == args.str(1)) as i64)
+}
+
+pub fn concat(args: &NativeArgs) -> NativeValue<'static> {
+    let parts = [args.str(0).as_bytes(), args.str(1).as_bytes()];
+    NativeValue::Str(new_string(&parts).unwrap_or_else(empty_string))
+}
+
+/// Bytes `start..end` of the string, clamped to its length; empty if not on char boundaries
+pub fn slice(args: &NativeArgs) -> NativeValue<'static> {
+    let string = args.str(0);
+    let end = (args.int(2).max(0) as usize).min(string.len());
+    let start = (args.int(1).max(0) as usize).min(end);
+    let slice = string.get(start..end).unwrap_or("");
+    NativeValue::Str(new_string(&[slice.as_bytes()]).unwrap_or_else(empty_string))
+}
+
+/// Byte offset of the first occurrence of the second string in the first, or -1
+pub fn find(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(args.str(0).find(args.str(1)).map(|pos| pos as i64).unwrap_or(-1))
+}
+
+/// Parse a decimal integer, or 0 if the string isn't one
+pub fn to_int(args: &NativeArgs) -> NativeValue<'static> {
+    NativeValue::Int(args.str(0).trim().parse::<i64>().unwrap_or(0))
+}
+
+pub fn from_int(args: &NativeArgs) -> NativeValue<'static> {
+    let mut digits = Digits { size: 0, bytes: [0; 20] };
+    let _ = write!(digits, "{}", args.int(0));
+    NativeValue::Str(new_string(&[&digits.bytes[..digits.size]]).unwrap_or_else(empty_string))
+}
+
+/// Enough room to format any i64
+struct Digits {
+    size: usize,
+    bytes: [u8; 20],
+}
+
+impl Write for Digits {
+    fn write_str(&mut self, string: &str) -> core::fmt::Result {
+        let end = self.size + string.len();
+        self.bytes.get_mut(self.size..end)
+            .ok_or(core::fmt::Error)?
+            .copy_from_slice(string.as_bytes());
+        self.size = end;
+        Ok(())
+    }
+}
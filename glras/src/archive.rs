@@ -0,0 +1,130 @@
+//! Class archives (`.glra`) bundle class files into one file the VM maps into memory.
+//!
+//! ```text
+//! "$GLA" u8 version
+//! u16 entry class name size, entry class name (size 0 when there's no entry class)
+//! u32 number of classes
+//! per class: u16 name size, name, u8 compression, u32 offset, u32 size, u32 uncompressed size
+//! class data, each at its offset from the start of the archive
+//! ```
+
+use super::lz;
+use std::convert::TryFrom;
+use std::fs;
+
+const ARCHIVE_MAGIC: &[u8; 4] = b"$GLA";
+const ARCHIVE_VERSION: u8 = 1;
+const CLASS_MAGIC: &[u8; 4] = b"$GLR";
+
+const COMPRESSION_NONE: u8 = 0;
+const COMPRESSION_LZ: u8 = 1;
+
+const USAGE: &str = "usage: glras pack -o <archive.glra> [-e <entry class>] [-z] <class.glrc>...";
+
+struct Entry {
+    name: String,
+    compression: u8,
+    size: usize,
+    data: Vec<u8>,
+}
+
+/// `glras pack`: write the given class files into an archive
+pub fn pack(args: &[String]) -> Result<(), String> {
+    let mut output = None;
+    let mut entry_class = None;
+    let mut compress = false;
+    let mut paths = Vec::new();
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(args.next().ok_or(USAGE)?),
+            "-e" => entry_class = Some(args.next().ok_or(USAGE)?),
+            "-z" => compress = true,
+            path => paths.push(path),
+        }
+    }
+
+    let output = output.ok_or(USAGE)?;
+    if paths.is_empty() {
+        return Err(USAGE.to_string())
+    }
+
+    let mut entries = Vec::with_capacity(paths.len());
+    for path in paths {
+        let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+        let name = class_name(&bytes).ok_or_else(|| format!("{}: not a class file", path))?;
+        if entries.iter().any(|entry: &Entry| entry.name == name) {
+            return Err(format!("{}: class {} is already in the archive", path, name))
+        }
+
+        let compressed = if compress { Some(lz::compress(&bytes)) } else { None };
+        entries.push(match compressed {
+            Some(data) if data.len() < bytes.len() => Entry { name, compression: COMPRESSION_LZ, size: bytes.len(), data },
+            _ => Entry { name, compression: COMPRESSION_NONE, size: bytes.len(), data: bytes },
+        });
+    }
+
+    if let Some(entry_class) = entry_class {
+        if !entries.iter().any(|entry| &entry.name == entry_class) {
+            return Err(format!("entry class {} is not in the archive", entry_class))
+        }
+    }
+
+    let archive = write(entry_class.map_or("", String::as_str), &entries)?;
+    fs::write(output, archive).map_err(|error| format!("{}: {}", output, error))
+}
+
+fn write(entry_class: &str, entries: &[Entry]) -> Result<Vec<u8>, String> {
+    let index_size: usize = entries.iter().map(|entry| 2 + entry.name.len() + 13).sum();
+    let mut offset = ARCHIVE_MAGIC.len() + 1 + 2 + entry_class.len() + 4 + index_size;
+
+    let mut archive = Vec::new();
+    archive.extend_from_slice(ARCHIVE_MAGIC);
+    archive.push(ARCHIVE_VERSION);
+    write_name(&mut archive, entry_class)?;
+    archive.extend_from_slice(&(entries.len() as u32).to_le_bytes());
+
+    for entry in entries {
+        write_name(&mut archive, &entry.name)?;
+        archive.push(entry.compression);
+        for value in &[offset, entry.data.len(), entry.size] {
+            let value = u32::try_from(*value).map_err(|_| "archive is larger than 4gb")?;
+            archive.extend_from_slice(&value.to_le_bytes());
+        }
+        offset += entry.data.len();
+    }
+
+    for entry in entries {
+        archive.extend_from_slice(&entry.data);
+    }
+    Ok(archive)
+}
+
+fn write_name(archive: &mut Vec<u8>, name: &str) -> Result<(), String> {
+    let size = u16::try_from(name.len()).map_err(|_| format!("class name {} is too long", name))?;
+    archive.extend_from_slice(&size.to_le_bytes());
+    archive.extend_from_slice(name.as_bytes());
+    Ok(())
+}
+
+/// A class is named by the string at const 0, which follows the magic, the u16 major
+/// and minor versions, class type, access byte and const count. A string's type byte
+/// holds the `TypeSize` of its size in the upper 3 bits and 1 in the lower ones.
+fn class_name(bytes: &[u8]) -> Option<String> {
+    if bytes.get(..4)? != CLASS_MAGIC {
+        return None
+    }
+
+    let type_byte = *bytes.get(12)?;
+    let size_bytes = match (type_byte >> 5, type_byte & 0b111) {
+        (type_size @ 0..=3, 1) => 1 << type_size,
+        _ => return None,
+    };
+
+    let mut size = [0u8; 8];
+    size[..size_bytes].copy_from_slice(bytes.get(13..13 + size_bytes)?);
+    let start = 13 + size_bytes;
+    let name = bytes.get(start..start + u64::from_le_bytes(size) as usize)?;
+    String::from_utf8(name.to_vec()).ok()
+}
@@ -0,0 +1,646 @@
+//! `glras asm`: assemble a class file from a listing, with a debug section mapping its
+//! bytecode to source lines and its local slots to names.
+//!
+//! ```text
+//! ; comments run from a semicolon starting a word to the end of the line
+//! public module Main                   ; [modifiers] enum|struct|module|interface Name[<T, ...>]
+//! source "main.gl"                     ; the file .line directives refer to
+//! implements Shape                     ; an interface a struct implements
+//! field mutable count                  ; a module field
+//! field x: q                           ; a struct field and its type
+//! field Some(value: TT;)               ; an enum variant and its fields
+//! method native public static abs(q)q  ; natives and interface methods have no code
+//! method public static main()q locals 1 stack 8
+//!     .local 0 x                       ; slot 0 is named x
+//!     .line 3                          ; what follows came from line 3 of the source
+//!     .catch std.error start end fail 1  ; catch std.error (or any) thrown from start
+//! start:                               ; to end at fail, keeping 1 slot of the stack
+//!     push 7
+//!     store 0
+//!     invoke Main.half(q)q 1           ; a member is its class, name and descriptor
+//! end:
+//!     ret
+//! fail:
+//!     ...
+//! ```
+//!
+//! Instructions are written as `glras disasm` prints them, with labels in place of code
+//! positions and the consts they use in place of const indices: `str "text"`, `new Point`,
+//! `getstatic Main.count`, `invoke std.map.put 3`. Without a `source` line, the lines of
+//! the debug section are those of the listing itself.
+
+use super::class::{ClassFile, Const, Field, FieldKind, Method, MethodBody, Writer, count};
+use super::class::{ACCESS_NAMES, CATCH_ALL, CLASS_TYPE_INTERFACE, CLASS_TYPE_NAMES, CLASS_VERSION_MINOR, NO_DESCRIPTOR};
+use super::class::{DEBUG_SECTION, EXCEPTIONS_SECTION, IMPLS_SECTION};
+use super::disasm::{ELEMENT_TYPES, OPCODES};
+use std::fs;
+use std::path::Path;
+
+const USAGE: &str = "usage: glras asm [-o <class.glrc>] <listing.glrs>";
+
+const CLASS_VERSION_MAJOR: u16 = 4;
+const ACCESS_NATIVE: u8 = 1 << 3;
+
+/// Stack slots of methods that don't give theirs
+const DEFAULT_MAX_STACK: u16 = 16;
+
+struct Handler {
+    line: usize,
+    error_class: u16,
+    start: String,
+    end: String,
+    handler: String,
+    stack_depth: u16,
+}
+
+/// What the debug and exceptions sections say about a method with code
+struct MethodInfo {
+    name: u16,
+    descriptor: u16,
+    lines: Vec<(u32, u32)>,
+    locals: Vec<(u16, u16)>,
+    handlers: Vec<Handler>,
+}
+
+struct Assembler {
+    class: ClassFile,
+    impls: Vec<u16>,
+    source: Option<u16>,
+    methods: Vec<MethodInfo>,
+    labels: Vec<(String, u32)>,
+    /// jump and call targets to patch once every label is known
+    fixups: Vec<(usize, usize, String)>,
+}
+
+/// `glras asm`: assemble a listing into a class file, next to it unless `-o` names one
+pub fn asm(args: &[String]) -> Result<(), String> {
+    let (path, output) = match args {
+        [path] => (path, Path::new(path).with_extension("glrc")),
+        [flag, output, path] if flag == "-o" => (path, output.into()),
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let listing = fs::read_to_string(path).map_err(|error| format!("{}: {}", path, error))?;
+    let file_name = Path::new(path).file_name().map_or(path.as_str(), |name| name.to_str().unwrap_or(path));
+    let class = assemble(&listing, file_name).map_err(|error| format!("{}:{}", path, error))?;
+    let bytes = class.write().map_err(|error| format!("{}: {}", path, error))?;
+    fs::write(&output, bytes).map_err(|error| format!("{}: {}", output.display(), error))
+}
+
+/// The class a listing describes. Errors start with the line number they were found on,
+/// and `file_name` is the source file of a listing without a `source` line.
+pub fn assemble(listing: &str, file_name: &str) -> Result<ClassFile, String> {
+    let mut lines = listing.lines().enumerate()
+        .map(|(index, line)| (index + 1, strip_comment(line).trim()))
+        .filter(|(_, line)| !line.is_empty());
+
+    let (line, header) = lines.next().ok_or("1: expected a class header")?;
+    let mut asm = Assembler::new(header).map_err(|error| format!("{}: {}", line, error))?;
+    for (line, text) in lines {
+        asm.line(line, text).map_err(|error| format!("{}: {}", line, error))?;
+    }
+    asm.finish(file_name)
+}
+
+impl Assembler {
+    fn new(header: &str) -> Result<Self, String> {
+        let (access, rest) = modifiers(header)?;
+        let (kind, name) = split_word(rest);
+        let class_type = CLASS_TYPE_NAMES.iter().position(|name| *name == kind)
+            .ok_or("expected a class header: [modifiers] enum|struct|module|interface Name")?;
+
+        let mut asm = Assembler {
+            class: ClassFile {
+                major: CLASS_VERSION_MAJOR,
+                minor: CLASS_VERSION_MINOR,
+                class_type: class_type as u8,
+                access,
+                consts: Vec::new(),
+                type_params: Vec::new(),
+                fields: Vec::new(),
+                methods: Vec::new(),
+                code: Vec::new(),
+                sections: Vec::new(),
+            },
+            impls: Vec::new(),
+            source: None,
+            methods: Vec::new(),
+            labels: Vec::new(),
+            fixups: Vec::new(),
+        };
+
+        // the class is named by its first const
+        let (name, params) = match name.find('<') {
+            Some(start) if name.ends_with('>') => (&name[..start], Some(&name[start + 1..name.len() - 1])),
+            Some(_) => return Err(format!("bad type parameters in {}", name)),
+            None => (name, None),
+        };
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err("expected a class name".to_string())
+        }
+        asm.string(name);
+        for param in params.iter().flat_map(|params| params.split(',')) {
+            let param = asm.string(param.trim());
+            asm.class.type_params.push(param);
+        }
+        Ok(asm)
+    }
+
+    fn line(&mut self, line: usize, text: &str) -> Result<(), String> {
+        let (keyword, rest) = split_word(text);
+        match keyword {
+            "source" => self.source = Some(self.string(&unquote(rest)?)),
+            "implements" => {
+                let interface = self.class_const(rest);
+                self.impls.push(interface);
+            },
+            "field" => self.field(rest)?,
+            "method" => self.method(rest)?,
+            label if label.ends_with(':') && rest.is_empty() => {
+                let label = &label[..label.len() - 1];
+                if self.labels.iter().any(|(name, _)| name == label) {
+                    return Err(format!("label {} is already defined", label))
+                }
+                self.labels.push((label.to_string(), self.class.code.len() as u32));
+            },
+            directive if directive.starts_with('.') => self.directive(line, directive, rest)?,
+            mnemonic => self.instruction(line, mnemonic, rest)?,
+        }
+        Ok(())
+    }
+
+    fn field(&mut self, text: &str) -> Result<(), String> {
+        let (access, rest) = modifiers(text)?;
+        let (name, kind) = match (rest.find('('), rest.find(':')) {
+            (Some(start), _) if rest.ends_with(')') => {
+                let fields = rest[start + 1..rest.len() - 1].split(',')
+                    .filter(|field| !field.trim().is_empty())
+                    .map(|field| self.typed(field))
+                    .collect::<Result<_, _>>()?;
+                (&rest[..start], FieldKind::Enum(fields))
+            },
+            (None, Some(_)) => {
+                let (name, field_type) = self.typed(rest)?;
+                self.class.fields.push(Field { name, access, kind: FieldKind::Struct(field_type) });
+                return Ok(())
+            },
+            (None, None) => (rest, FieldKind::Module),
+            _ => return Err(format!("bad field {}", rest)),
+        };
+        let name = self.string(name.trim());
+        self.class.fields.push(Field { name, access, kind });
+        Ok(())
+    }
+
+    /// A `name: type` pair
+    fn typed(&mut self, text: &str) -> Result<(u16, u16), String> {
+        let colon = text.find(':').ok_or_else(|| format!("expected name: type, found {}", text.trim()))?;
+        Ok((self.string(text[..colon].trim()), self.string(text[colon + 1..].trim())))
+    }
+
+    fn method(&mut self, text: &str) -> Result<(), String> {
+        let (access, rest) = modifiers(text)?;
+        let (signature, rest) = split_word(rest);
+        let mut words = rest.split_whitespace();
+        let paren = signature.find('(').ok_or_else(|| format!("expected a descriptor after {}", signature))?;
+        let name = self.string(&signature[..paren]);
+        let descriptor = self.string(&signature[paren..]);
+
+        let (mut max_locals, mut max_stack) = (0, DEFAULT_MAX_STACK);
+        while let Some(word) = words.next() {
+            let value = words.next().ok_or_else(|| format!("expected a number after {}", word))?;
+            match word {
+                "locals" => max_locals = number(value)?,
+                "stack" => max_stack = number(value)?,
+                _ => return Err(format!("unexpected {} after the descriptor", word)),
+            }
+        }
+
+        let body = if self.class.class_type == CLASS_TYPE_INTERFACE {
+            MethodBody::Abstract
+        } else if access & ACCESS_NATIVE != 0 {
+            MethodBody::Native
+        } else {
+            self.methods.push(MethodInfo { name, descriptor, lines: Vec::new(), locals: Vec::new(), handlers: Vec::new() });
+            MethodBody::Code { max_locals, max_stack, code_pos: self.class.code.len() as u64 }
+        };
+        self.class.methods.push(Method { name, descriptor, access, body });
+        Ok(())
+    }
+
+    fn directive(&mut self, line: usize, directive: &str, text: &str) -> Result<(), String> {
+        if self.methods.is_empty() {
+            return Err(format!("{} outside a method", directive))
+        }
+
+        let pos = self.class.code.len() as u32;
+        match (directive, &text.split_whitespace().collect::<Vec<_>>()[..]) {
+            (".line", [source_line]) => {
+                let source_line = number(source_line)?;
+                self.current().lines.push((pos, source_line));
+            },
+            (".local", [slot, name]) => {
+                let (slot, name) = (number(slot)?, self.string(name));
+                self.current().locals.push((slot, name));
+            },
+            (".catch", [error_class, start, end, handler, stack_depth]) => {
+                let error_class = match *error_class {
+                    "any" => CATCH_ALL,
+                    error_class => self.class_const(error_class),
+                };
+                let (start, end, handler) = (start.to_string(), end.to_string(), handler.to_string());
+                let stack_depth = number(stack_depth)?;
+                self.current().handlers.push(Handler { line, error_class, start, end, handler, stack_depth });
+            },
+            (".line", _) => return Err("usage: .line <source line>".to_string()),
+            (".local", _) => return Err("usage: .local <slot> <name>".to_string()),
+            (".catch", _) => return Err("usage: .catch <class | any> <start> <end> <handler> <kept slots>".to_string()),
+            _ => return Err(format!("unknown directive {}", directive)),
+        }
+        Ok(())
+    }
+
+    /// The method being assembled
+    fn current(&mut self) -> &mut MethodInfo {
+        self.methods.last_mut().expect("no method to assemble")
+    }
+
+    fn instruction(&mut self, line: usize, mnemonic: &str, text: &str) -> Result<(), String> {
+        let opcode = OPCODES.iter().position(|(name, _)| *name == mnemonic)
+            .ok_or_else(|| format!("unknown instruction {}", mnemonic))?;
+        if self.methods.is_empty() {
+            return Err(format!("{} outside a method", mnemonic))
+        }
+
+        // without a source file, each instruction comes from its line of the listing
+        let pos = self.class.code.len();
+        if self.source.is_none() {
+            self.current().lines.push((pos as u32, line as u32));
+        }
+
+        let words: Vec<_> = text.split_whitespace().collect();
+        let mut code = Writer(vec![opcode as u8]);
+        match (mnemonic, &words[..]) {
+            ("push", [value]) => code.u64(value.parse::<i64>().map_err(|_| format!("bad number {}", value))? as u64),
+            ("load", [slot]) | ("store", [slot]) | ("enter", [slot]) | ("capture", [slot]) => code.u16(number(slot)?),
+            ("callclosure", [num_args]) => code.u8(number(num_args)?),
+            ("newarray", [elements]) => code.u8(ELEMENT_TYPES.iter().position(|name| name == elements)
+                .ok_or_else(|| format!("unknown element type {}", elements))? as u8),
+            ("jmp", [target]) | ("jz", [target]) => {
+                self.fixups.push((line, pos + 1, target.to_string()));
+                code.u32(0);
+            },
+            ("call", [target, num_args]) => {
+                self.fixups.push((line, pos + 1, target.to_string()));
+                code.u32(0);
+                code.u8(number(num_args)?);
+            },
+            ("str", _) => code.u16(self.string(&unquote(text)?)),
+            ("native", [index]) => code.u16(number(index)?),
+            ("throw", [class]) | ("new", [class]) => code.u16(self.class_const(class)),
+            ("getstatic", [member]) | ("putstatic", [member]) => code.u16(self.member(member)?),
+            ("invoke", [member, num_args]) | ("invokeinterface", [member, num_args]) | ("closure", [member, num_args]) => {
+                code.u16(self.member(member)?);
+                code.u8(number(num_args)?);
+            },
+            (_, []) if OPCODES[opcode].1 == 0 => {},
+            _ => return Err(format!("bad operands for {}", mnemonic)),
+        }
+        self.class.code.extend_from_slice(&code.0);
+        Ok(())
+    }
+
+    fn finish(mut self, file_name: &str) -> Result<ClassFile, String> {
+        for (line, pos, label) in &self.fixups {
+            let target = self.target(*line, label)?;
+            self.class.code[*pos..*pos + 4].copy_from_slice(&target.to_le_bytes());
+        }
+
+        if !self.impls.is_empty() {
+            let mut impls = Writer(Vec::new());
+            impls.u16(count(self.impls.len(), "interfaces")?);
+            for interface in &self.impls {
+                impls.u16(*interface);
+            }
+            self.section(IMPLS_SECTION, impls.0);
+        }
+
+        let methods: Vec<_> = self.methods.iter().filter(|method| !method.handlers.is_empty()).collect();
+        if !methods.is_empty() {
+            let mut exceptions = Writer(Vec::new());
+            exceptions.u16(count(methods.len(), "methods with handlers")?);
+            for method in methods {
+                exceptions.u16(method.name);
+                exceptions.u16(method.descriptor);
+                exceptions.u16(count(method.handlers.len(), "handlers")?);
+                for handler in &method.handlers {
+                    for label in &[&handler.start, &handler.end, &handler.handler] {
+                        exceptions.u32(self.target(handler.line, label)?);
+                    }
+                    exceptions.u16(handler.error_class);
+                    exceptions.u16(handler.stack_depth);
+                }
+            }
+            let exceptions = exceptions.0;
+            self.section(EXCEPTIONS_SECTION, exceptions);
+        }
+
+        // the VM only keeps lines for code that exists, and each one up to the next
+        let code_size = self.class.code.len() as u32;
+        let source = match self.source {
+            Some(source) => source,
+            None => self.string(file_name),
+        };
+        let mut debug = Writer(Vec::new());
+        debug.u16(source);
+        debug.u16(count(self.methods.len(), "methods")?);
+        for method in &self.methods {
+            let lines: Vec<_> = method.lines.iter().filter(|(pos, _)| *pos < code_size).collect();
+            debug.u16(method.name);
+            debug.u16(method.descriptor);
+            debug.u32(lines.len() as u32);
+            for (pos, line) in lines {
+                debug.u32(*pos);
+                debug.u32(*line);
+            }
+            debug.u16(count(method.locals.len(), "locals")?);
+            for (slot, name) in &method.locals {
+                debug.u16(*slot);
+                debug.u16(*name);
+            }
+        }
+        self.section(DEBUG_SECTION, debug.0);
+        Ok(self.class)
+    }
+
+    /// The code position of a label, or a number given in its place
+    fn target(&self, line: usize, label: &str) -> Result<u32, String> {
+        match self.labels.iter().find(|(name, _)| name == label) {
+            Some((_, pos)) => Ok(*pos),
+            None => label.parse().map_err(|_| format!("{}: undefined label {}", line, label)),
+        }
+    }
+
+    fn section(&mut self, name: &str, data: Vec<u8>) {
+        let name = self.string(name);
+        self.class.sections.push((name, data));
+    }
+
+    /// The index of a const, adding it unless the pool already has it
+    fn add(&mut self, constant: Const) -> u16 {
+        let found = self.class.consts.iter().position(|existing| match (existing, &constant) {
+            (Const::Str(a), Const::Str(b)) => a == b,
+            (Const::Class(a), Const::Class(b)) => a == b,
+            (Const::Member(a, b, c), Const::Member(d, e, f)) => (a, b, c) == (d, e, f),
+            _ => false,
+        });
+        match found {
+            Some(index) => index as u16,
+            None => {
+                self.class.consts.push(constant);
+                self.class.consts.len() as u16 - 1
+            },
+        }
+    }
+
+    fn string(&mut self, string: &str) -> u16 {
+        self.add(Const::Str(string.to_string()))
+    }
+
+    fn class_const(&mut self, name: &str) -> u16 {
+        let name = self.string(name);
+        self.add(Const::Class(name))
+    }
+
+    /// A member written as `Class.name` or, for a method, `Class.name(params)ret`
+    fn member(&mut self, text: &str) -> Result<u16, String> {
+        let (path, descriptor) = match text.find('(') {
+            Some(paren) => (&text[..paren], Some(&text[paren..])),
+            None => (text, None),
+        };
+        let dot = path.rfind('.').ok_or_else(|| format!("expected Class.member, found {}", text))?;
+        let class = self.class_const(&path[..dot]);
+        let name = self.string(&path[dot + 1..]);
+        let descriptor = descriptor.map_or(NO_DESCRIPTOR, |descriptor| self.string(descriptor));
+        Ok(self.add(Const::Member(class, name, descriptor)))
+    }
+}
+
+/// The access modifiers at the start of `text`, and the rest of it
+fn modifiers(text: &str) -> Result<(u8, &str), String> {
+    let (mut access, mut rest) = (0, text);
+    loop {
+        let (word, after) = split_word(rest);
+        match ACCESS_NAMES.iter().position(|name| *name == word) {
+            Some(bit) if access & 1 << bit != 0 => return Err(format!("{} is given twice", word)),
+            Some(bit) => access |= 1 << bit,
+            None => return Ok((access, rest)),
+        }
+        rest = after;
+    }
+}
+
+fn number<T: std::str::FromStr>(text: &str) -> Result<T, String> {
+    text.parse().map_err(|_| format!("bad number {}", text))
+}
+
+fn split_word(text: &str) -> (&str, &str) {
+    match text.find(char::is_whitespace) {
+        Some(end) => (&text[..end], text[end..].trim()),
+        None => (text, ""),
+    }
+}
+
+/// The line up to a `;` outside of a string that starts it or follows a space, as the
+/// `;` ending class names in descriptors never does
+fn strip_comment(line: &str) -> &str {
+    let mut quoted = false;
+    let mut escaped = false;
+    let mut spaced = true;
+    for (pos, char) in line.char_indices() {
+        match char {
+            _ if escaped => escaped = false,
+            '\\' if quoted => escaped = true,
+            '"' => quoted = !quoted,
+            ';' if !quoted && spaced => return &line[..pos],
+            _ => {},
+        }
+        spaced = char.is_whitespace();
+    }
+    line
+}
+
+/// The contents of a string literal, with `\"`, `\\`, `\n` and `\t` escapes
+fn unquote(text: &str) -> Result<String, String> {
+    if text.len() < 2 || !text.starts_with('"') || !text.ends_with('"') {
+        return Err(format!("expected a string in quotes, found {}", text))
+    }
+
+    let mut string = String::new();
+    let mut chars = text[1..text.len() - 1].chars();
+    while let Some(char) = chars.next() {
+        string.push(match char {
+            '\\' => match chars.next() {
+                Some('n') => '\n',
+                Some('t') => '\t',
+                Some(char @ '"') | Some(char @ '\\') => char,
+                _ => return Err(format!("bad escape in {}", text)),
+            },
+            char => char,
+        });
+    }
+    Ok(string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::assemble;
+    use crate::class::{ClassFile, Const, FieldKind, MethodBody, CATCH_ALL};
+
+    /// The class assembled from `listing`, written out and read back
+    fn round_trip(listing: &str) -> ClassFile {
+        let class = assemble(listing, "test.glrs").unwrap();
+        ClassFile::read(&class.write().unwrap()).unwrap()
+    }
+
+    fn str(class: &ClassFile, index: u16) -> &str {
+        class.str(index).unwrap()
+    }
+
+    #[test]
+    fn lines_and_locals_of_the_source() {
+        let class = round_trip(r#"
+            public module Main
+            source "main.gl"
+            method public static main()q locals 1
+                .local 0 total
+                .line 4
+                push 2
+                store 0
+                .line 7
+                load 0
+                ret
+        "#);
+
+        let debug = class.debug_info().unwrap().unwrap();
+        assert_eq!(str(&class, debug.source_file), "main.gl");
+        assert_eq!(debug.methods.len(), 1);
+        let method = &debug.methods[0];
+        assert_eq!((str(&class, method.name), str(&class, method.descriptor)), ("main", "()q"));
+        assert_eq!(method.lines, [(0, 4), (12, 7)]);
+        assert_eq!(method.locals.len(), 1);
+        assert_eq!((method.locals[0].0, str(&class, method.locals[0].1)), (0, "total"));
+    }
+
+    #[test]
+    fn lines_of_the_listing() {
+        let class = round_trip("module Main\nmethod main()q\n    push 1\n\n    ret ; done\n");
+        let debug = class.debug_info().unwrap().unwrap();
+        assert_eq!(str(&class, debug.source_file), "test.glrs");
+        assert_eq!(debug.methods[0].lines, [(0, 3), (9, 5)]);
+    }
+
+    #[test]
+    fn labels_and_handlers() {
+        let class = round_trip(r#"
+            module Main
+            method main()q locals 1
+                .catch std.error start end fail 1
+                .catch any start end 0 0
+            start:
+                push 0
+                jz end
+                str "a;\"b\""
+                pop
+            end:
+                ret
+            fail:
+                jmp start
+        "#);
+
+        assert_eq!(&class.code[9..14], &[13, 18, 0, 0, 0]);
+        assert_eq!(&class.code[19..24], &[12, 0, 0, 0, 0]);
+        match class.consts.get(class.code[15] as usize) {
+            Some(Const::Str(string)) => assert_eq!(string, "a;\"b\""),
+            _ => panic!("str doesn't load a string const"),
+        }
+
+        let exceptions = class.exceptions().unwrap();
+        assert_eq!(exceptions.len(), 1);
+        let handlers = &exceptions[0].2;
+        assert_eq!((handlers[0].start, handlers[0].end, handlers[0].handler, handlers[0].stack_depth), (0, 18, 19, 1));
+        assert_eq!((handlers[1].error_class, handlers[1].handler), (CATCH_ALL, 0));
+        match class.consts.get(handlers[0].error_class as usize) {
+            Some(Const::Class(name)) => assert_eq!(str(&class, *name), "std.error"),
+            _ => panic!("handler doesn't name a class const"),
+        }
+    }
+
+    #[test]
+    fn members_and_consts() {
+        let class = round_trip(r#"
+            module Main
+            field mutable count
+            method main()q
+                invoke std.map.put 3
+                invoke Main.main()q 0
+                getstatic Main.count
+                invoke std.map.put 3
+                ret
+        "#);
+
+        let members = class.consts.iter().filter(|constant| matches!(constant, Const::Member(..))).count();
+        assert_eq!(members, 3);
+        assert_eq!(class.code[1..3], class.code[12..14]);
+        assert_eq!((str(&class, class.fields[0].name), class.fields[0].access), ("count", 1 << 5));
+    }
+
+    #[test]
+    fn structs_enums_and_interfaces() {
+        let class = round_trip("public final struct Box<T, U>\nimplements Shape\nfield x: q\nfield private value: TT;\n");
+        assert_eq!(class.access, 1 | 1 << 4);
+        assert_eq!(class.type_params.iter().map(|param| str(&class, *param)).collect::<Vec<_>>(), ["T", "U"]);
+        assert_eq!(class.impls().unwrap().len(), 1);
+        assert_eq!((str(&class, class.fields[1].name), class.fields[1].access), ("value", 1 << 1));
+        match class.fields[1].kind {
+            FieldKind::Struct(field_type) => assert_eq!(str(&class, field_type), "TT;"),
+            _ => panic!("not a struct field"),
+        }
+
+        let class = round_trip("enum Option<T>\nfield None()\nfield Some(value: TT;)\n");
+        match &class.fields[1].kind {
+            FieldKind::Enum(fields) => assert_eq!((str(&class, fields[0].0), str(&class, fields[0].1)), ("value", "TT;")),
+            _ => panic!("not an enum field"),
+        }
+
+        let class = round_trip("module Math\nmethod native public static max(qq)q\nmethod main()q locals 2 stack 4\n    ret\n");
+        assert_eq!(str(&class, class.methods[0].descriptor), "(qq)q");
+        match class.methods[0].body {
+            MethodBody::Native => {},
+            _ => panic!("not a native"),
+        }
+        match class.methods[1].body {
+            MethodBody::Code { max_locals, max_stack, code_pos } => assert_eq!((max_locals, max_stack, code_pos), (2, 4, 0)),
+            _ => panic!("not a method with code"),
+        }
+
+        let class = round_trip("interface Shape\nmethod public area()q\n");
+        match class.methods[0].body {
+            MethodBody::Abstract => {},
+            _ => panic!("not an interface method"),
+        }
+    }
+
+    #[test]
+    fn errors_name_their_line() {
+        let error = |listing| assemble(listing, "test.glrs").err().unwrap();
+        assert_eq!(error("\n\nclass Main"), "3: expected a class header: [modifiers] enum|struct|module|interface Name");
+        assert_eq!(error("module Main\npush 1"), "2: push outside a method");
+        assert_eq!(error("module Main\nfield mutable mutable count"), "2: mutable is given twice");
+        assert_eq!(error("module Main\nmethod main()q\n  frob"), "3: unknown instruction frob");
+        assert_eq!(error("module Main\nmethod main()q\n  jmp nowhere"), "3: undefined label nowhere");
+        assert_eq!(error("module Main\nmethod main()q\n  push"), "3: bad operands for push");
+        assert_eq!(error("module Main\nmethod main locals 1"), "2: expected a descriptor after main");
+        assert_eq!(error("module Main\nmethod main()q\n  .catch any a b"), "3: usage: .catch <class | any> <start> <end> <handler> <kept slots>");
+    }
+}
@@ -0,0 +1,445 @@
+//! Reading and writing class files (`.glrc`) as laid out by the VM's class loader
+
+use std::convert::{TryFrom, TryInto};
+
+const CLASS_MAGIC: &[u8; 4] = b"$GLR";
+const CLASS_VERSION_MAJOR: u16 = 4;
+pub const CLASS_VERSION_MINOR: u16 = 3;
+const ACCESS_NATIVE: u8 = 1 << 3;
+
+/// Names of the access modifier bits, from the lowest bit up
+pub const ACCESS_NAMES: [&str; 6] = ["public", "private", "static", "native", "final", "mutable"];
+
+pub const CLASS_TYPE_ENUM: u8 = 0;
+pub const CLASS_TYPE_STRUCT: u8 = 1;
+pub const CLASS_TYPE_MODULE: u8 = 2;
+pub const CLASS_TYPE_INTERFACE: u8 = 3;
+
+/// Names of the class types, indexed by type
+pub const CLASS_TYPE_NAMES: [&str; 4] = ["enum", "struct", "module", "interface"];
+
+// the low bits of a const's type byte, below the `TypeSize` of its number or string size
+const CONST_KIND_NUM: u8 = 0;
+const CONST_KIND_STR: u8 = 1;
+const CONST_KIND_CLASS: u8 = 2;
+const CONST_KIND_MEMBER: u8 = 3;
+
+// `TypeSize`s, which the upper 3 bits of a number's type byte hold
+const TYPE_SIZE_U16: u8 = 1;
+const TYPE_SIZE_U32: u8 = 2;
+const TYPE_SIZE_U64: u8 = 3;
+const TYPE_SIZE_I64: u8 = 5;
+const TYPE_SIZE_F64: u8 = 7;
+
+pub const DEBUG_SECTION: &str = "debug";
+pub const EXCEPTIONS_SECTION: &str = "exceptions";
+pub const IMPLS_SECTION: &str = "impls";
+
+/// Error class index of handlers catching every error
+pub const CATCH_ALL: u16 = 0xffff;
+
+/// Descriptor index of member consts that don't name a method by descriptor
+pub const NO_DESCRIPTOR: u16 = 0xffff;
+
+pub enum Const {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Str(String),
+    Class(u16),
+    Member(u16, u16, u16),
+}
+
+pub struct Field {
+    pub name: u16,
+    pub access: u8,
+    pub kind: FieldKind,
+}
+
+pub enum FieldKind {
+    Module,
+    Struct(u16),
+    /// the name and type of each of the variant's fields
+    Enum(Vec<(u16, u16)>),
+}
+
+pub struct Method {
+    pub name: u16,
+    pub descriptor: u16,
+    pub access: u8,
+    pub body: MethodBody,
+}
+
+pub enum MethodBody {
+    Code { max_locals: u16, max_stack: u16, code_pos: u64 },
+    Native,
+    Abstract,
+}
+
+pub struct ClassFile {
+    pub major: u16,
+    pub minor: u16,
+    pub class_type: u8,
+    pub access: u8,
+    pub consts: Vec<Const>,
+    pub type_params: Vec<u16>,
+    pub fields: Vec<Field>,
+    pub methods: Vec<Method>,
+    pub code: Vec<u8>,
+    pub sections: Vec<(u16, Vec<u8>)>,
+}
+
+pub struct MethodDebug {
+    pub name: u16,
+    pub descriptor: u16,
+    pub lines: Vec<(u32, u32)>,
+    pub locals: Vec<(u16, u16)>,
+}
+
+pub struct DebugInfo {
+    pub source_file: u16,
+    pub methods: Vec<MethodDebug>,
+}
+
+pub struct ErrorHandler {
+    pub start: u32,
+    pub end: u32,
+    pub handler: u32,
+    pub error_class: u16,
+    pub stack_depth: u16,
+}
+
+/// Little endian cursor over a byte slice
+pub struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, pos: 0 }
+    }
+
+    pub fn bytes(&mut self, size: usize) -> Result<&'a [u8], String> {
+        let bytes = self.bytes.get(self.pos..self.pos + size)
+            .ok_or_else(|| format!("unexpected end of data at offset {}", self.pos))?;
+        self.pos += size;
+        Ok(bytes)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        Ok(u16::from_le_bytes(self.bytes(2)?.try_into().unwrap()))
+    }
+
+    pub fn u32(&mut self) -> Result<u32, String> {
+        Ok(u32::from_le_bytes(self.bytes(4)?.try_into().unwrap()))
+    }
+
+    pub fn u64(&mut self) -> Result<u64, String> {
+        Ok(u64::from_le_bytes(self.bytes(8)?.try_into().unwrap()))
+    }
+
+    /// A number whose `TypeSize` is in the upper 3 bits of `type_byte`
+    fn number(&mut self, type_byte: u8) -> Result<Const, String> {
+        Ok(match type_byte >> 5 {
+            0 => Const::UInt(self.u8()? as u64),
+            1 => Const::UInt(self.u16()? as u64),
+            2 => Const::UInt(self.u32()? as u64),
+            3 => Const::UInt(self.u64()?),
+            4 => Const::Int(self.u32()? as i32 as i64),
+            5 => Const::Int(self.u64()? as i64),
+            6 => Const::Float(f32::from_bits(self.u32()?) as f64),
+            _ => Const::Float(f64::from_bits(self.u64()?)),
+        })
+    }
+}
+
+impl ClassFile {
+    pub fn read(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader::new(bytes);
+        if reader.bytes(4)? != CLASS_MAGIC {
+            return Err("not a class file".to_string())
+        }
+
+        let major = reader.u16()?;
+        let minor = reader.u16()?;
+        if major != CLASS_VERSION_MAJOR {
+            return Err(format!("unsupported class version {}.{}", major, minor))
+        }
+        let class_type = reader.u8()?;
+        let access = reader.u8()?;
+
+        let num_consts = reader.u16()?;
+        let consts = (0..num_consts).map(|_| read_const(&mut reader)).collect::<Result<Vec<_>, _>>()?;
+        let num_type_params = reader.u8()?;
+        let type_params = (0..num_type_params).map(|_| reader.u16()).collect::<Result<Vec<_>, _>>()?;
+
+        let code_size = reader.u32()? as usize;
+        let num_fields = reader.u16()?;
+        let fields = (0..num_fields).map(|_| read_field(class_type, &mut reader)).collect::<Result<Vec<_>, _>>()?;
+        let num_methods = reader.u16()?;
+        let methods = (0..num_methods).map(|_| read_method(class_type, &mut reader)).collect::<Result<Vec<_>, _>>()?;
+        let code = reader.bytes(code_size)?.to_vec();
+
+        let mut sections = Vec::new();
+        for _ in 0..reader.u16()? {
+            let name = reader.u16()?;
+            let size = reader.u32()? as usize;
+            sections.push((name, reader.bytes(size)?.to_vec()));
+        }
+
+        Ok(ClassFile { major, minor, class_type, access, consts, type_params, fields, methods, code, sections })
+    }
+
+    pub fn str(&self, index: u16) -> Option<&str> {
+        match self.consts.get(index as usize) {
+            Some(Const::Str(string)) => Some(string),
+            _ => None,
+        }
+    }
+
+    pub fn class_type_name(&self) -> &'static str {
+        CLASS_TYPE_NAMES.get(self.class_type as usize).copied().unwrap_or("unknown")
+    }
+
+    pub fn section(&self, name: &str) -> Option<&[u8]> {
+        self.sections.iter().find(|(section, _)| self.str(*section) == Some(name)).map(|(_, data)| &data[..])
+    }
+
+    pub fn debug_info(&self) -> Result<Option<DebugInfo>, String> {
+        let mut reader = match self.section(DEBUG_SECTION) {
+            Some(data) => Reader::new(data),
+            None => return Ok(None),
+        };
+
+        let source_file = reader.u16()?;
+        let mut methods = Vec::new();
+        for _ in 0..reader.u16()? {
+            let name = reader.u16()?;
+            let descriptor = reader.u16()?;
+            let lines = (0..reader.u32()?).map(|_| Ok((reader.u32()?, reader.u32()?))).collect::<Result<_, String>>()?;
+            let locals = (0..reader.u16()?).map(|_| Ok((reader.u16()?, reader.u16()?))).collect::<Result<_, String>>()?;
+            methods.push(MethodDebug { name, descriptor, lines, locals });
+        }
+        Ok(Some(DebugInfo { source_file, methods }))
+    }
+
+    /// The error handlers of each method, by name and descriptor, in the exceptions section
+    pub fn exceptions(&self) -> Result<Vec<(u16, u16, Vec<ErrorHandler>)>, String> {
+        let mut reader = match self.section(EXCEPTIONS_SECTION) {
+            Some(data) => Reader::new(data),
+            None => return Ok(Vec::new()),
+        };
+
+        let mut methods = Vec::new();
+        for _ in 0..reader.u16()? {
+            let name = reader.u16()?;
+            let descriptor = reader.u16()?;
+            let handlers = (0..reader.u16()?).map(|_| Ok(ErrorHandler {
+                start: reader.u32()?,
+                end: reader.u32()?,
+                handler: reader.u32()?,
+                error_class: reader.u16()?,
+                stack_depth: reader.u16()?,
+            })).collect::<Result<_, String>>()?;
+            methods.push((name, descriptor, handlers));
+        }
+        Ok(methods)
+    }
+
+    /// The class consts of the interfaces listed in the impls section
+    pub fn impls(&self) -> Result<Vec<u16>, String> {
+        let mut reader = match self.section(IMPLS_SECTION) {
+            Some(data) => Reader::new(data),
+            None => return Ok(Vec::new()),
+        };
+        (0..reader.u16()?).map(|_| reader.u16()).collect()
+    }
+}
+
+impl ClassFile {
+    /// The class file's bytes, which `read` reads back as this class
+    pub fn write(&self) -> Result<Vec<u8>, String> {
+        let mut writer = Writer(Vec::new());
+        writer.bytes(CLASS_MAGIC);
+        writer.u16(self.major);
+        writer.u16(self.minor);
+        writer.u8(self.class_type);
+        writer.u8(self.access);
+
+        writer.u16(count(self.consts.len(), "consts")?);
+        for constant in &self.consts {
+            write_const(&mut writer, constant);
+        }
+        writer.u8(u8::try_from(self.type_params.len()).map_err(|_| "too many type parameters")?);
+        for param in &self.type_params {
+            writer.u16(*param);
+        }
+
+        writer.u32(u32::try_from(self.code.len()).map_err(|_| "code is larger than 4gb")?);
+        writer.u16(count(self.fields.len(), "fields")?);
+        for field in &self.fields {
+            writer.u16(field.name);
+            writer.u8(field.access);
+            match &field.kind {
+                FieldKind::Module => {},
+                FieldKind::Struct(field_type) => writer.u16(*field_type),
+                FieldKind::Enum(fields) => {
+                    writer.u16(count(fields.len(), "enum fields")?);
+                    for (name, field_type) in fields {
+                        writer.u16(*name);
+                        writer.u16(*field_type);
+                    }
+                },
+            }
+        }
+
+        writer.u16(count(self.methods.len(), "methods")?);
+        for method in &self.methods {
+            writer.u16(method.name);
+            writer.u16(method.descriptor);
+            writer.u8(method.access);
+            if let MethodBody::Code { max_locals, max_stack, code_pos } = method.body {
+                writer.u16(max_locals);
+                writer.u16(max_stack);
+                writer.u8(TYPE_SIZE_U32 << 5);
+                writer.u32(u32::try_from(code_pos).map_err(|_| "code is larger than 4gb")?);
+            }
+        }
+        writer.bytes(&self.code);
+
+        writer.u16(count(self.sections.len(), "sections")?);
+        for (name, data) in &self.sections {
+            writer.u16(*name);
+            writer.u32(u32::try_from(data.len()).map_err(|_| "section is larger than 4gb")?);
+            writer.bytes(data);
+        }
+        Ok(writer.0)
+    }
+}
+
+/// Little endian writer of the data `Reader` reads
+pub struct Writer(pub Vec<u8>);
+
+impl Writer {
+    pub fn bytes(&mut self, bytes: &[u8]) {
+        self.0.extend_from_slice(bytes);
+    }
+
+    pub fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+
+    pub fn u16(&mut self, value: u16) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    pub fn u64(&mut self, value: u64) {
+        self.bytes(&value.to_le_bytes());
+    }
+}
+
+/// `len` as a u16 count of `what`
+pub fn count(len: usize, what: &str) -> Result<u16, String> {
+    u16::try_from(len).map_err(|_| format!("too many {}", what))
+}
+
+fn write_const(writer: &mut Writer, constant: &Const) {
+    match constant {
+        Const::Int(value) => {
+            writer.u8(TYPE_SIZE_I64 << 5);
+            writer.u64(*value as u64);
+        },
+        Const::UInt(value) => {
+            writer.u8(TYPE_SIZE_U64 << 5);
+            writer.u64(*value);
+        },
+        Const::Float(value) => {
+            writer.u8(TYPE_SIZE_F64 << 5);
+            writer.u64(value.to_bits());
+        },
+        Const::Str(string) => {
+            let len = string.len();
+            if let Ok(len) = u8::try_from(len) {
+                writer.u8(CONST_KIND_STR);
+                writer.u8(len);
+            } else if let Ok(len) = u16::try_from(len) {
+                writer.u8(TYPE_SIZE_U16 << 5 | CONST_KIND_STR);
+                writer.u16(len);
+            } else {
+                writer.u8(TYPE_SIZE_U64 << 5 | CONST_KIND_STR);
+                writer.u64(len as u64);
+            }
+            writer.bytes(string.as_bytes());
+        },
+        Const::Class(name) => {
+            writer.u8(CONST_KIND_CLASS);
+            writer.u16(*name);
+        },
+        Const::Member(class, name, descriptor) => {
+            writer.u8(CONST_KIND_MEMBER);
+            writer.u16(*class);
+            writer.u16(*name);
+            writer.u16(*descriptor);
+        },
+    }
+}
+
+fn read_const(reader: &mut Reader) -> Result<Const, String> {
+    let type_byte = reader.u8()?;
+    match type_byte & 0b111 {
+        CONST_KIND_NUM => reader.number(type_byte),
+        CONST_KIND_STR => {
+            let size = match reader.number(type_byte)? {
+                Const::UInt(size) => size as usize,
+                _ => return Err("bad string size type".to_string()),
+            };
+            String::from_utf8(reader.bytes(size)?.to_vec())
+                .map(Const::Str)
+                .map_err(|_| "string const is not utf8".to_string())
+        },
+        CONST_KIND_CLASS => Ok(Const::Class(reader.u16()?)),
+        CONST_KIND_MEMBER => Ok(Const::Member(reader.u16()?, reader.u16()?, reader.u16()?)),
+        kind => Err(format!("unknown const kind {}", kind)),
+    }
+}
+
+fn read_field(class_type: u8, reader: &mut Reader) -> Result<Field, String> {
+    let name = reader.u16()?;
+    let access = reader.u8()?;
+    let kind = match class_type {
+        CLASS_TYPE_MODULE => FieldKind::Module,
+        CLASS_TYPE_STRUCT => FieldKind::Struct(reader.u16()?),
+        CLASS_TYPE_ENUM => FieldKind::Enum((0..reader.u16()?).map(|_| Ok((reader.u16()?, reader.u16()?))).collect::<Result<_, String>>()?),
+        _ => return Err(format!("unknown class type {}", class_type)),
+    };
+    Ok(Field { name, access, kind })
+}
+
+fn read_method(class_type: u8, reader: &mut Reader) -> Result<Method, String> {
+    let name = reader.u16()?;
+    let descriptor = reader.u16()?;
+    let access = reader.u8()?;
+    let body = if class_type == CLASS_TYPE_INTERFACE {
+        MethodBody::Abstract
+    } else if access & ACCESS_NATIVE != 0 {
+        MethodBody::Native
+    } else {
+        let max_locals = reader.u16()?;
+        let max_stack = reader.u16()?;
+        let type_byte = reader.u8()?;
+        match reader.number(type_byte)? {
+            Const::UInt(code_pos) => MethodBody::Code { max_locals, max_stack, code_pos },
+            _ => return Err("bad method code position type".to_string()),
+        }
+    };
+    Ok(Method { name, descriptor, access, body })
+}
@@ -0,0 +1,254 @@
+//! `glras disasm`: print a class file's consts, members and bytecode, annotated with
+//! source lines and local names when the class carries a debug section
+
+use super::class::{ClassFile, Const, FieldKind, MethodBody, MethodDebug, Reader, ACCESS_NAMES, CATCH_ALL, NO_DESCRIPTOR};
+use std::fs;
+
+const USAGE: &str = "usage: glras disasm <class.glrc>";
+
+/// Element types of `newarray`: the VM's number types, then references
+pub const ELEMENT_TYPES: [&str; 9] = ["u8", "u16", "u32", "u64", "i32", "i64", "f32", "f64", "ref"];
+
+/// Mnemonic and operand bytes of each opcode, indexed by opcode
+pub const OPCODES: [(&str, usize); 41] = [
+    ("halt", 0),
+    ("push", 8),
+    ("pop", 0),
+    ("dup", 0),
+    ("load", 2),
+    ("store", 2),
+    ("add", 0),
+    ("sub", 0),
+    ("mul", 0),
+    ("div", 0),
+    ("lt", 0),
+    ("eq", 0),
+    ("jmp", 4),
+    ("jz", 4),
+    ("call", 5),
+    ("enter", 2),
+    ("ret", 0),
+    ("str", 2),
+    ("native", 2),
+    ("nop", 0),
+    ("invoke", 3),
+    ("getstatic", 2),
+    ("putstatic", 2),
+    ("throw", 2),
+    ("rethrow", 0),
+    ("new", 2),
+    ("invokeinterface", 3),
+    ("closure", 3),
+    ("callclosure", 1),
+    ("capture", 2),
+    ("newarray", 1),
+    ("arraylen", 0),
+    ("arrayget", 0),
+    ("arrayset", 0),
+    ("arraypush", 0),
+    ("arrayconcat", 0),
+    ("arrayslice", 0),
+    ("strlen", 0),
+    ("strget", 0),
+    ("strconcat", 0),
+    ("strslice", 0),
+];
+
+pub fn disasm(args: &[String]) -> Result<(), String> {
+    let path = match args {
+        [path] => path,
+        _ => return Err(USAGE.to_string()),
+    };
+
+    let bytes = fs::read(path).map_err(|error| format!("{}: {}", path, error))?;
+    let class = ClassFile::read(&bytes).map_err(|error| format!("{}: {}", path, error))?;
+    let debug = class.debug_info().map_err(|error| format!("{}: bad debug section: {}", path, error))?;
+    let exceptions = class.exceptions().map_err(|error| format!("{}: bad exceptions section: {}", path, error))?;
+    let impls = class.impls().map_err(|error| format!("{}: bad impls section: {}", path, error))?;
+
+    let type_params = match class.type_params.is_empty() {
+        true => String::new(),
+        false => format!("<{}>", class.type_params.iter().map(|param| name(&class, *param)).collect::<Vec<_>>().join(", ")),
+    };
+    println!("{}{} {}{} (version {}.{})", modifiers(class.access), class.class_type_name(), name(&class, 0), type_params, class.major, class.minor);
+    if let Some(debug) = &debug {
+        println!("source {}", name(&class, debug.source_file));
+    }
+    for interface in &impls {
+        println!("implements {}", describe_class(&class, *interface));
+    }
+
+    println!("\nconsts:");
+    for (index, constant) in class.consts.iter().enumerate() {
+        println!("  #{:<4} {}", index, describe_const(&class, constant));
+    }
+
+    if !class.fields.is_empty() {
+        println!("\nfields:");
+    }
+    for field in &class.fields {
+        let access = modifiers(field.access);
+        match &field.kind {
+            FieldKind::Module => println!("  {}{}", access, name(&class, field.name)),
+            FieldKind::Struct(field_type) => println!("  {}{}: {}", access, name(&class, field.name), name(&class, *field_type)),
+            FieldKind::Enum(fields) => {
+                let fields: Vec<_> = fields.iter()
+                    .map(|(field, field_type)| format!("{}: {}", name(&class, *field), name(&class, *field_type)))
+                    .collect();
+                println!("  {}{}({})", access, name(&class, field.name), fields.join(", "));
+            },
+        }
+    }
+
+    let mut methods: Vec<_> = class.methods.iter().filter_map(|method| match method.body {
+        MethodBody::Code { code_pos, .. } => Some((code_pos as usize, method.name, method.descriptor)),
+        MethodBody::Native | MethodBody::Abstract => None,
+    }).collect();
+    methods.sort();
+
+    if !class.methods.is_empty() {
+        println!("\nmethods:");
+    }
+    for method in &class.methods {
+        let signature = format!("{}{}{}", modifiers(method.access), name(&class, method.name), name(&class, method.descriptor));
+        match &method.body {
+            MethodBody::Code { max_locals, max_stack, code_pos } =>
+                println!("  {} @{:04} (locals {}, stack {})", signature, code_pos, max_locals, max_stack),
+            MethodBody::Native | MethodBody::Abstract => println!("  {}", signature),
+        }
+
+        let handlers = exceptions.iter()
+            .filter(|(name, descriptor, _)| (*name, *descriptor) == (method.name, method.descriptor))
+            .flat_map(|(_, _, handlers)| handlers);
+        for handler in handlers {
+            let error_class = match handler.error_class {
+                CATCH_ALL => "any".to_string(),
+                index => describe_class(&class, index),
+            };
+            println!("    catch {} in {:04}..{:04} at {:04}, keeping {} slots",
+                error_class, handler.start, handler.end, handler.handler, handler.stack_depth);
+        }
+    }
+
+    println!("\ncode:");
+    let mut pos = 0;
+    let mut method_debug = None;
+    while pos < class.code.len() {
+        for (_, method, descriptor) in methods.iter().filter(|(code_pos, _, _)| *code_pos == pos) {
+            println!("{}{}:", name(&class, *method), name(&class, *descriptor));
+            method_debug = debug.as_ref().and_then(|debug| debug.methods.iter()
+                .find(|debug| (debug.name, debug.descriptor) == (*method, *descriptor)));
+        }
+
+        let (mnemonic, size) = OPCODES.get(class.code[pos] as usize)
+            .ok_or_else(|| format!("{}: bad opcode {} at {:04}", path, class.code[pos], pos))?;
+        let operands = class.code.get(pos + 1..pos + 1 + size)
+            .ok_or_else(|| format!("{}: truncated {} at {:04}", path, mnemonic, pos))?;
+
+        let instruction = format!("{} {}", mnemonic, describe_operands(&class, class.code[pos], operands, method_debug)?);
+        match method_debug.and_then(|debug| debug.lines.iter().find(|(code_pos, _)| *code_pos as usize == pos)) {
+            Some((_, line)) => println!("  {:04}  {:<40} ; line {}", pos, instruction.trim_end(), line),
+            None => println!("  {:04}  {}", pos, instruction.trim_end()),
+        }
+        pos += 1 + size;
+    }
+
+    Ok(())
+}
+
+fn describe_operands(class: &ClassFile, opcode: u8, operands: &[u8], debug: Option<&MethodDebug>) -> Result<String, String> {
+    let mut reader = Reader::new(operands);
+    Ok(match OPCODES[opcode as usize].0 {
+        "push" => format!("{}", reader.u64()? as i64),
+        "load" | "store" => {
+            let slot = reader.u16()?;
+            match debug.and_then(|debug| debug.locals.iter().find(|(local, _)| *local == slot)) {
+                Some((_, local)) => format!("{} ({})", slot, name(class, *local)),
+                None => format!("{}", slot),
+            }
+        },
+        "enter" | "capture" => format!("{}", reader.u16()?),
+        "callclosure" => format!("{}", reader.u8()?),
+        "newarray" => {
+            let elements = reader.u8()?;
+            ELEMENT_TYPES.get(elements as usize).map_or_else(|| format!("{} <bad element type>", elements), |name| name.to_string())
+        },
+        "jmp" | "jz" => format!("{:04}", reader.u32()?),
+        "call" => format!("{:04} {}", reader.u32()?, reader.u8()?),
+        "str" | "native" => {
+            let index = reader.u16()?;
+            match class.consts.get(index as usize) {
+                Some(constant) => format!("#{} {}", index, describe_const(class, constant)),
+                None => format!("#{} <bad const>", index),
+            }
+        },
+        "throw" | "new" => {
+            let index = reader.u16()?;
+            format!("#{} {}", index, describe_class(class, index))
+        },
+        "getstatic" | "putstatic" => {
+            let index = reader.u16()?;
+            format!("#{} {}", index, describe_member(class, index))
+        },
+        "invoke" | "invokeinterface" | "closure" => {
+            let index = reader.u16()?;
+            format!("#{} {} {}", index, describe_member(class, index), reader.u8()?)
+        },
+        _ => String::new(),
+    })
+}
+
+fn describe_const(class: &ClassFile, constant: &Const) -> String {
+    match constant {
+        Const::Int(value) => format!("int {}", value),
+        Const::UInt(value) => format!("uint {}", value),
+        Const::Float(value) => format!("float {}", value),
+        Const::Str(string) => format!("str {:?}", string),
+        Const::Class(class_name) => format!("class {}", name(class, *class_name)),
+        Const::Member(owner, member, descriptor) => {
+            let descriptor = match *descriptor {
+                NO_DESCRIPTOR => String::new(),
+                descriptor => name(class, descriptor),
+            };
+            match class.consts.get(*owner as usize) {
+                Some(Const::Class(owner)) => format!("member {}.{}{}", name(class, *owner), name(class, *member), descriptor),
+                _ => format!("member #{}.{}{}", owner, name(class, *member), descriptor),
+            }
+        },
+    }
+}
+
+fn describe_member(class: &ClassFile, index: u16) -> String {
+    match class.consts.get(index as usize) {
+        Some(constant @ Const::Member(..)) => describe_const(class, constant),
+        _ => "<not a member>".to_string(),
+    }
+}
+
+fn describe_class(class: &ClassFile, index: u16) -> String {
+    match class.consts.get(index as usize) {
+        Some(Const::Class(class_name)) => name(class, *class_name),
+        _ => "<not a class>".to_string(),
+    }
+}
+
+/// The names of the bits set in `access`, each followed by a space, with unknown bits in hex
+fn modifiers(access: u8) -> String {
+    let mut modifiers = String::new();
+    for (_, modifier) in ACCESS_NAMES.iter().enumerate().filter(|(bit, _)| access & 1 << bit != 0) {
+        modifiers += modifier;
+        modifiers += " ";
+    }
+    let unknown = access >> ACCESS_NAMES.len();
+    if unknown != 0 {
+        modifiers += &format!("{:#04x} ", unknown << ACCESS_NAMES.len());
+    }
+    modifiers
+}
+
+fn name(class: &ClassFile, index: u16) -> String {
+    match class.str(index) {
+        Some(string) => string.to_string(),
+        None => format!("<#{}>", index),
+    }
+}
@@ -0,0 +1,198 @@
+//! LZ77 block compression in the layout the VM's archive loader decompresses:
+//! a run of sequences, each a token byte holding the literal count in its upper
+//! nibble and the match length (minus `MIN_MATCH`) in its lower one, the literals,
+//! then a little endian u16 offset back into the output. A nibble of 15 continues
+//! in extra bytes after the token (literal count) or offset (match length), added
+//! on until one is below 255. The last sequence stops after its literals.
+
+const MIN_MATCH: usize = 4;
+const MAX_OFFSET: usize = 0xffff;
+const HASH_BITS: u32 = 12;
+
+pub fn compress(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut table = vec![usize::MAX; 1 << HASH_BITS];
+    let mut literals = 0;
+    let mut pos = 0;
+
+    while pos + MIN_MATCH <= input.len() {
+        let slot = &mut table[hash(&input[pos..])];
+        let candidate = *slot;
+        *slot = pos;
+
+        let matched = candidate != usize::MAX
+            && pos - candidate <= MAX_OFFSET
+            && input[candidate..candidate + MIN_MATCH] == input[pos..pos + MIN_MATCH];
+        if !matched {
+            pos += 1;
+            continue
+        }
+
+        let length = input[pos..].iter()
+            .zip(&input[candidate..])
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        write_sequence(&mut output, &input[literals..pos], Some((pos - candidate, length)));
+        pos += length;
+        literals = pos;
+    }
+
+    write_sequence(&mut output, &input[literals..], None);
+    output
+}
+
+fn write_sequence(output: &mut Vec<u8>, literals: &[u8], matched: Option<(usize, usize)>) {
+    let match_length = matched.map_or(0, |(_, length)| length - MIN_MATCH);
+    output.push((literals.len().min(15) << 4 | match_length.min(15)) as u8);
+    write_length(output, literals.len());
+    output.extend_from_slice(literals);
+
+    if let Some((offset, _)) = matched {
+        output.extend_from_slice(&(offset as u16).to_le_bytes());
+        write_length(output, match_length);
+    }
+}
+
+fn write_length(output: &mut Vec<u8>, length: usize) {
+    if length >= 15 {
+        let mut rest = length - 15;
+        while rest >= 255 {
+            output.push(255);
+            rest -= 255;
+        }
+        output.push(rest as u8);
+    }
+}
+
+fn hash(bytes: &[u8]) -> usize {
+    let word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    (word.wrapping_mul(2_654_435_761) >> (32 - HASH_BITS)) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{compress, MIN_MATCH, MAX_OFFSET};
+
+    /// The VM's decompressor (glr/src/bytecode/archive.rs), which archives are written for
+    fn decompress(input: &[u8], size: usize) -> Option<Vec<u8>> {
+        let mut output = vec![0; size];
+        let (mut src, mut dst) = (0, 0);
+        loop {
+            let token = *input.get(src)?;
+            src += 1;
+
+            let literals = read_length(input, &mut src, token as usize >> 4)?;
+            output.get_mut(dst..dst + literals)?.copy_from_slice(input.get(src..src + literals)?);
+            src += literals;
+            dst += literals;
+
+            if src == input.len() {
+                return if dst == output.len() { Some(output) } else { None }
+            }
+
+            let offset = *input.get(src)? as usize | (*input.get(src + 1)? as usize) << 8;
+            src += 2;
+            let length = read_length(input, &mut src, token as usize & 15)? + MIN_MATCH;
+            if offset == 0 || offset > dst || dst + length > output.len() {
+                return None
+            }
+
+            for pos in dst..dst + length {
+                output[pos] = output[pos - offset];
+            }
+            dst += length;
+        }
+    }
+
+    fn read_length(input: &[u8], pos: &mut usize, nibble: usize) -> Option<usize> {
+        let mut length = nibble;
+        if nibble == 15 {
+            loop {
+                let byte = *input.get(*pos)?;
+                *pos += 1;
+                length += byte as usize;
+                if byte != 255 {
+                    break
+                }
+            }
+        }
+        Some(length)
+    }
+
+    fn round_trip(input: &[u8]) -> Vec<u8> {
+        let compressed = compress(input);
+        assert_eq!(decompress(&compressed, input.len()), Some(input.to_vec()));
+        compressed
+    }
+
+    /// Bytes from a fixed seed, which have no matches to speak of
+    fn noise(len: usize) -> Vec<u8> {
+        let mut state = 0x2545_f491_4f6c_dd1du64;
+        (0..len).map(|_| {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            (state >> 56) as u8
+        }).collect()
+    }
+
+    #[test]
+    fn empty() {
+        assert_eq!(round_trip(&[]), [0]);
+    }
+
+    #[test]
+    fn shorter_than_a_match() {
+        assert_eq!(round_trip(b"abc"), b"\x30abc");
+    }
+
+    #[test]
+    fn incompressible() {
+        // literal counts either side of where their extra bytes start and roll over
+        for &len in &[14, 15, 16, 269, 270, 271, 524, 525, 100_000] {
+            let input = noise(len);
+            let compressed = round_trip(&input);
+            assert!(compressed.len() <= len + len / 255 + 2, "{} bytes grew to {}", len, compressed.len());
+        }
+    }
+
+    #[test]
+    fn long_runs() {
+        for &len in &[MIN_MATCH + 1, 19, 20, 274, 275, 100_000, 1 << 20] {
+            let compressed = round_trip(&vec![b'a'; len]);
+            assert!(compressed.len() < 16 + len / 255, "{} bytes compressed to {}", len, compressed.len());
+        }
+
+        let mut input = noise(1000);
+        input.extend(vec![0; 70_000]);
+        input.extend(noise(1000));
+        round_trip(&input);
+    }
+
+    #[test]
+    fn match_ending_the_input() {
+        // the last sequence holds no literals when a match runs to the end
+        assert_eq!(round_trip(b"abcdabcd"), b"\x40abcd\x04\x00\x00");
+        assert_eq!(round_trip(b"abcdeabcde"), b"\x51abcde\x05\x00\x00");
+
+        let mut input = noise(300);
+        let repeat = input[100..].to_vec();
+        input.extend(repeat);
+        let compressed = round_trip(&input);
+        assert_eq!(compressed.last(), Some(&0));
+    }
+
+    #[test]
+    fn far_offsets() {
+        // a block repeated as far back as an offset reaches is matched, and one byte further isn't
+        let sizes: Vec<usize> = [MAX_OFFSET, MAX_OFFSET + 1].iter().map(|&offset| {
+            let block = noise(64);
+            let mut input = block.clone();
+            input.resize(offset, 0);
+            input.extend_from_slice(&block);
+            round_trip(&input).len()
+        }).collect();
+        assert!(sizes[0] + 32 < sizes[1], "compressed to {:?}", sizes);
+    }
+}
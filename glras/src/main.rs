@@ -1,5 +1,25 @@
+mod archive;
+mod asm;
+mod class;
+mod disasm;
+mod lz;
 
+use std::env;
+use std::process;
+
+const USAGE: &str = "usage: glras pack -o <archive.glra> [-e <entry class>] [-z] <class.glrc>...\n       glras asm [-o <class.glrc>] <listing.glrs>\n       glras disasm <class.glrc>";
 
 fn main() {
-    
-}
\ No newline at end of file
+    let args: Vec<String> = env::args().skip(1).collect();
+    let result = match args.first().map(String::as_str) {
+        Some("pack") => archive::pack(&args[1..]),
+        Some("asm") => asm::asm(&args[1..]),
+        Some("disasm") => disasm::disasm(&args[1..]),
+        _ => Err(USAGE.to_string()),
+    };
+
+    if let Err(error) = result {
+        eprintln!("glras: {}", error);
+        process::exit(1);
+    }
+}